@@ -0,0 +1,335 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::ir::{Instruction, Operand};
+
+/// A maximal straight-line run of instructions, `start..end` in program order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub start: usize,
+    pub end: usize,
+    pub successors: Vec<usize>,
+    pub predecessors: Vec<usize>,
+}
+
+impl BasicBlock {
+    pub fn range(&self) -> std::ops::Range<usize> {
+        self.start..self.end
+    }
+}
+
+/// Control-flow graph over a parsed program. Block 0 is the entry block.
+#[derive(Debug, Clone)]
+pub struct Cfg {
+    pub blocks: Vec<BasicBlock>,
+}
+
+impl Cfg {
+    pub fn build(instructions: &[Instruction]) -> Self {
+        if instructions.is_empty() {
+            return Self { blocks: Vec::new() };
+        }
+
+        // Leaders: the first instruction, every labeled instruction, and every
+        // instruction following a branch
+        let mut leaders = vec![0];
+        for (idx, inst) in instructions.iter().enumerate() {
+            if idx > 0 && inst.label.is_some() {
+                leaders.push(idx);
+            }
+            if crate::ir::is_branch(&inst.opcode) && idx + 1 < instructions.len() {
+                leaders.push(idx + 1);
+            }
+        }
+        leaders.sort_unstable();
+        leaders.dedup();
+
+        let mut blocks: Vec<BasicBlock> = leaders
+            .iter()
+            .enumerate()
+            .map(|(i, &start)| BasicBlock {
+                start,
+                end: leaders.get(i + 1).copied().unwrap_or(instructions.len()),
+                successors: Vec::new(),
+                predecessors: Vec::new(),
+            })
+            .collect();
+
+        let block_of_label: HashMap<&str, usize> = blocks
+            .iter()
+            .enumerate()
+            .filter_map(|(b, block)| {
+                instructions[block.start]
+                    .label
+                    .as_deref()
+                    .map(|label| (label, b))
+            })
+            .collect();
+
+        for b in 0..blocks.len() {
+            let last = &instructions[blocks[b].end - 1];
+            let mut successors = Vec::new();
+            match last.opcode.as_str() {
+                "jumpI" | "cbr" => {
+                    for target in &last.targets {
+                        if let Operand::Label(label) = target {
+                            if let Some(&succ) = block_of_label.get(label.as_str()) {
+                                successors.push(succ);
+                            }
+                        }
+                    }
+                }
+                "jump" => {
+                    // Register-indirect jump: any labeled block may be the target
+                    successors.extend(block_of_label.values().copied());
+                    successors.sort_unstable();
+                }
+                _ => {
+                    if b + 1 < blocks.len() {
+                        successors.push(b + 1);
+                    }
+                }
+            }
+            successors.dedup();
+            blocks[b].successors = successors;
+        }
+
+        for b in 0..blocks.len() {
+            for succ in blocks[b].successors.clone() {
+                blocks[succ].predecessors.push(b);
+            }
+        }
+
+        Self { blocks }
+    }
+
+    /// Index of the block containing instruction `idx`.
+    pub fn block_of(&self, idx: usize) -> Option<usize> {
+        self.blocks
+            .iter()
+            .position(|block| block.range().contains(&idx))
+    }
+}
+
+/// Registers live on entry to and exit from every block of `cfg`.
+#[derive(Debug, Clone)]
+pub struct Liveness {
+    pub live_in: Vec<HashSet<String>>,
+    pub live_out: Vec<HashSet<String>>,
+}
+
+impl Liveness {
+    pub fn compute(instructions: &[Instruction], cfg: &Cfg) -> Self {
+        Self::compute_with_exit(instructions, cfg, &HashSet::new())
+    }
+
+    /// Like `compute`, but treats `live_at_exit` as read once the program
+    /// falls off the end of a block without successors.
+    pub fn compute_with_exit(
+        instructions: &[Instruction],
+        cfg: &Cfg,
+        live_at_exit: &HashSet<String>,
+    ) -> Self {
+        let count = cfg.blocks.len();
+        let mut upward_exposed = vec![HashSet::new(); count];
+        let mut killed = vec![HashSet::new(); count];
+
+        for (b, block) in cfg.blocks.iter().enumerate() {
+            for inst in &instructions[block.range()] {
+                for reg in inst.uses() {
+                    if !killed[b].contains(reg) {
+                        upward_exposed[b].insert(reg.to_string());
+                    }
+                }
+                for reg in inst.defs() {
+                    killed[b].insert(reg.to_string());
+                }
+            }
+        }
+
+        let mut live_in: Vec<HashSet<String>> = vec![HashSet::new(); count];
+        let mut live_out: Vec<HashSet<String>> = vec![HashSet::new(); count];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for b in (0..count).rev() {
+                let mut out = if cfg.blocks[b].successors.is_empty() {
+                    live_at_exit.clone()
+                } else {
+                    HashSet::new()
+                };
+                for &succ in &cfg.blocks[b].successors {
+                    out.extend(live_in[succ].iter().cloned());
+                }
+
+                let mut inn = upward_exposed[b].clone();
+                inn.extend(out.difference(&killed[b]).cloned());
+
+                if inn != live_in[b] || out != live_out[b] {
+                    live_in[b] = inn;
+                    live_out[b] = out;
+                    changed = true;
+                }
+            }
+        }
+
+        Self { live_in, live_out }
+    }
+
+    /// Registers live immediately after each instruction of block `b`.
+    pub fn live_after(
+        &self,
+        instructions: &[Instruction],
+        cfg: &Cfg,
+        b: usize,
+    ) -> Vec<HashSet<String>> {
+        let block = &cfg.blocks[b];
+        let mut live = self.live_out[b].clone();
+        let mut result = vec![HashSet::new(); block.end - block.start];
+        for idx in block.range().rev() {
+            result[idx - block.start] = live.clone();
+            let inst = &instructions[idx];
+            for reg in inst.defs() {
+                live.remove(reg);
+            }
+            for reg in inst.uses() {
+                live.insert(reg.to_string());
+            }
+        }
+        result
+    }
+}
+
+/// Dominator tree and dominance frontiers of a `Cfg`, rooted at block 0.
+/// Blocks unreachable from the entry have no immediate dominator and
+/// dominate nothing.
+#[derive(Debug, Clone)]
+pub struct Dominators {
+    pub idom: Vec<Option<usize>>,
+    pub children: Vec<Vec<usize>>,
+    pub frontiers: Vec<BTreeSet<usize>>,
+}
+
+impl Dominators {
+    /// The iterative algorithm of Cooper, Harvey and Kennedy over reverse
+    /// postorder.
+    pub fn compute(cfg: &Cfg) -> Self {
+        let count = cfg.blocks.len();
+        let mut idom: Vec<Option<usize>> = vec![None; count];
+        let mut children = vec![Vec::new(); count];
+        let mut frontiers = vec![BTreeSet::new(); count];
+        if count == 0 {
+            return Self {
+                idom,
+                children,
+                frontiers,
+            };
+        }
+
+        let order = reverse_postorder(cfg);
+        let mut position = vec![usize::MAX; count];
+        for (i, &b) in order.iter().enumerate() {
+            position[b] = i;
+        }
+
+        let intersect = |idom: &[Option<usize>], mut a: usize, mut b: usize| {
+            while a != b {
+                while position[a] > position[b] {
+                    a = idom[a].unwrap();
+                }
+                while position[b] > position[a] {
+                    b = idom[b].unwrap();
+                }
+            }
+            a
+        };
+
+        idom[0] = Some(0);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &b in order.iter().skip(1) {
+                let mut new_idom = None;
+                for &pred in &cfg.blocks[b].predecessors {
+                    if idom[pred].is_none() {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => pred,
+                        Some(current) => intersect(&idom, pred, current),
+                    });
+                }
+                if new_idom.is_some() && idom[b] != new_idom {
+                    idom[b] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+
+        for (b, parent) in idom.iter().enumerate().skip(1) {
+            if let Some(parent) = *parent {
+                children[parent].push(b);
+            }
+        }
+
+        // A join point is in the frontier of every block on the paths from
+        // its predecessors up to (but excluding) its immediate dominator. The
+        // entry has an implicit edge from outside, so one predecessor
+        // already makes it a join point.
+        for b in 0..count {
+            let Some(dominator) = idom[b] else { continue };
+            if cfg.blocks[b].predecessors.len() < if b == 0 { 1 } else { 2 } {
+                continue;
+            }
+            for &pred in &cfg.blocks[b].predecessors {
+                let mut runner = pred;
+                while idom[runner].is_some() && (b == 0 || runner != dominator) {
+                    frontiers[runner].insert(b);
+                    if runner == 0 {
+                        break;
+                    }
+                    runner = idom[runner].unwrap();
+                }
+            }
+        }
+
+        Self {
+            idom,
+            children,
+            frontiers,
+        }
+    }
+
+    /// Whether every path from the entry to `b` passes through `a`.
+    pub fn dominates(&self, a: usize, mut b: usize) -> bool {
+        loop {
+            if a == b {
+                return self.idom[b].is_some();
+            }
+            match self.idom[b] {
+                Some(parent) if parent != b => b = parent,
+                _ => return false,
+            }
+        }
+    }
+}
+
+fn reverse_postorder(cfg: &Cfg) -> Vec<usize> {
+    let mut visited = vec![false; cfg.blocks.len()];
+    let mut order = Vec::new();
+    let mut stack = vec![(0, 0)];
+    visited[0] = true;
+    while let Some((b, next)) = stack.pop() {
+        match cfg.blocks[b].successors.get(next) {
+            Some(&succ) => {
+                stack.push((b, next + 1));
+                if !visited[succ] {
+                    visited[succ] = true;
+                    stack.push((succ, 0));
+                }
+            }
+            None => order.push(b),
+        }
+    }
+    order.reverse();
+    order
+}
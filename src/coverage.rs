@@ -0,0 +1,199 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::parser::SourceLine;
+use crate::vm::VM;
+
+/// How often a `cbr` went each way.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BranchCoverage {
+    /// Whether the branch ran at all; lcov reports `-` for branches that
+    /// did not.
+    pub executed: bool,
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+/// Line and branch coverage of one source file, keyed by 1-based source
+/// line. Runs of the same file can be merged to cover a whole test suite.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Coverage {
+    /// Hits of every source line that holds an instruction.
+    pub lines: BTreeMap<usize, u64>,
+    /// Every source line that holds a `cbr`.
+    pub branches: BTreeMap<usize, BranchCoverage>,
+}
+
+impl Coverage {
+    /// Coverage of the run in `vm`, whose program line `i` came from source
+    /// line `source_lines[i]`, as given by `parser::parse_with_lines`.
+    pub fn from_vm(vm: &VM, source_lines: &[usize]) -> Self {
+        Self::collect(vm, |idx| source_lines.get(idx).copied())
+    }
+
+    /// Coverage of the run in `vm` for every file its program was parsed
+    /// from, such as included libraries, keyed by file name.
+    pub fn by_file(vm: &VM, code: &[SourceLine]) -> BTreeMap<String, Self> {
+        let files: BTreeSet<&str> = code.iter().map(|line| line.file.as_str()).collect();
+        files
+            .into_iter()
+            .map(|file| {
+                let coverage = Self::collect(vm, |idx| {
+                    code.get(idx)
+                        .filter(|line| line.file == file)
+                        .map(|line| line.line)
+                });
+                (file.to_string(), coverage)
+            })
+            .collect()
+    }
+
+    /// Coverage of the program lines that `source_line` maps to a source line.
+    fn collect(vm: &VM, source_line: impl Fn(usize) -> Option<usize>) -> Self {
+        let mut coverage = Self::default();
+        let counts = vm.get_line_counts();
+        let branch_counts = vm.get_branch_counts();
+
+        for (idx, text) in vm.get_program().iter().enumerate() {
+            let Some(line) = source_line(idx) else {
+                continue;
+            };
+            let count = counts.get(idx).copied().unwrap_or(0);
+            *coverage.lines.entry(line).or_default() += count;
+
+            if has_cbr(text) {
+                let [taken, not_taken] = branch_counts.get(idx).copied().unwrap_or([0; 2]);
+                let branch = coverage.branches.entry(line).or_default();
+                branch.executed |= count > 0;
+                branch.taken += taken;
+                branch.not_taken += not_taken;
+            }
+        }
+        coverage
+    }
+
+    /// Adds the counts of another run of the same source.
+    pub fn merge(&mut self, other: &Coverage) {
+        for (&line, &count) in &other.lines {
+            *self.lines.entry(line).or_default() += count;
+        }
+        for (&line, other) in &other.branches {
+            let branch = self.branches.entry(line).or_default();
+            branch.executed |= other.executed;
+            branch.taken += other.taken;
+            branch.not_taken += other.not_taken;
+        }
+    }
+
+    /// Source lines holding an instruction that never ran.
+    pub fn missed_lines(&self) -> Vec<usize> {
+        self.lines
+            .iter()
+            .filter(|(_, &count)| count == 0)
+            .map(|(&line, _)| line)
+            .collect()
+    }
+
+    /// One lcov record for `source_file`, ending in `end_of_record`.
+    pub fn to_lcov(&self, source_file: &str) -> String {
+        let mut info = format!("TN:\nSF:{}\n", source_file);
+
+        let mut found = 0;
+        let mut hit = 0;
+        for (&line, branch) in &self.branches {
+            for (edge, count) in [branch.taken, branch.not_taken].into_iter().enumerate() {
+                found += 1;
+                hit += usize::from(count > 0);
+                let count = if branch.executed {
+                    count.to_string()
+                } else {
+                    "-".to_string()
+                };
+                info.push_str(&format!("BRDA:{},0,{},{}\n", line, edge, count));
+            }
+        }
+        info.push_str(&format!("BRF:{}\nBRH:{}\n", found, hit));
+
+        for (line, count) in &self.lines {
+            info.push_str(&format!("DA:{},{}\n", line, count));
+        }
+        info.push_str(&format!(
+            "LF:{}\nLH:{}\n",
+            self.lines.len(),
+            self.lines.values().filter(|&&count| count > 0).count()
+        ));
+        info.push_str("end_of_record\n");
+        info
+    }
+
+    /// A section of the HTML report: `source` with every instruction line
+    /// colored by whether it ran, and branch counts next to each `cbr`.
+    pub fn to_html(&self, source_file: &str, source: &str) -> String {
+        let covered = self.lines.values().filter(|&&count| count > 0).count();
+        let mut html = format!(
+            "<h2>{}</h2>\n<p>{} of {} lines covered</p>\n<table>\n",
+            escape_html(source_file),
+            covered,
+            self.lines.len()
+        );
+        for (idx, text) in source.lines().enumerate() {
+            let line = idx + 1;
+            let (class, count) = match self.lines.get(&line) {
+                Some(0) => (" class=\"missed\"", "0".to_string()),
+                Some(count) => (" class=\"hit\"", count.to_string()),
+                None => ("", String::new()),
+            };
+            let branch = match self.branches.get(&line) {
+                Some(branch) => format!("taken {} / not taken {}", branch.taken, branch.not_taken),
+                None => String::new(),
+            };
+            html.push_str(&format!(
+                "<tr{}><td class=\"line\">{}</td><td class=\"count\">{}</td><td><pre>{}</pre></td><td class=\"branch\">{}</td></tr>\n",
+                class,
+                line,
+                count,
+                escape_html(text),
+                branch
+            ));
+        }
+        html.push_str("</table>\n");
+        html
+    }
+}
+
+/// Wraps the sections from `Coverage::to_html` into a complete page.
+pub fn html_page(sections: &[String]) -> String {
+    format!(
+        "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>ILOC coverage</title>
+<style>
+table {{ border-collapse: collapse; font-family: monospace; }}
+td {{ padding: 0 0.5em; vertical-align: top; }}
+pre {{ margin: 0; }}
+.line, .count {{ text-align: right; color: #666; }}
+.hit {{ background: #dfd; }}
+.missed {{ background: #fdd; }}
+.branch {{ color: #666; }}
+</style>
+</head>
+<body>
+{}</body>
+</html>
+",
+        sections.concat()
+    )
+}
+
+fn has_cbr(text: &str) -> bool {
+    text.split(|c: char| c.is_whitespace() || c == '[' || c == ';')
+        .any(|token| token == "cbr")
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
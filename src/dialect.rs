@@ -0,0 +1,237 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::ir;
+use crate::literal;
+use crate::parser;
+
+/// The ILOC variant a program is written in, chosen at parse time.
+///
+/// Every dialect parses to the same normalized form the VM and the other
+/// tools work on: commas between operands, `->` after `jumpI`, `jump` and
+/// `cbr`, and `=>` after everything else.
+///
+/// - `Eac2` is the notation of the second edition of Engineering a
+///   Compiler: commas are required, data operations take `=>` and control
+///   flow `->`, and only the textbook's opcodes are accepted.
+/// - `Eac3` is the third edition's notation, which writes `=>` after every
+///   operation, control flow included.
+/// - `Lenient` accepts either arrow anywhere, operands separated by
+///   whitespace alone, opcodes in any case and the course's extensions
+///   such as `output`. Any other opcode is an error, as in the textbook
+///   dialects.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Dialect {
+    Eac2,
+    Eac3,
+    #[default]
+    Lenient,
+}
+
+/// The opcodes of the textbook's ILOC, as both editions define them.
+const TEXTBOOK_OPCODES: &[&str] = &[
+    "nop", "add", "sub", "mult", "div", "addI", "subI", "rsubI", "multI", "divI", "rdivI",
+    "lshift", "lshiftI", "rshift", "rshiftI", "and", "andI", "or", "orI", "xor", "xorI", "loadI",
+    "load", "loadAI", "loadAO", "cload", "cloadAI", "cloadAO", "store", "storeAI", "storeAO",
+    "cstore", "cstoreAI", "cstoreAO", "i2i", "c2c", "c2i", "i2c", "cmp_LT", "cmp_LE", "cmp_EQ",
+    "cmp_GE", "cmp_GT", "cmp_NE", "cbr", "jumpI", "jump", "tbl", "phi",
+];
+
+/// Opcodes this emulator adds to the textbook's, accepted by `Lenient`.
+const EXTENSION_OPCODES: &[&str] = &[
+    "output", "rshiftL", "rshiftLI", "loadF", "fadd", "fsub", "fmult", "fdiv", "fload", "floadAI",
+    "floadAO", "fstore", "fstoreAI", "fstoreAO", "i2f", "f2i", "f2f", "fcmp_LT", "fcmp_LE",
+    "fcmp_EQ", "fcmp_GE", "fcmp_GT", "fcmp_NE",
+];
+
+impl Dialect {
+    pub const ALL: [Dialect; 3] = [Dialect::Eac2, Dialect::Eac3, Dialect::Lenient];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Dialect::Eac2 => "eac2",
+            Dialect::Eac3 => "eac3",
+            Dialect::Lenient => "lenient",
+        }
+    }
+
+    /// Whether `opcode` is an instruction of this dialect.
+    pub fn accepts(&self, opcode: &str) -> bool {
+        match self {
+            Dialect::Eac2 | Dialect::Eac3 => TEXTBOOK_OPCODES.contains(&opcode),
+            Dialect::Lenient => canonical_opcode(opcode).is_some(),
+        }
+    }
+
+    /// The arrow this dialect writes after `opcode`.
+    pub fn arrow(&self, opcode: &str) -> &'static str {
+        match self {
+            Dialect::Eac3 => "=>",
+            _ if ir::is_branch(opcode) => "->",
+            _ => "=>",
+        }
+    }
+
+    /// Rewrites one operation, without label, written in this dialect and
+    /// with its whitespace already collapsed, into the normalized form.
+    pub(crate) fn normalize(&self, operation: &str) -> Result<String, String> {
+        let (opcode, operands) = match operation.split_once(' ') {
+            Some((opcode, operands)) => (opcode, operands.trim()),
+            None => (operation, ""),
+        };
+        let known = match self {
+            Dialect::Lenient => canonical_opcode(opcode),
+            _ => Some(opcode).filter(|opcode| self.accepts(opcode)),
+        };
+        let Some(opcode) = known else {
+            return Err(format!(
+                "Unknown opcode '{}' in {} ILOC",
+                opcode,
+                self.description()
+            ));
+        };
+        if operands.is_empty() {
+            return Ok(opcode.to_string());
+        }
+
+        let arrow = ["=>", "->"]
+            .into_iter()
+            .find(|arrow| operands.contains(arrow));
+        let (sources, targets) = match arrow {
+            Some(arrow) => {
+                let (sources, targets) = operands.split_once(arrow).unwrap();
+                if *self != Dialect::Lenient && arrow != self.arrow(opcode) {
+                    return Err(format!(
+                        "'{}' takes '{}' in {} ILOC",
+                        opcode,
+                        self.arrow(opcode),
+                        self.description()
+                    ));
+                }
+                (sources, Some(targets))
+            }
+            None => (operands, None),
+        };
+
+        let sources = self.operands(sources)?;
+        Ok(match targets {
+            Some(targets) => {
+                let targets = self.operands(targets)?;
+                match sources.is_empty() {
+                    true => format!("{} {} {}", opcode, canonical_arrow(opcode), targets),
+                    false => format!(
+                        "{} {} {} {}",
+                        opcode,
+                        sources,
+                        canonical_arrow(opcode),
+                        targets
+                    ),
+                }
+            }
+            None => format!("{} {}", opcode, sources),
+        })
+    }
+
+    /// Writes one normalized program line in this dialect.
+    pub fn render(&self, line: &str) -> String {
+        if *self != Dialect::Eac3 {
+            return line.to_string();
+        }
+        if let Some((label, operations)) = ir::split_bundle(line) {
+            let operations: Vec<String> = operations.iter().map(|op| self.render(op)).collect();
+            let bundle = format!("[ {} ]", operations.join(" ; "));
+            return match label {
+                Some(label) => format!("{}: {}", label, bundle),
+                None => bundle,
+            };
+        }
+        line.replace(" -> ", " => ")
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            Dialect::Eac2 => "EaC 2nd edition",
+            Dialect::Eac3 => "EaC 3rd edition",
+            Dialect::Lenient => "lenient",
+        }
+    }
+
+    /// Joins the operands on one side of the arrow with commas. Only
+    /// `Lenient` accepts operands separated by whitespace alone.
+    fn operands(&self, side: &str) -> Result<String, String> {
+        let side = side.trim();
+        let words: Vec<&str> = side.split(' ').filter(|w| !w.is_empty()).collect();
+        if side.contains(',') || words.len() < 2 || !words.iter().all(|w| is_operand(w)) {
+            return Ok(side.replace(", ", ",").replace(" ,", ","));
+        }
+        match self {
+            Dialect::Lenient => Ok(words.join(",")),
+            _ => Err(format!(
+                "Operands '{}' must be separated by commas in {} ILOC",
+                side,
+                self.description()
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Dialect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Dialect {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        Dialect::ALL
+            .into_iter()
+            .find(|dialect| dialect.name() == name)
+            .ok_or_else(|| {
+                let names: Vec<&str> = Dialect::ALL.iter().map(|d| d.name()).collect();
+                format!(
+                    "Unknown dialect '{}', expected one of {}",
+                    name,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// Translates `source`, read from `file` and written in `from`, to `to`.
+///
+/// The result is the parsed program printed canonically, so includes and
+/// macros come out expanded, `.equ` constants and literals evaluated and
+/// data as `.byte` rows.
+pub fn translate(source: &str, file: &str, from: Dialect, to: Dialect) -> Result<String, String> {
+    let mut program = parser::parse_program_in(source, file, from)?;
+    for line in program.code.iter_mut() {
+        line.text = to.render(&line.text);
+    }
+    Ok(program.to_string())
+}
+
+/// The arrow after `opcode` in the normalized form.
+fn canonical_arrow(opcode: &str) -> &'static str {
+    if ir::is_branch(opcode) {
+        "->"
+    } else {
+        "=>"
+    }
+}
+
+/// The textbook or extension opcode that `opcode` spells in another case,
+/// such as `LOADI` for `loadI`, if there is one.
+fn canonical_opcode(opcode: &str) -> Option<&'static str> {
+    TEXTBOOK_OPCODES
+        .iter()
+        .chain(EXTENSION_OPCODES)
+        .find(|known| known.eq_ignore_ascii_case(opcode))
+        .copied()
+}
+
+/// Whether `word` is a whole operand rather than part of an expression.
+fn is_operand(word: &str) -> bool {
+    ir::is_identifier(word) || ir::is_register(word) || literal::evaluate(word).is_ok()
+}
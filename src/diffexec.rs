@@ -0,0 +1,305 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::cfg::{Cfg, Liveness};
+use crate::ir::{self, Instruction};
+use crate::parser::Program;
+use crate::vm::{Limits, Termination, VM};
+
+/// How `diff_exec` feeds and compares the two programs.
+#[derive(Debug, Clone)]
+pub struct DiffConfig {
+    pub memory_size: usize,
+    /// Registers set before either program starts.
+    pub inputs: HashMap<String, i64>,
+    /// Registers whose final values must agree, in the order they are checked.
+    pub result_registers: Vec<String>,
+    /// With a seed, every trial fills memory and the registers either
+    /// program reads before writing with pseudo-random values. Trial `i`
+    /// uses seed `seed + i`. Without one, memory starts zeroed and only
+    /// `inputs` are set, so a single trial is run.
+    pub seed: Option<u64>,
+    pub trials: usize,
+    /// A program still running after this many steps is stopped.
+    pub max_steps: u64,
+}
+
+impl Default for DiffConfig {
+    fn default() -> Self {
+        Self {
+            memory_size: 1024,
+            inputs: HashMap::new(),
+            result_registers: Vec::new(),
+            seed: None,
+            trials: 1,
+            max_steps: 1_000_000,
+        }
+    }
+}
+
+/// The first observable difference between two runs. Each variant holds the
+/// first program's side before the second's.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Divergence {
+    Termination(Termination, Termination),
+    /// Output line `line` (1-based); `None` where one output ended first.
+    Output {
+        line: usize,
+        left: Option<String>,
+        right: Option<String>,
+    },
+    Memory {
+        address: usize,
+        left: u8,
+        right: u8,
+    },
+    /// `None` where the register was never written.
+    Register {
+        name: String,
+        left: Option<i64>,
+        right: Option<i64>,
+    },
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let or_missing = |value: &Option<String>| match value {
+            Some(value) => format!("{:?}", value),
+            None => "end of output".to_string(),
+        };
+        let or_undefined = |value: &Option<i64>| match value {
+            Some(value) => value.to_string(),
+            None => "undefined".to_string(),
+        };
+        match self {
+            Divergence::Termination(left, right) => write!(f, "Termination: {} vs {}", left, right),
+            Divergence::Output { line, left, right } => write!(
+                f,
+                "Output line {}: {} vs {}",
+                line,
+                or_missing(left),
+                or_missing(right)
+            ),
+            Divergence::Memory {
+                address,
+                left,
+                right,
+            } => write!(
+                f,
+                "Memory at {:#06x}: {:02x} vs {:02x}",
+                address, left, right
+            ),
+            Divergence::Register { name, left, right } => write!(
+                f,
+                "Register {}: {} vs {}",
+                name,
+                or_undefined(left),
+                or_undefined(right)
+            ),
+        }
+    }
+}
+
+/// Result of `diff_exec`: the number of trials that agreed and, if one did
+/// not, the seed that reproduces it (`None` for the unseeded trial).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffReport {
+    pub agreeing_trials: usize,
+    pub divergence: Option<(Option<u64>, Divergence)>,
+}
+
+/// The observable result of one run.
+#[derive(Debug, Clone)]
+struct Run {
+    termination: Termination,
+    output: String,
+    memory: Vec<u8>,
+    registers: HashMap<String, i64>,
+}
+
+/// Runs `left` and `right` in separate VMs on the same inputs and reports
+/// the first trial where their termination, output, final memory or result
+/// registers differ.
+pub fn diff_exec(left: &[String], right: &[String], config: &DiffConfig) -> DiffReport {
+    diff_runs((left, &[]), (right, &[]), config)
+}
+
+/// Like `diff_exec`, for parsed programs: each run starts with the
+/// program's data section copied to address 0, over the trial's memory.
+pub fn diff_exec_programs(left: &Program, right: &Program, config: &DiffConfig) -> DiffReport {
+    diff_runs(
+        (&left.text(), &left.data.bytes),
+        (&right.text(), &right.data.bytes),
+        config,
+    )
+}
+
+/// `diff_exec` on programs given as their code and data bytes.
+fn diff_runs(
+    (left, left_data): (&[String], &[u8]),
+    (right, right_data): (&[String], &[u8]),
+    config: &DiffConfig,
+) -> DiffReport {
+    let mut read_first: Vec<String> = entry_reads(left)
+        .union(&entry_reads(right))
+        .cloned()
+        .collect();
+    read_first.sort();
+
+    let trials = if config.seed.is_some() {
+        config.trials
+    } else {
+        1
+    };
+    for trial in 0..trials {
+        let seed = config.seed.map(|seed| seed.wrapping_add(trial as u64));
+
+        let mut registers = HashMap::new();
+        let mut memory = vec![0; config.memory_size];
+        if let Some(seed) = seed {
+            let mut random = SplitMix64(seed);
+            for reg in &read_first {
+                registers.insert(reg.clone(), i64::from(random.next() as i32));
+            }
+            for byte in memory.iter_mut() {
+                *byte = random.next() as u8;
+            }
+        }
+        registers.extend(
+            config
+                .inputs
+                .iter()
+                .map(|(reg, value)| (reg.clone(), *value)),
+        );
+
+        let left_run = run(left, left_data, &registers, &memory, config.max_steps);
+        let right_run = run(right, right_data, &registers, &memory, config.max_steps);
+        if let Some(divergence) = compare(&left_run, &right_run, &config.result_registers) {
+            return DiffReport {
+                agreeing_trials: trial,
+                divergence: Some((seed, divergence)),
+            };
+        }
+    }
+
+    DiffReport {
+        agreeing_trials: trials,
+        divergence: None,
+    }
+}
+
+fn run(
+    program: &[String],
+    data: &[u8],
+    registers: &HashMap<String, i64>,
+    memory: &[u8],
+    max_steps: u64,
+) -> Run {
+    let mut vm = VM::new(memory.len());
+    vm.load_program(program.to_vec());
+    vm.write_memory(0, memory);
+    vm.write_memory(0, data);
+    for (reg, value) in registers {
+        if ir::is_float_register(reg) {
+            vm.set_float_register(reg, *value as f64);
+        } else {
+            vm.set_register(reg, *value);
+        }
+    }
+
+    // A program that crashes the VM ends differently from one that does not
+    let termination = vm
+        .run_catching(&Limits {
+            max_steps: Some(max_steps),
+            ..Limits::default()
+        })
+        .unwrap_or_else(Termination::Crashed);
+
+    let mut memory = vec![0; vm.get_memory().size()];
+    vm.get_memory().read(0, &mut memory);
+    Run {
+        termination,
+        output: vm.get_output().to_string(),
+        memory,
+        registers: vm.get_registers().clone(),
+    }
+}
+
+fn compare(left: &Run, right: &Run, result_registers: &[String]) -> Option<Divergence> {
+    if left.termination != right.termination {
+        return Some(Divergence::Termination(
+            left.termination.clone(),
+            right.termination.clone(),
+        ));
+    }
+
+    let mut left_lines = left.output.lines();
+    let mut right_lines = right.output.lines();
+    for line in 1.. {
+        let (l, r) = (left_lines.next(), right_lines.next());
+        if l.is_none() && r.is_none() {
+            break;
+        }
+        if l != r {
+            return Some(Divergence::Output {
+                line,
+                left: l.map(str::to_string),
+                right: r.map(str::to_string),
+            });
+        }
+    }
+
+    if let Some(address) = (0..left.memory.len()).find(|&a| left.memory[a] != right.memory[a]) {
+        return Some(Divergence::Memory {
+            address,
+            left: left.memory[address],
+            right: right.memory[address],
+        });
+    }
+
+    for name in result_registers {
+        let (l, r) = (left.registers.get(name), right.registers.get(name));
+        if l != r {
+            return Some(Divergence::Register {
+                name: name.clone(),
+                left: l.copied(),
+                right: r.copied(),
+            });
+        }
+    }
+    None
+}
+
+/// Registers `program` may read before writing them, i.e. its inputs.
+fn entry_reads(program: &[String]) -> HashSet<String> {
+    let mut instructions = Vec::new();
+    for line in program {
+        let operations = match ir::split_bundle(line) {
+            Some((_, operations)) => operations,
+            None => vec![line.as_str()],
+        };
+        for op in operations {
+            match Instruction::parse(op) {
+                Ok(inst) => instructions.push(inst),
+                Err(_) => return HashSet::new(),
+            }
+        }
+    }
+
+    let cfg = Cfg::build(&instructions);
+    let liveness = Liveness::compute(&instructions, &cfg);
+    liveness.live_in.into_iter().next().unwrap_or_default()
+}
+
+/// Small deterministic generator for reproducible trial inputs.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
@@ -0,0 +1,297 @@
+use std::fmt;
+
+use crate::literal;
+use crate::parser;
+use crate::vm::{Fault, Limits, Termination, VM};
+
+/// Steps a self-checking program may take before it counts as hung, unless
+/// it sets its own limit with `//! max-steps N`.
+pub const MAX_STEPS: u64 = 1_000_000;
+
+/// One `//! expect` directive embedded in a program's comments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expectation {
+    /// `//! expect r2 == 15`, with any value a 64-bit word can hold
+    Register { name: String, value: i64 },
+    /// `//! expect mem[0x40..0x44] == 01 00 00 00`, or `mem[0x40]` for one byte
+    Memory { address: usize, bytes: Vec<u8> },
+    /// `//! expect output "42\n"`
+    Output(String),
+    /// `//! expect fault DivideByZero`
+    Fault(String),
+}
+
+impl fmt::Display for Expectation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expectation::Register { name, value } => write!(f, "{} == {}", name, value),
+            Expectation::Memory { address, bytes } => write!(
+                f,
+                "mem[{:#x}..{:#x}] == {}",
+                address,
+                address + bytes.len(),
+                hex_bytes(bytes)
+            ),
+            Expectation::Output(text) => write!(f, "output {:?}", text),
+            Expectation::Fault(name) => write!(f, "fault {}", name),
+        }
+    }
+}
+
+/// An expectation that did not hold, with the source line it came from.
+/// Failures of the run itself, such as an unexpected fault, have no line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "Line {}: {}", line, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Collects the `//! expect` directives of `source` with their 1-based line
+/// numbers. `//! max-steps` is read by `step_limit`; any other `//!` line
+/// is an error, so a typo cannot silently disable a check.
+pub fn parse_expectations(source: &str) -> Result<Vec<(usize, Expectation)>, String> {
+    let mut expectations = Vec::new();
+    for (line_number, directive) in directives(source) {
+        if directive.starts_with("max-steps") {
+            continue;
+        }
+        let expectation =
+            parse_directive(directive).map_err(|e| format!("Line {}: {}", line_number, e))?;
+        expectations.push((line_number, expectation));
+    }
+    Ok(expectations)
+}
+
+/// The limit set by a `//! max-steps N` directive, if any.
+pub fn step_limit(source: &str) -> Result<Option<u64>, String> {
+    let mut limit = None;
+    for (line_number, directive) in directives(source) {
+        if let Some(steps) = directive.strip_prefix("max-steps") {
+            let steps = literal::evaluate(steps)
+                .ok()
+                .and_then(|steps| u64::try_from(steps).ok())
+                .ok_or_else(|| {
+                    format!(
+                        "Line {}: Invalid step limit '{}'",
+                        line_number,
+                        steps.trim()
+                    )
+                })?;
+            limit = Some(steps);
+        }
+    }
+    Ok(limit)
+}
+
+/// The text after `//!` on every line that has it, with 1-based line numbers.
+fn directives(source: &str) -> impl Iterator<Item = (usize, &str)> {
+    source.lines().enumerate().filter_map(|(idx, line)| {
+        let pos = line.find("//!")?;
+        Some((idx + 1, line[pos + 3..].trim()))
+    })
+}
+
+pub(crate) fn parse_directive(directive: &str) -> Result<Expectation, String> {
+    let rest = directive
+        .strip_prefix("expect ")
+        .ok_or_else(|| format!("Unknown directive '{}'", directive))?
+        .trim();
+
+    if let Some(text) = rest.strip_prefix("output ") {
+        return parse_string(text.trim()).map(Expectation::Output);
+    }
+    if let Some(name) = rest.strip_prefix("fault ") {
+        return Ok(Expectation::Fault(name.trim().to_string()));
+    }
+
+    let (left, right) = rest
+        .split_once("==")
+        .ok_or_else(|| format!("Expected '==' in '{}'", rest))?;
+    let (left, right) = (left.trim(), right.trim());
+
+    if let Some(range) = left.strip_prefix("mem[").and_then(|r| r.strip_suffix(']')) {
+        let (start, end) = match range.split_once("..") {
+            Some((start, end)) => (literal::evaluate(start)?, literal::evaluate(end)?),
+            None => {
+                let start = literal::evaluate(range)?;
+                (start, start + 1)
+            }
+        };
+        if start < 0 || end <= start {
+            return Err(format!("Invalid memory range '{}'", range));
+        }
+        let bytes = right
+            .split_whitespace()
+            .map(|byte| {
+                u8::from_str_radix(byte, 16).map_err(|_| format!("Invalid byte '{}'", byte))
+            })
+            .collect::<Result<Vec<u8>, String>>()?;
+        if bytes.len() as i64 != end - start {
+            return Err(format!(
+                "Range '{}' covers {} bytes but {} were given",
+                range,
+                end - start,
+                bytes.len()
+            ));
+        }
+        return Ok(Expectation::Memory {
+            address: start as usize,
+            bytes,
+        });
+    }
+
+    if crate::ir::is_register(left) {
+        let value = literal::evaluate(right)?;
+        return Ok(Expectation::Register {
+            name: left.to_string(),
+            value,
+        });
+    }
+
+    Err(format!("Cannot check '{}'", left))
+}
+
+pub(crate) fn parse_string(text: &str) -> Result<String, String> {
+    let inner = text
+        .strip_prefix('"')
+        .and_then(|t| t.strip_suffix('"'))
+        .ok_or_else(|| format!("Expected a quoted string, got '{}'", text))?;
+    let mut result = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('\\') => result.push('\\'),
+            Some('"') => result.push('"'),
+            Some('0') => result.push('\0'),
+            other => return Err(format!("Invalid escape '\\{}'", other.unwrap_or(' '))),
+        }
+    }
+    Ok(result)
+}
+
+pub(crate) fn hex_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Runs the program in `source`, read from `file`, and checks its
+/// `//! expect` directives against the final state. Returns the failed
+/// expectations; an `Err` means the program or a directive could not be
+/// parsed. Includes are found relative to `file`.
+///
+/// A program that faults without a matching `expect fault`, expects a
+/// fault and finishes normally, or crashes the VM fails as well.
+pub fn check(source: &str, file: &str) -> Result<Vec<Failure>, String> {
+    let expectations = parse_expectations(source)?;
+    let program = parser::parse_program(source, file)?;
+
+    let mut vm = VM::new(1024);
+    vm.load(&program)?;
+    let limits = Limits {
+        max_steps: Some(step_limit(source)?.unwrap_or(MAX_STEPS)),
+        ..Limits::default()
+    };
+    let fault = match vm.run_catching(&limits) {
+        Ok(Termination::Finished) => None,
+        Ok(Termination::Fault(fault)) => Some(fault),
+        Err(message) => {
+            return Ok(vec![Failure {
+                line: None,
+                message: format!("Crashed: {}", message),
+            }])
+        }
+        Ok(termination) => {
+            return Ok(vec![Failure {
+                line: None,
+                message: format!("Did not finish: {}", termination),
+            }])
+        }
+    };
+
+    Ok(verify(&expectations, &vm, fault.as_ref()))
+}
+
+/// Checks `expectations` against the final state of `vm`, which stopped
+/// with `fault` or finished normally.
+pub(crate) fn verify(
+    expectations: &[(usize, Expectation)],
+    vm: &VM,
+    fault: Option<&Fault>,
+) -> Vec<Failure> {
+    let (registers, memory) = (vm.get_registers(), vm.get_memory());
+    let mut failures = Vec::new();
+    let mut fault_expected = false;
+    for (line, expectation) in expectations {
+        let mut fail = |message: String| {
+            failures.push(Failure {
+                line: Some(*line),
+                message,
+            })
+        };
+        match expectation {
+            Expectation::Register { name, value } => match registers.get(name) {
+                Some(actual) if actual == value => {}
+                Some(actual) => fail(format!("expected {} == {}, got {}", name, value, actual)),
+                None => fail(format!(
+                    "expected {} == {}, but it was never written",
+                    name, value
+                )),
+            },
+            Expectation::Memory { address, bytes } => {
+                let actual = (address + bytes.len() <= memory.size()).then(|| {
+                    let mut actual = vec![0; bytes.len()];
+                    memory.read(*address, &mut actual);
+                    actual
+                });
+                if actual.as_ref() != Some(bytes) {
+                    let actual =
+                        actual.map_or("out of bounds".to_string(), |actual| hex_bytes(&actual));
+                    fail(format!("expected {}, got {}", expectation, actual));
+                }
+            }
+            Expectation::Output(text) => {
+                if vm.get_output() != text {
+                    fail(format!(
+                        "expected output {:?}, got {:?}",
+                        text,
+                        vm.get_output()
+                    ));
+                }
+            }
+            Expectation::Fault(name) => {
+                fault_expected = true;
+                match fault {
+                    Some(actual) if actual.name() == name => {}
+                    Some(actual) => fail(format!("expected fault {}, got {}", name, actual.name())),
+                    None => fail(format!("expected fault {}, but the program finished", name)),
+                }
+            }
+        }
+    }
+    if let (Some(fault), false) = (fault, fault_expected) {
+        failures.push(Failure {
+            line: None,
+            message: format!("Unexpected fault {}: {}", fault.name(), fault),
+        });
+    }
+
+    failures
+}
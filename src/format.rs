@@ -0,0 +1,365 @@
+use crate::ir;
+use crate::parser::{self, Program};
+use crate::preprocess;
+
+/// One line of formatted output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Row {
+    pub label: Option<String>,
+    pub body: Body,
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Body {
+    /// A blank line, or a line holding only a label or a comment.
+    Empty,
+    /// An instruction or macro call, aligned with the others.
+    Operation {
+        opcode: String,
+        sources: Vec<String>,
+        /// The arrow and the operands right of it.
+        targets: Option<(String, Vec<String>)>,
+    },
+    /// Directives and bundles, written as they are after the label.
+    Text(String),
+}
+
+impl Row {
+    /// The row for `code`, one statement with an optional `name:` label.
+    pub fn parse(code: &str, comment: Option<String>) -> Row {
+        let code = code.trim();
+        let (label, rest) = match code.split_once(':') {
+            Some((label, rest)) if ir::is_identifier(label.trim()) => {
+                (Some(label.trim().to_string()), rest.trim())
+            }
+            _ => (None, code),
+        };
+
+        let body = if rest.is_empty() {
+            Body::Empty
+        } else if rest.starts_with('.') {
+            let (keyword, args) = split_keyword(rest);
+            match args.is_empty() {
+                true => Body::Text(keyword.to_string()),
+                false => Body::Text(format!("{} {}", keyword, split_list(args, ",").join(", "))),
+            }
+        } else if let Some(inner) = rest.strip_prefix('[').and_then(|r| r.strip_suffix(']')) {
+            let operations: Vec<String> = split_list(inner, ";")
+                .into_iter()
+                .filter(|op| !op.is_empty())
+                .map(|op| operation(&op).to_string())
+                .collect();
+            Body::Text(format!("[ {} ]", operations.join(" ; ")))
+        } else {
+            operation(rest)
+        };
+        Row {
+            label,
+            body,
+            comment,
+        }
+    }
+
+    fn comment_only(comment: String) -> Row {
+        Row {
+            label: None,
+            body: Body::Empty,
+            comment: Some(comment),
+        }
+    }
+
+    fn blank() -> Row {
+        Row {
+            label: None,
+            body: Body::Empty,
+            comment: None,
+        }
+    }
+}
+
+impl std::fmt::Display for Body {
+    /// The body on its own, without alignment.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Body::Empty => Ok(()),
+            Body::Text(text) => write!(f, "{}", text),
+            Body::Operation {
+                opcode,
+                sources,
+                targets,
+            } => {
+                write!(f, "{}", opcode)?;
+                if !sources.is_empty() {
+                    write!(f, " {}", sources.join(", "))?;
+                }
+                if let Some((arrow, targets)) = targets {
+                    write!(f, " {} {}", arrow, targets.join(", "))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Lays out `rows` with labels in column 0, and opcodes, operands, arrows
+/// and trailing comments each starting in a column of their own.
+pub(crate) fn layout(rows: &[Row]) -> String {
+    let label_width = rows
+        .iter()
+        .filter(|row| row.body != Body::Empty)
+        .filter_map(|row| row.label.as_ref())
+        .map(|label| label.len() + 2)
+        .max()
+        .unwrap_or(0);
+    let opcode_width = rows
+        .iter()
+        .filter_map(|row| match &row.body {
+            Body::Operation { opcode, .. } => Some(opcode.len()),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0);
+    let sources_width = rows
+        .iter()
+        .filter_map(|row| match &row.body {
+            Body::Operation {
+                sources,
+                targets: Some(_),
+                ..
+            } => Some(sources.join(", ").len()),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0);
+
+    let code: Vec<String> = rows
+        .iter()
+        .map(|row| {
+            let label = row
+                .label
+                .as_ref()
+                .map_or(String::new(), |label| format!("{}:", label));
+            let body = match &row.body {
+                Body::Empty => return label,
+                Body::Text(text) => text.clone(),
+                Body::Operation {
+                    opcode,
+                    sources,
+                    targets,
+                } => {
+                    let sources = sources.join(", ");
+                    match targets {
+                        Some((arrow, targets)) => format!(
+                            "{:o$} {:s$} {} {}",
+                            opcode,
+                            sources,
+                            arrow,
+                            targets.join(", "),
+                            o = opcode_width,
+                            s = sources_width
+                        ),
+                        None => format!("{:o$} {}", opcode, sources, o = opcode_width),
+                    }
+                }
+            };
+            format!("{:w$}{}", label, body, w = label_width)
+                .trim_end()
+                .to_string()
+        })
+        .collect();
+    let comment_column = rows
+        .iter()
+        .zip(&code)
+        .filter(|(row, code)| row.comment.is_some() && !code.is_empty())
+        .map(|(_, code)| code.len() + 2)
+        .max()
+        .unwrap_or(0);
+
+    let mut result = String::new();
+    for (row, code) in rows.iter().zip(code) {
+        let line = match &row.comment {
+            Some(comment) if code.is_empty() => format!("// {}", comment),
+            Some(comment) => format!("{:w$}// {}", code, comment, w = comment_column),
+            None => code,
+        };
+        result.push_str(line.trim_end());
+        result.push('\n');
+    }
+    result
+}
+
+/// Formats `source`, read from `file`, canonically: labels in column 0,
+/// aligned opcodes, operands, arrows and comments, `, ` between operands,
+/// `//` comments and at most one blank line in a row.
+///
+/// Includes and macros are formatted where they are, not expanded. Fails if
+/// `source` does not parse, or if the result would parse to anything else.
+pub fn format_source(source: &str, file: &str) -> Result<String, String> {
+    let program = parser::parse_program(source, file)?;
+
+    let mut rows = Vec::new();
+    let mut in_block_comment = false;
+    for (idx, text) in source.lines().enumerate() {
+        let trimmed = text.trim();
+        if trimmed.starts_with("#include") && !in_block_comment {
+            rows.push(Row {
+                label: None,
+                body: Body::Text(trimmed.split_whitespace().collect::<Vec<&str>>().join(" ")),
+                comment: None,
+            });
+            continue;
+        }
+        let line = preprocess::scan_line(text, &mut in_block_comment, file, idx + 1);
+        let comment = (!line.comments.is_empty()).then(|| {
+            line.comments
+                .iter()
+                .map(|comment| comment.trim())
+                .filter(|comment| !comment.is_empty())
+                .collect::<Vec<&str>>()
+                .join(" ")
+        });
+        let row = match (line.code.trim().is_empty(), comment) {
+            (true, None) => Row::blank(),
+            (true, Some(comment)) => Row::comment_only(comment),
+            (false, comment) => Row::parse(&line.code, comment.filter(|c| !c.is_empty())),
+        };
+        if row == Row::blank() && rows.last().is_none_or(|last| *last == Row::blank()) {
+            continue;
+        }
+        rows.push(row);
+    }
+    if rows.last() == Some(&Row::blank()) {
+        rows.pop();
+    }
+
+    let formatted = layout(&rows);
+    let reparsed = parser::parse_program(&formatted, file)?;
+    if reparsed.text() != program.text() || reparsed.data != program.data {
+        return Err("Formatting would change the program".to_string());
+    }
+    Ok(formatted)
+}
+
+/// The rows that `Display for Program` lays out: the data segment as
+/// labeled `.byte` rows, then the instructions with their comments.
+pub(crate) fn program_rows(program: &Program) -> Vec<Row> {
+    let mut rows = Vec::new();
+    let data = &program.data;
+    if !data.bytes.is_empty() || !data.labels.is_empty() {
+        rows.push(Row::parse(".data", None));
+
+        // Every label starts a new row, so that it keeps its address
+        let mut starts: Vec<usize> = data.labels.values().copied().collect();
+        starts.push(0);
+        starts.push(data.bytes.len());
+        starts.sort();
+        starts.dedup();
+        for pair in starts.windows(2) {
+            let mut labels = labels_at(program, pair[0]);
+            let last = labels.pop();
+            rows.extend(labels.into_iter().map(|label| Row::parse(&label, None)));
+            for (idx, chunk) in data.bytes[pair[0]..pair[1]].chunks(16).enumerate() {
+                let bytes: Vec<String> = chunk.iter().map(|byte| byte.to_string()).collect();
+                let mut row = Row::parse(&format!(".byte {}", bytes.join(", ")), None);
+                if idx == 0 {
+                    row.label = last
+                        .as_ref()
+                        .map(|label| label.trim_end_matches(':').to_string());
+                }
+                rows.push(row);
+            }
+        }
+        for label in labels_at(program, data.bytes.len()) {
+            rows.push(Row::parse(&label, None));
+        }
+        rows.push(Row::parse(".text", None));
+    }
+    for line in &program.code {
+        rows.push(Row::parse(&line.text, line.comment.clone()));
+    }
+    rows
+}
+
+/// Every data label at `address`, as `name:`.
+fn labels_at(program: &Program, address: usize) -> Vec<String> {
+    program
+        .data
+        .labels
+        .iter()
+        .filter(|(_, &at)| at == address)
+        .map(|(label, _)| format!("{}:", label))
+        .collect()
+}
+
+fn operation(text: &str) -> Body {
+    let (opcode, operands) = split_keyword(text);
+    let arrow = ["=>", "->"]
+        .into_iter()
+        .find(|arrow| split_list(operands, arrow).len() > 1);
+    let (sources, targets) = match arrow {
+        Some(arrow) => {
+            let sides = split_list(operands, arrow);
+            let (sources, targets) = sides.split_at(1);
+            let targets = targets.join(arrow);
+            (
+                sources[0].clone(),
+                Some((arrow.to_string(), split_list(&targets, ","))),
+            )
+        }
+        None => (operands.to_string(), None),
+    };
+    let sources = match sources.trim().is_empty() {
+        true => Vec::new(),
+        false => split_list(&sources, ","),
+    };
+    Body::Operation {
+        opcode: opcode.to_string(),
+        sources,
+        targets,
+    }
+}
+
+/// Splits `.word 1, 2` into `.word` and `1, 2`.
+fn split_keyword(statement: &str) -> (&str, &str) {
+    match statement.find(char::is_whitespace) {
+        Some(pos) => (&statement[..pos], statement[pos..].trim()),
+        None => (statement, ""),
+    }
+}
+
+/// Splits `text` at every `separator` outside string and character
+/// literals, collapsing whitespace outside them in each part.
+fn split_list(text: &str, separator: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut part = String::new();
+    let mut quote = None;
+    let mut escaped = false;
+    let mut chars = text.char_indices();
+    while let Some((idx, c)) = chars.next() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if text[idx..].starts_with(separator) => {
+                parts.push(std::mem::take(&mut part).trim().to_string());
+                for _ in 1..separator.len() {
+                    chars.next();
+                }
+                continue;
+            }
+            None if c.is_whitespace() => {
+                if !part.ends_with(' ') {
+                    part.push(' ');
+                }
+                continue;
+            }
+            None => {}
+        }
+        part.push(c);
+    }
+    parts.push(part.trim().to_string());
+    parts
+}
@@ -0,0 +1,263 @@
+use std::fmt;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::expect;
+use crate::parser;
+use crate::vm::{Limits, VM};
+
+/// How `run_directory` runs the programs of a golden-file directory.
+#[derive(Debug, Clone)]
+pub struct GoldenConfig {
+    pub memory_size: usize,
+    /// Step limit for programs without a `//! max-steps N` directive.
+    pub max_steps: u64,
+    /// Wall-clock limit per program.
+    pub timeout: Option<Duration>,
+    /// Stop programs whose machine state repeats, see `Limits::detect_repeats`.
+    pub detect_repeats: bool,
+    /// Overwrite `.expected` files with the actual state instead of comparing.
+    pub bless: bool,
+    /// Programs run at once; 0 uses every available core.
+    pub jobs: usize,
+}
+
+impl Default for GoldenConfig {
+    fn default() -> Self {
+        Self {
+            memory_size: 1024,
+            max_steps: expect::MAX_STEPS,
+            timeout: None,
+            detect_repeats: false,
+            bless: false,
+            jobs: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    Passed,
+    /// The final state differs; holds a unified diff from expected to actual.
+    Failed(String),
+    /// `--bless` wrote a new or changed `.expected` file.
+    Blessed,
+    /// There is no `.expected` file and `bless` is off.
+    Missing,
+    /// The program could not be read or parsed, or crashed the VM.
+    Error(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GoldenResult {
+    pub program: PathBuf,
+    pub status: Status,
+}
+
+impl GoldenResult {
+    pub fn passed(&self) -> bool {
+        matches!(self.status, Status::Passed | Status::Blessed)
+    }
+}
+
+impl fmt::Display for GoldenResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let path = self.program.display();
+        match &self.status {
+            Status::Passed => write!(f, "PASS {}", path),
+            Status::Blessed => write!(f, "BLESS {}", path),
+            Status::Missing => write!(f, "MISSING {} (run with --bless to create it)", path),
+            Status::Failed(diff) => write!(f, "FAIL {}\n{}", path, diff.trim_end()),
+            Status::Error(e) => write!(f, "ERROR {}\n    {}", path, e),
+        }
+    }
+}
+
+/// Runs every `foo.iloc` in `dir` and compares its final state with
+/// `foo.expected` next to it. Results come back sorted by path.
+pub fn run_directory(dir: &Path, config: &GoldenConfig) -> Result<Vec<GoldenResult>, String> {
+    let entries = fs::read_dir(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    let mut programs: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "iloc"))
+        .collect();
+    programs.sort();
+
+    let jobs = match config.jobs {
+        0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        jobs => jobs,
+    };
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::new());
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.min(programs.len()) {
+            scope.spawn(|| loop {
+                let idx = next.fetch_add(1, Ordering::Relaxed);
+                let Some(program) = programs.get(idx) else {
+                    break;
+                };
+                // A crash is reported like any other error, so that one
+                // program cannot abort the whole directory
+                let status = panic::catch_unwind(AssertUnwindSafe(|| run_one(program, config)))
+                    .unwrap_or_else(|_| Status::Error("Crashed".to_string()));
+                results.lock().unwrap().push(GoldenResult {
+                    program: program.clone(),
+                    status,
+                });
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by(|a, b| a.program.cmp(&b.program));
+    Ok(results)
+}
+
+fn run_one(program: &Path, config: &GoldenConfig) -> Status {
+    let actual = match fs::read_to_string(program)
+        .map_err(|e| e.to_string())
+        .and_then(|source| final_state(&source, &program.display().to_string(), config))
+    {
+        Ok(actual) => actual,
+        Err(e) => return Status::Error(e),
+    };
+
+    let golden = program.with_extension("expected");
+    let expected = fs::read_to_string(&golden).ok();
+    if expected.as_deref() == Some(actual.as_str()) {
+        return Status::Passed;
+    }
+    if config.bless {
+        return match fs::write(&golden, &actual) {
+            Ok(()) => Status::Blessed,
+            Err(e) => Status::Error(format!("{}: {}", golden.display(), e)),
+        };
+    }
+    match expected {
+        Some(expected) => Status::Failed(unified_diff(
+            &expected,
+            &actual,
+            &golden.display().to_string(),
+            "actual",
+        )),
+        None => Status::Missing,
+    }
+}
+
+/// Runs the program in `source`, read from `file`, and renders its final
+/// state the way `.expected` files store it: how the run ended, the
+/// registers in name order, every 16-byte memory row that is not all
+/// zeros, and the output.
+pub fn final_state(source: &str, file: &str, config: &GoldenConfig) -> Result<String, String> {
+    let program = parser::parse_program(source, file)?;
+    let max_steps = expect::step_limit(source)?.unwrap_or(config.max_steps);
+
+    let mut vm = VM::new(config.memory_size);
+    vm.load(&program)?;
+    let termination = vm
+        .run_catching(&Limits {
+            max_steps: Some(max_steps),
+            timeout: config.timeout,
+            detect_repeats: config.detect_repeats,
+        })
+        .map_err(|message| format!("Crashed: {}", message))?;
+    let registers = vm.get_registers();
+
+    let mut dump = format!("termination: {}\n", termination);
+    dump.push_str("registers:\n");
+    let mut names: Vec<&String> = registers.keys().collect();
+    names.sort_by_key(|name| (name.len(), name.as_str()));
+    for name in names {
+        dump.push_str(&format!("    {} = {}\n", name, registers[name]));
+    }
+    dump.push_str("memory:\n");
+    for (start, page) in vm.get_memory().pages() {
+        for (row, bytes) in page.chunks(16).enumerate() {
+            if bytes.iter().any(|&byte| byte != 0) {
+                let bytes: Vec<String> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+                dump.push_str(&format!(
+                    "    {:#06x}: {}\n",
+                    start + row * 16,
+                    bytes.join(" ")
+                ));
+            }
+        }
+    }
+    dump.push_str("output:\n");
+    for line in vm.get_output().lines() {
+        dump.push_str(&format!("    {}\n", line));
+    }
+    Ok(dump)
+}
+
+/// A unified diff turning `old` into `new`, with three lines of context.
+pub fn unified_diff(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // Longest common subsequence table, from the end of both inputs
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    // Edit script as (kind, old index, new index)
+    let mut edits = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            edits.push((' ', i, j));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            edits.push(('-', i, j));
+            i += 1;
+        } else {
+            edits.push(('+', i, j));
+            j += 1;
+        }
+    }
+
+    const CONTEXT: usize = 3;
+    let mut diff = format!("--- {}\n+++ {}\n", old_name, new_name);
+    let changes: Vec<usize> = (0..edits.len()).filter(|&e| edits[e].0 != ' ').collect();
+    let mut c = 0;
+    while c < changes.len() {
+        // Grow the hunk while the next change is close enough to share context
+        let start = changes[c].saturating_sub(CONTEXT);
+        let mut last = changes[c];
+        while c + 1 < changes.len() && changes[c + 1] <= last + 2 * CONTEXT {
+            c += 1;
+            last = changes[c];
+        }
+        let end = (last + CONTEXT + 1).min(edits.len());
+        c += 1;
+
+        let hunk = &edits[start..end];
+        let old_count = hunk.iter().filter(|edit| edit.0 != '+').count();
+        let new_count = hunk.iter().filter(|edit| edit.0 != '-').count();
+        let (_, old_start, new_start) = hunk[0];
+        diff.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            old_start + usize::from(old_count > 0),
+            old_count,
+            new_start + usize::from(new_count > 0),
+            new_count
+        ));
+        for &(kind, i, j) in hunk {
+            let line = if kind == '+' { new[j] } else { old[i] };
+            diff.push_str(&format!("{}{}\n", kind, line));
+        }
+    }
+    diff
+}
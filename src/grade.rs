@@ -0,0 +1,441 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::time::Duration;
+
+use crate::expect::{self, Expectation};
+use crate::literal;
+use crate::parser;
+use crate::vm::{Limits, Termination, VM};
+
+/// A grading rubric, read from a text file such as:
+///
+/// ```text
+/// # Factorial, assignment 3
+/// memory 4096
+/// max-steps 100000
+/// timeout 2000
+/// detect-loops
+///
+/// case small 2
+///     set r1 = 5
+///     word 0x100 = 1 2 3
+///     expect r2 == 120
+///     expect output "120\n"
+///
+/// limit instructions 60 1
+/// limit cycles 200 1
+/// limit registers 8 1
+/// ```
+///
+/// A case's points are awarded when all of its expectations hold. A limit's
+/// points are awarded when every case passes and the worst case stays within
+/// the limit, so a wrong but fast program earns nothing for speed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rubric {
+    /// Bytes of memory each sandboxed run gets.
+    pub memory_size: usize,
+    /// Steps after which a run is stopped and its case fails.
+    pub max_steps: u64,
+    /// Wall-clock limit per run, set in milliseconds with `timeout`. How
+    /// far a program gets before it depends on the machine and its load,
+    /// so the same submission can pass on one run and time out on the
+    /// next; rubrics that must score reproducibly should rely on
+    /// `max-steps` and leave this unset.
+    pub timeout: Option<Duration>,
+    /// Set by `detect-loops`: stop runs whose machine state repeats.
+    pub detect_repeats: bool,
+    pub cases: Vec<Case>,
+    pub limits: Vec<Limit>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Case {
+    pub name: String,
+    pub weight: u32,
+    /// Registers set before the run.
+    pub registers: Vec<(String, i32)>,
+    /// Little-endian words written to memory before the run.
+    pub words: Vec<(usize, Vec<i32>)>,
+    /// Expectations with the rubric line they came from.
+    pub expectations: Vec<(usize, Expectation)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    /// Program lines executed, a bundle counting once.
+    Instructions,
+    /// Cycles under the default cycle model.
+    Cycles,
+    /// Distinct registers named in the program text.
+    Registers,
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Metric::Instructions => write!(f, "instructions"),
+            Metric::Cycles => write!(f, "cycles"),
+            Metric::Registers => write!(f, "registers"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limit {
+    pub metric: Metric,
+    pub max: u64,
+    pub weight: u32,
+}
+
+impl Rubric {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut rubric = Rubric {
+            memory_size: 1024,
+            max_steps: expect::MAX_STEPS,
+            timeout: None,
+            detect_repeats: false,
+            cases: Vec::new(),
+            limits: Vec::new(),
+        };
+
+        for (idx, line) in text.lines().enumerate() {
+            let line_number = idx + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            rubric
+                .parse_line(line_number, line)
+                .map_err(|e| format!("Line {}: {}", line_number, e))?;
+        }
+
+        if rubric.cases.is_empty() {
+            return Err("Rubric has no cases".to_string());
+        }
+        Ok(rubric)
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read rubric '{}': {}", path, e))?;
+        Self::parse(&text).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    fn parse_line(&mut self, line_number: usize, line: &str) -> Result<(), String> {
+        let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        let fields: Vec<&str> = rest.split_whitespace().collect();
+
+        match keyword {
+            "memory" => self.memory_size = parse_count(rest)? as usize,
+            "max-steps" => self.max_steps = parse_count(rest)?,
+            "timeout" => self.timeout = Some(Duration::from_millis(parse_count(rest)?)),
+            "detect-loops" if rest.is_empty() => self.detect_repeats = true,
+            "case" => {
+                let [name, weight] = fields[..] else {
+                    return Err("Expected 'case <name> <weight>'".to_string());
+                };
+                self.cases.push(Case {
+                    name: name.to_string(),
+                    weight: parse_count(weight)? as u32,
+                    registers: Vec::new(),
+                    words: Vec::new(),
+                    expectations: Vec::new(),
+                });
+            }
+            "limit" => {
+                let [metric, max, weight] = fields[..] else {
+                    return Err("Expected 'limit <metric> <max> <weight>'".to_string());
+                };
+                let metric = match metric {
+                    "instructions" => Metric::Instructions,
+                    "cycles" => Metric::Cycles,
+                    "registers" => Metric::Registers,
+                    _ => return Err(format!("Unknown metric '{}'", metric)),
+                };
+                self.limits.push(Limit {
+                    metric,
+                    max: parse_count(max)?,
+                    weight: parse_count(weight)? as u32,
+                });
+            }
+            "set" | "word" | "expect" => {
+                let case = self
+                    .cases
+                    .last_mut()
+                    .ok_or_else(|| format!("'{}' outside of a case", keyword))?;
+                match keyword {
+                    "set" => {
+                        let (reg, value) = split_assignment(rest)?;
+                        if !crate::ir::is_register(reg) {
+                            return Err(format!("Invalid register '{}'", reg));
+                        }
+                        case.registers.push((reg.to_string(), parse_word(value)?));
+                    }
+                    "word" => {
+                        let (address, values) = split_assignment(rest)?;
+                        let address = parse_count(address)? as usize;
+                        let values = values.split_whitespace().map(parse_word).collect::<Result<
+                            Vec<i32>,
+                            String,
+                        >>(
+                        )?;
+                        case.words.push((address, values));
+                    }
+                    _ => {
+                        let expectation = expect::parse_directive(line)?;
+                        case.expectations.push((line_number, expectation));
+                    }
+                }
+            }
+            _ => return Err(format!("Unknown keyword '{}'", keyword)),
+        }
+        Ok(())
+    }
+
+    /// The most points a submission can earn.
+    pub fn total(&self) -> u32 {
+        self.cases.iter().map(|case| case.weight).sum::<u32>()
+            + self.limits.iter().map(|limit| limit.weight).sum::<u32>()
+    }
+}
+
+fn split_assignment(text: &str) -> Result<(&str, &str), String> {
+    text.split_once('=')
+        .map(|(left, right)| (left.trim(), right.trim()))
+        .ok_or_else(|| format!("Expected '=' in '{}'", text))
+}
+
+fn parse_count(text: &str) -> Result<u64, String> {
+    literal::evaluate(text)
+        .ok()
+        .and_then(|value| u64::try_from(value).ok())
+        .ok_or_else(|| format!("Invalid count '{}'", text.trim()))
+}
+
+fn parse_word(text: &str) -> Result<i32, String> {
+    let value = literal::evaluate(text)?;
+    i32::try_from(value).map_err(|_| format!("Value {} is out of range", value))
+}
+
+/// How one case of the rubric went for a submission.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaseResult {
+    pub name: String,
+    pub weight: u32,
+    pub passed: bool,
+    pub termination: String,
+    pub failures: Vec<String>,
+    pub instructions: u64,
+    pub cycles: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LimitResult {
+    pub limit: Limit,
+    /// Worst value over all cases, or the static count for registers.
+    pub actual: u64,
+    pub met: bool,
+}
+
+/// The score report of one submission.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub student: String,
+    pub score: u32,
+    pub total: u32,
+    /// Set when the submission could not be parsed; nothing ran then.
+    pub error: Option<String>,
+    pub cases: Vec<CaseResult>,
+    pub limits: Vec<LimitResult>,
+}
+
+/// Grades the program in `source`, read from `file`, against `rubric`.
+/// Every case runs in a fresh VM with the rubric's memory size and
+/// execution limits; a run that panics, e.g. by reading an undefined
+/// register, fails its case instead of aborting the grader.
+pub fn grade(student: &str, source: &str, file: &str, rubric: &Rubric) -> Report {
+    let mut report = Report {
+        student: student.to_string(),
+        score: 0,
+        total: rubric.total(),
+        error: None,
+        cases: Vec::new(),
+        limits: Vec::new(),
+    };
+    let program = match parser::parse_program_sized(source, file, rubric.memory_size) {
+        Ok(program) => program,
+        Err(e) => {
+            report.error = Some(e);
+            return report;
+        }
+    };
+    if let Err(e) = VM::new(rubric.memory_size).load(&program) {
+        report.error = Some(e);
+        return report;
+    }
+
+    for case in &rubric.cases {
+        let result = run_case(&program, case, rubric);
+        if result.passed {
+            report.score += case.weight;
+        }
+        report.cases.push(result);
+    }
+
+    let all_passed = report.cases.iter().all(|case| case.passed);
+    let registers = registers_used(&program.text());
+    for limit in &rubric.limits {
+        let actual = match limit.metric {
+            Metric::Instructions => report.cases.iter().map(|c| c.instructions).max(),
+            Metric::Cycles => report.cases.iter().map(|c| c.cycles).max(),
+            Metric::Registers => Some(registers),
+        }
+        .unwrap_or(0);
+        let met = all_passed && actual <= limit.max;
+        if met {
+            report.score += limit.weight;
+        }
+        report.limits.push(LimitResult {
+            limit: *limit,
+            actual,
+            met,
+        });
+    }
+    report
+}
+
+fn run_case(program: &parser::Program, case: &Case, rubric: &Rubric) -> CaseResult {
+    let mut vm = VM::new(rubric.memory_size);
+    vm.load(program)
+        .expect("grade checks that the data section fits");
+    for (reg, value) in &case.registers {
+        vm.set_register(reg, (*value).into());
+    }
+    for (address, values) in &case.words {
+        let bytes: Vec<u8> = values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        vm.write_memory(*address, &bytes);
+    }
+
+    let limits = Limits {
+        max_steps: Some(rubric.max_steps),
+        timeout: rubric.timeout,
+        detect_repeats: rubric.detect_repeats,
+    };
+    let run = vm.run_catching(&limits);
+    let mut result = CaseResult {
+        name: case.name.clone(),
+        weight: case.weight,
+        passed: false,
+        termination: String::new(),
+        failures: Vec::new(),
+        instructions: vm.get_instruction_count(),
+        cycles: vm.get_cycle_stats().cycles,
+    };
+    // A run stopped by the step limit or a crash cannot meet any expectation
+    let (termination, failures) = match run {
+        Ok(Termination::Finished) => (
+            Termination::Finished.to_string(),
+            Some(expect::verify(&case.expectations, &vm, None)),
+        ),
+        Ok(Termination::Fault(fault)) => (
+            Termination::Fault(fault.clone()).to_string(),
+            Some(expect::verify(&case.expectations, &vm, Some(&fault))),
+        ),
+        Ok(termination) => (termination.to_string(), None),
+        Err(message) => (format!("crashed: {}", message), None),
+    };
+    result.termination = termination;
+    result.passed = failures.as_ref().is_some_and(Vec::is_empty);
+    result.failures = failures
+        .unwrap_or_default()
+        .iter()
+        .map(|failure| failure.to_string())
+        .collect();
+    result
+}
+
+/// Distinct registers named anywhere in `program`.
+pub fn registers_used(program: &[String]) -> u64 {
+    program
+        .iter()
+        .flat_map(|line| line.split(|c: char| c.is_whitespace() || ",;[]".contains(c)))
+        .filter(|token| crate::ir::is_register(token))
+        .collect::<BTreeSet<&str>>()
+        .len() as u64
+}
+
+impl Report {
+    /// The report as a JSON object.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\n");
+        json.push_str(&format!("  \"student\": {},\n", json_string(&self.student)));
+        json.push_str(&format!("  \"score\": {},\n", self.score));
+        json.push_str(&format!("  \"total\": {},\n", self.total));
+        let error = self
+            .error
+            .as_deref()
+            .map_or("null".to_string(), json_string);
+        json.push_str(&format!("  \"error\": {},\n", error));
+
+        let cases: Vec<String> = self
+            .cases
+            .iter()
+            .map(|case| {
+                let failures: Vec<String> = case.failures.iter().map(|f| json_string(f)).collect();
+                format!(
+                    "    {{\"name\": {}, \"weight\": {}, \"passed\": {}, \"termination\": {}, \
+                     \"instructions\": {}, \"cycles\": {}, \"failures\": [{}]}}",
+                    json_string(&case.name),
+                    case.weight,
+                    case.passed,
+                    json_string(&case.termination),
+                    case.instructions,
+                    case.cycles,
+                    failures.join(", ")
+                )
+            })
+            .collect();
+        json.push_str(&format!("  \"cases\": {},\n", json_array(&cases)));
+
+        let limits: Vec<String> = self
+            .limits
+            .iter()
+            .map(|result| {
+                format!(
+                    "    {{\"metric\": \"{}\", \"max\": {}, \"actual\": {}, \"weight\": {}, \"met\": {}}}",
+                    result.limit.metric, result.limit.max, result.actual, result.limit.weight, result.met
+                )
+            })
+            .collect();
+        json.push_str(&format!("  \"limits\": {}\n", json_array(&limits)));
+        json.push_str("}\n");
+        json
+    }
+}
+
+fn json_array(items: &[String]) -> String {
+    if items.is_empty() {
+        return "[]".to_string();
+    }
+    format!("[\n{}\n  ]", items.join(",\n"))
+}
+
+fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,309 @@
+use std::fmt;
+
+/// A single operand of an ILOC instruction.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Operand {
+    Register(String),
+    Immediate(i64),
+    /// The immediate of `loadF`, as written in the normalized program.
+    Float(String),
+    Label(String),
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Register(name) => write!(f, "{}", name),
+            Operand::Immediate(value) => write!(f, "{}", value),
+            Operand::Float(text) => write!(f, "{}", text),
+            Operand::Label(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// A structured view of one normalized program line, as produced by
+/// `parser::parse_iloc`.
+///
+/// `sources` are the operands left of the arrow and `targets` the ones right
+/// of it. For stores and branches every target is still read, never written;
+/// use `uses` and `defs` to get the registers an instruction reads and writes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    pub label: Option<String>,
+    pub opcode: String,
+    pub sources: Vec<Operand>,
+    pub targets: Vec<Operand>,
+}
+
+impl Instruction {
+    pub fn new(opcode: &str, sources: Vec<Operand>, targets: Vec<Operand>) -> Self {
+        Self {
+            label: None,
+            opcode: opcode.to_string(),
+            sources,
+            targets,
+        }
+    }
+
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let mut rest = line.trim();
+        let mut label = None;
+
+        // Leading `L1:` style label, either alone on the line or in front of an instruction
+        if let Some(colon) = rest.find(':') {
+            let candidate = &rest[..colon];
+            if is_identifier(candidate) {
+                label = Some(candidate.to_string());
+                rest = rest[colon + 1..].trim();
+            }
+        }
+
+        if rest.starts_with('[') {
+            return Err("Bundles must be split with ir::split_bundle".to_string());
+        }
+
+        if rest.is_empty() {
+            return match label {
+                Some(_) => Ok(Self {
+                    label,
+                    opcode: String::new(),
+                    sources: Vec::new(),
+                    targets: Vec::new(),
+                }),
+                None => Err("Empty instruction".to_string()),
+            };
+        }
+
+        let (opcode, operands) = match rest.find(char::is_whitespace) {
+            Some(pos) => (&rest[..pos], rest[pos..].trim()),
+            None => (rest, ""),
+        };
+
+        let (left, right) = match operands.find("=>").or_else(|| operands.find("->")) {
+            Some(pos) => (&operands[..pos], &operands[pos + 2..]),
+            None => (operands, ""),
+        };
+
+        let branch = is_branch(opcode);
+        let sources = parse_operands(left, false)?;
+        let targets = parse_operands(right, branch)?;
+
+        Ok(Self {
+            label,
+            opcode: opcode.to_string(),
+            sources,
+            targets,
+        })
+    }
+
+    /// Whether this line carries only a label and no instruction.
+    pub fn is_label_only(&self) -> bool {
+        self.opcode.is_empty()
+    }
+
+    /// Registers read by this instruction, in operand order.
+    pub fn uses(&self) -> Vec<&str> {
+        let mut operands: Vec<&Operand> = self.sources.iter().collect();
+        if is_store(&self.opcode) || is_branch(&self.opcode) {
+            operands.extend(self.targets.iter());
+        }
+        operands
+            .into_iter()
+            .filter_map(|operand| match operand {
+                Operand::Register(name) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Registers written by this instruction.
+    pub fn defs(&self) -> Vec<&str> {
+        if is_store(&self.opcode) || is_branch(&self.opcode) {
+            return Vec::new();
+        }
+        self.targets
+            .iter()
+            .filter_map(|operand| match operand {
+                Operand::Register(name) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Renames read registers through `rename_use` and written registers through `rename_def`.
+    pub fn rename_uses_and_defs<U, D>(&mut self, mut rename_use: U, mut rename_def: D)
+    where
+        U: FnMut(&str) -> String,
+        D: FnMut(&str) -> String,
+    {
+        let targets_are_uses = is_store(&self.opcode) || is_branch(&self.opcode);
+        for operand in self.sources.iter_mut() {
+            if let Operand::Register(name) = operand {
+                *name = rename_use(name);
+            }
+        }
+        for operand in self.targets.iter_mut() {
+            if let Operand::Register(name) = operand {
+                *name = if targets_are_uses {
+                    rename_use(name)
+                } else {
+                    rename_def(name)
+                };
+            }
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(label) = &self.label {
+            write!(f, "{}:", label)?;
+            if self.is_label_only() {
+                return Ok(());
+            }
+            write!(f, " ")?;
+        }
+
+        write!(f, "{}", self.opcode)?;
+        if !self.sources.is_empty() {
+            write!(f, " {}", join_operands(&self.sources))?;
+        }
+        if !self.targets.is_empty() {
+            let arrow = if is_branch(&self.opcode) { "->" } else { "=>" };
+            write!(f, " {} {}", arrow, join_operands(&self.targets))?;
+        }
+        Ok(())
+    }
+}
+
+/// Splits a bundle line such as `L1: [ add r1,r2 => r3 ; loadI 4 => r5 ]`
+/// into its label and operations. Returns `None` for lines that are not
+/// bundles.
+pub fn split_bundle(line: &str) -> Option<(Option<&str>, Vec<&str>)> {
+    let mut rest = line.trim();
+    let mut label = None;
+    if let Some(colon) = rest.find(':') {
+        if is_identifier(&rest[..colon]) && rest[colon + 1..].trim_start().starts_with('[') {
+            label = Some(&rest[..colon]);
+            rest = rest[colon + 1..].trim();
+        }
+    }
+
+    let inner = rest.strip_prefix('[')?.strip_suffix(']')?;
+    let operations = inner
+        .split(';')
+        .map(str::trim)
+        .filter(|op| !op.is_empty())
+        .collect();
+    Some((label, operations))
+}
+
+pub fn parse_program(program: &[String]) -> Result<Vec<Instruction>, String> {
+    program
+        .iter()
+        .enumerate()
+        .map(|(idx, line)| {
+            Instruction::parse(line).map_err(|e| format!("Instruction {}: {}", idx + 1, e))
+        })
+        .collect()
+}
+
+pub fn to_program(instructions: &[Instruction]) -> Vec<String> {
+    instructions.iter().map(|inst| inst.to_string()).collect()
+}
+
+pub fn is_register(token: &str) -> bool {
+    token.len() > 1
+        && token.starts_with('r')
+        && token[1..]
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Whether `token` names a float register, `f` followed by digits. Float
+/// registers are a class of their own, separate from the `r` registers.
+pub fn is_float_register(token: &str) -> bool {
+    token.len() > 1 && token.starts_with('f') && token[1..].chars().all(|c| c.is_ascii_digit())
+}
+
+pub fn is_branch(opcode: &str) -> bool {
+    matches!(opcode, "jumpI" | "jump" | "cbr")
+}
+
+pub fn is_load(opcode: &str) -> bool {
+    matches!(
+        opcode,
+        "load"
+            | "loadAI"
+            | "loadAO"
+            | "cload"
+            | "cloadAI"
+            | "cloadAO"
+            | "fload"
+            | "floadAI"
+            | "floadAO"
+    )
+}
+
+pub fn is_store(opcode: &str) -> bool {
+    matches!(
+        opcode,
+        "store"
+            | "storeAI"
+            | "storeAO"
+            | "cstore"
+            | "cstoreAI"
+            | "cstoreAO"
+            | "fstore"
+            | "fstoreAI"
+            | "fstoreAO"
+    )
+}
+
+/// Whether `opcode` prints a value, which orders it against memory writes
+/// and other output.
+pub fn is_output(opcode: &str) -> bool {
+    opcode == "output"
+}
+
+pub(crate) fn is_identifier(token: &str) -> bool {
+    let mut chars = token.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '.' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+        }
+        _ => false,
+    }
+}
+
+fn parse_operands(text: &str, labels: bool) -> Result<Vec<Operand>, String> {
+    text.split(',')
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+        .map(|token| {
+            if labels && !is_register(token) {
+                Ok(Operand::Label(token.to_string()))
+            } else if is_register(token) || is_float_register(token) {
+                Ok(Operand::Register(token.to_string()))
+            } else if let Ok(value) = token.parse::<i64>() {
+                Ok(Operand::Immediate(value))
+            } else if token.starts_with(|c: char| c.is_ascii_digit() || c == '-')
+                && token.parse::<f64>().is_ok()
+            {
+                Ok(Operand::Float(token.to_string()))
+            } else if is_identifier(token) {
+                Ok(Operand::Label(token.to_string()))
+            } else {
+                Err(format!("Invalid operand '{}'", token))
+            }
+        })
+        .collect()
+}
+
+fn join_operands(operands: &[Operand]) -> String {
+    operands
+        .iter()
+        .map(|operand| operand.to_string())
+        .collect::<Vec<String>>()
+        .join(",")
+}
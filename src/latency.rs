@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+
+use crate::ir;
+
+/// Per-opcode instruction latencies in cycles.
+///
+/// The default table follows the textbook model: loads and stores take 3
+/// cycles, multiplies take 2 and everything else takes 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LatencyTable {
+    latencies: HashMap<String, u64>,
+    default: u64,
+}
+
+impl Default for LatencyTable {
+    fn default() -> Self {
+        let mut latencies = HashMap::new();
+        for opcode in [
+            "load", "loadAI", "loadAO", "cload", "cloadAI", "cloadAO", "store", "storeAI",
+            "storeAO", "cstore", "cstoreAI", "cstoreAO", "fload", "floadAI", "floadAO", "fstore",
+            "fstoreAI", "fstoreAO",
+        ] {
+            latencies.insert(opcode.to_string(), 3);
+        }
+        for opcode in ["mult", "multI", "fmult"] {
+            latencies.insert(opcode.to_string(), 2);
+        }
+        Self {
+            latencies,
+            default: 1,
+        }
+    }
+}
+
+impl LatencyTable {
+    /// A table where every instruction takes a single cycle.
+    pub fn uniform() -> Self {
+        Self {
+            latencies: HashMap::new(),
+            default: 1,
+        }
+    }
+
+    /// Parses a latency table. Each non-empty line holds an opcode and its
+    /// latency, optionally separated by `=`; `default` sets the latency of
+    /// opcodes not listed. Entries override the built-in table.
+    ///
+    /// ```text
+    /// # memory is slow on this machine
+    /// load = 5
+    /// mult 3
+    /// default 1
+    /// ```
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut table = Self::default();
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let fields: Vec<&str> = line
+                .split(|c: char| c.is_whitespace() || c == '=')
+                .filter(|field| !field.is_empty())
+                .collect();
+            if fields.len() != 2 {
+                return Err(format!("Line {}: expected '<opcode> <latency>'", idx + 1));
+            }
+
+            let latency: u64 = fields[1]
+                .parse()
+                .map_err(|_| format!("Line {}: invalid latency '{}'", idx + 1, fields[1]))?;
+            if latency == 0 {
+                return Err(format!("Line {}: latency must be at least 1", idx + 1));
+            }
+
+            if fields[0] == "default" {
+                table.default = latency;
+            } else {
+                table.latencies.insert(fields[0].to_string(), latency);
+            }
+        }
+        Ok(table)
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read latency file '{}': {}", path, e))?;
+        Self::parse(&text)
+    }
+
+    pub fn get(&self, opcode: &str) -> u64 {
+        self.latencies.get(opcode).copied().unwrap_or(self.default)
+    }
+
+    pub fn set(&mut self, opcode: &str, latency: u64) {
+        self.latencies.insert(opcode.to_string(), latency);
+    }
+}
+
+/// Cycle counts accumulated by the VM while running a program.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CycleStats {
+    /// Cycle at which the last issued instruction completes.
+    pub cycles: u64,
+    /// Cycles the in-order pipeline spent waiting on operands.
+    pub stalls: u64,
+}
+
+/// Timing model driven by the VM on every executed instruction.
+///
+/// Without a pipeline each instruction occupies the machine for its full
+/// latency. With the in-order pipeline one instruction issues per cycle, but
+/// an instruction whose operands are still being computed stalls until they
+/// are ready. The operations of a bundle issue together in a single cycle.
+#[derive(Debug, Clone, Default)]
+pub struct CycleModel {
+    pub latencies: LatencyTable,
+    pub pipelined: bool,
+    clock: u64,
+    ready: HashMap<String, u64>,
+    stats: CycleStats,
+}
+
+impl CycleModel {
+    pub fn new(latencies: LatencyTable, pipelined: bool) -> Self {
+        Self {
+            latencies,
+            pipelined,
+            ..Self::default()
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.clock = 0;
+        self.ready.clear();
+        self.stats = CycleStats::default();
+    }
+
+    /// Accounts for one executed instruction, or for all operations of a
+    /// bundle, and returns the cycle they issued in.
+    pub fn issue(&mut self, operations: &[ir::Instruction]) -> u64 {
+        // A label on its own line takes no machine time
+        if operations.iter().all(|op| op.is_label_only()) {
+            return self.clock;
+        }
+
+        let latency = |op: &ir::Instruction| self.latencies.get(&op.opcode);
+
+        if !self.pipelined {
+            let issue = self.clock;
+            self.clock += operations.iter().map(latency).max().unwrap_or(1);
+            self.stats.cycles = self.clock;
+            return issue;
+        }
+
+        let operands_ready = operations
+            .iter()
+            .flat_map(|op| op.uses())
+            .filter_map(|reg| self.ready.get(reg))
+            .max()
+            .copied()
+            .unwrap_or(0);
+        let issue = self.clock.max(operands_ready);
+        self.stats.stalls += issue - self.clock;
+
+        for op in operations {
+            let done = issue + latency(op);
+            for reg in op.defs() {
+                self.ready.insert(reg.to_string(), done);
+            }
+            self.stats.cycles = self.stats.cycles.max(done);
+        }
+
+        self.clock = issue + 1;
+        issue
+    }
+
+    /// Cycle in which the next instruction can issue at the earliest.
+    pub fn clock(&self) -> u64 {
+        self.clock
+    }
+
+    pub fn stats(&self) -> CycleStats {
+        self.stats
+    }
+}
@@ -1,2 +1,22 @@
+pub mod cfg;
+pub mod coverage;
+pub mod dialect;
+pub mod diffexec;
+pub mod expect;
+pub mod format;
+pub mod golden;
+pub mod grade;
+pub mod ir;
+pub mod latency;
+pub mod literal;
+pub mod lvn;
+pub mod machine;
+pub mod memory;
+pub mod opt;
 pub mod parser;
+mod preprocess;
+pub mod profile;
+pub mod regalloc;
+pub mod scheduler;
+pub mod ssa;
 pub mod vm;
@@ -0,0 +1,270 @@
+/// Evaluates an integer literal or constant expression such as `0x10`,
+/// `0b1010_0101`, `0o17`, `'A'`, `'\n'` or `4*8+2`.
+///
+/// Underscores may separate digits. Expressions use C precedence over
+/// `| ^ & << >> + - * / %`, unary `- + ~` and parentheses, and are
+/// evaluated in 64 bits; overflow and division by zero are errors.
+pub fn evaluate(text: &str) -> Result<i64, String> {
+    let text = text.trim();
+    let tokens = tokenize(text)?;
+    let mut parser = Parser {
+        text,
+        tokens: &tokens,
+        pos: 0,
+    };
+    let value = parser.expression(0)?;
+    if parser.pos != tokens.len() {
+        return Err(parser.invalid());
+    }
+    Ok(value)
+}
+
+/// Evaluates a float literal such as `1.5`, `-2e-3` or `1_000.25`, or any
+/// integer `evaluate` accepts. Infinities and NaN are not literals.
+pub fn evaluate_float(text: &str) -> Result<f64, String> {
+    let text = text.trim();
+    let digits = text.replace('_', "");
+    let is_float = digits
+        .trim_start_matches(['-', '+'])
+        .starts_with(|c: char| c.is_ascii_digit() || c == '.')
+        && digits.contains(['.', 'e', 'E'])
+        && !digits.to_ascii_lowercase().starts_with("0x");
+    if !is_float {
+        return evaluate(text).map(|value| value as f64);
+    }
+    match digits.parse::<f64>() {
+        Ok(value) if value.is_finite() => Ok(value),
+        Ok(_) => Err(format!("Value {} is out of range", text)),
+        Err(_) => Err(format!("Invalid number '{}'", text)),
+    }
+}
+
+/// Whether `text` is a literal or expression rather than a register or a
+/// label, so that it should be evaluated.
+pub fn is_literal(text: &str) -> bool {
+    text.trim()
+        .starts_with(|c: char| c.is_ascii_digit() || "'-+~(".contains(c))
+}
+
+/// `value` as a 32-bit word. Values up to `u32::MAX` wrap to negative
+/// words, so that `0xffffffff` is -1.
+pub fn to_word(value: i64) -> Result<i32, String> {
+    i32::try_from(value)
+        .or_else(|_| u32::try_from(value).map(|word| word as i32))
+        .map_err(|_| format!("Value {} does not fit in a word", value))
+}
+
+/// Replaces every character literal in `code` by its value, leaving string
+/// literals alone, so that `' '` and `','` survive splitting on whitespace
+/// and commas.
+pub fn replace_chars(code: &str) -> Result<String, String> {
+    let mut result = String::new();
+    let mut in_string = false;
+    let mut chars = code.char_indices().peekable();
+
+    while let Some((idx, c)) = chars.next() {
+        if in_string {
+            result.push(c);
+            match c {
+                '\\' => result.extend(chars.next().map(|(_, c)| c)),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => {
+                in_string = true;
+                result.push(c);
+            }
+            '\'' => {
+                let (value, len) = char_literal(&code[idx..])?;
+                for _ in 1..len {
+                    chars.next();
+                }
+                result.push_str(&value.to_string());
+            }
+            _ => result.push(c),
+        }
+    }
+    Ok(result)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Number(i64),
+    Op(&'static str),
+    Open,
+    Close,
+}
+
+const OPERATORS: [&str; 11] = ["<<", ">>", "|", "^", "&", "+", "-", "*", "/", "%", "~"];
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = text;
+
+    while let Some(c) = rest.chars().next() {
+        if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+        } else if c == '(' || c == ')' {
+            tokens.push(if c == '(' { Token::Open } else { Token::Close });
+            rest = &rest[1..];
+        } else if c == '\'' {
+            let (value, len) = char_literal(rest)?;
+            tokens.push(Token::Number(value));
+            rest = &rest[len..];
+        } else if c.is_ascii_alphanumeric() || c == '_' || c == '.' {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Number(integer(&rest[..len])?));
+            rest = &rest[len..];
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        } else {
+            return Err(format!("Invalid expression '{}'", text));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Parses a decimal, `0x`, `0b` or `0o` integer with optional underscores
+/// between digits.
+fn integer(token: &str) -> Result<i64, String> {
+    let invalid = || format!("Invalid number '{}'", token);
+    let lower = token.to_ascii_lowercase();
+    let (radix, digits) = match lower.get(..2) {
+        Some("0x") => (16, &token[2..]),
+        Some("0b") => (2, &token[2..]),
+        Some("0o") => (8, &token[2..]),
+        _ => (10, token),
+    };
+    if digits.is_empty()
+        || digits.starts_with('_')
+        || digits.ends_with('_')
+        || digits.contains("__")
+        || !digits.chars().all(|c| c.is_digit(radix) || c == '_')
+    {
+        return Err(invalid());
+    }
+    i64::from_str_radix(&digits.replace('_', ""), radix)
+        .map_err(|_| format!("Number '{}' is out of range", token))
+}
+
+/// The value of the character literal `text` starts with, and its length
+/// in bytes.
+fn char_literal(text: &str) -> Result<(i64, usize), String> {
+    let mut chars = text.char_indices().skip(1);
+    let value = match chars.next() {
+        Some((_, '\\')) => match chars.next() {
+            Some((_, 'n')) => '\n',
+            Some((_, 't')) => '\t',
+            Some((_, 'r')) => '\r',
+            Some((_, '0')) => '\0',
+            Some((_, '\\')) => '\\',
+            Some((_, '\'')) => '\'',
+            Some((_, '"')) => '"',
+            other => {
+                let c = other.map_or(' ', |(_, c)| c);
+                return Err(format!("Invalid escape '\\{}'", c));
+            }
+        },
+        Some((_, c)) if c != '\'' => c,
+        _ => return Err(format!("Invalid character literal '{}'", text)),
+    };
+    match chars.next() {
+        Some((end, '\'')) if value.is_ascii() => Ok((value as i64, end + 1)),
+        Some((end, '\'')) => Err(format!("Character '{}' is not ASCII", &text[..end + 1])),
+        _ => Err(format!("Unterminated character literal '{}'", text)),
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+/// Binary operators from loosest to tightest binding.
+const LEVELS: [&[&str]; 6] = [
+    &["|"],
+    &["^"],
+    &["&"],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+impl Parser<'_> {
+    fn invalid(&self) -> String {
+        format!("Invalid expression '{}'", self.text)
+    }
+
+    fn overflow(&self) -> String {
+        format!("Expression '{}' overflows", self.text)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).copied();
+        self.pos += 1;
+        token
+    }
+
+    fn expression(&mut self, level: usize) -> Result<i64, String> {
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut value = self.expression(level + 1)?;
+        while let Some(Token::Op(op)) = self.tokens.get(self.pos).copied() {
+            if !LEVELS[level].contains(&op) {
+                break;
+            }
+            self.pos += 1;
+            let right = self.expression(level + 1)?;
+            value = self.apply(op, value, right)?;
+        }
+        Ok(value)
+    }
+
+    fn apply(&self, op: &str, left: i64, right: i64) -> Result<i64, String> {
+        let shift = || {
+            u32::try_from(right)
+                .ok()
+                .filter(|&amount| amount < 64)
+                .ok_or_else(|| format!("Invalid shift by {} in '{}'", right, self.text))
+        };
+        let value = match op {
+            "|" => Some(left | right),
+            "^" => Some(left ^ right),
+            "&" => Some(left & right),
+            "<<" => left.checked_shl(shift()?),
+            ">>" => left.checked_shr(shift()?),
+            "+" => left.checked_add(right),
+            "-" => left.checked_sub(right),
+            "*" => left.checked_mul(right),
+            "/" | "%" if right == 0 => return Err(format!("Division by zero in '{}'", self.text)),
+            "/" => left.checked_div(right),
+            _ => left.checked_rem(right),
+        };
+        value.ok_or_else(|| self.overflow())
+    }
+
+    fn unary(&mut self) -> Result<i64, String> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(value),
+            Some(Token::Op("-")) => self.unary()?.checked_neg().ok_or_else(|| self.overflow()),
+            Some(Token::Op("+")) => self.unary(),
+            Some(Token::Op("~")) => Ok(!self.unary()?),
+            Some(Token::Open) => {
+                let value = self.expression(0)?;
+                match self.next() {
+                    Some(Token::Close) => Ok(value),
+                    _ => Err(self.invalid()),
+                }
+            }
+            _ => Err(self.invalid()),
+        }
+    }
+}
@@ -0,0 +1,304 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::cfg::Cfg;
+use crate::ir::{self, Instruction, Operand};
+use crate::opt::{self, Pass};
+
+/// Value numbering over basic blocks. Recomputing a value that a register
+/// already holds is rewritten into an `i2i` copy from that register.
+///
+/// Immediate forms share value numbers with their register forms, so
+/// `addI r1, 4` matches `add r1, r2` once `r2` holds `4`, and the operands of
+/// commutative operations are ordered canonically. With `extended` set the
+/// table carries over from a block into successors that have no other
+/// predecessor (superlocal value numbering over extended basic blocks).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ValueNumbering {
+    pub extended: bool,
+}
+
+impl Pass for ValueNumbering {
+    fn name(&self) -> &'static str {
+        if self.extended {
+            "svn"
+        } else {
+            "lvn"
+        }
+    }
+
+    fn run(&self, instructions: &mut Vec<Instruction>) -> bool {
+        let (rewritten, _) = self.number(instructions);
+        let changed = rewritten != *instructions;
+        *instructions = rewritten;
+        changed
+    }
+}
+
+impl ValueNumbering {
+    /// The value-number table as it stands at the end of every block,
+    /// without rewriting anything.
+    pub fn tables(&self, instructions: &[Instruction]) -> Vec<ValueTable> {
+        self.number(instructions).1
+    }
+
+    fn number(&self, instructions: &[Instruction]) -> (Vec<Instruction>, Vec<ValueTable>) {
+        let cfg = Cfg::build(instructions);
+        let mut body = instructions.to_vec();
+        let mut tables: Vec<Option<ValueTable>> = vec![None; cfg.blocks.len()];
+        let mut next_value = 0;
+
+        let is_root = |b: usize| {
+            !self.extended
+                || b == 0
+                || cfg.blocks[b].predecessors.len() != 1
+                || cfg.blocks[b].predecessors[0] == b
+        };
+
+        // Walk every extended block from its root, handing each child a copy
+        // of the table its single predecessor ended with
+        let mut worklist: Vec<(usize, Table)> = Vec::new();
+        for root in (0..cfg.blocks.len()).filter(|&b| is_root(b)) {
+            worklist.push((root, Table::default()));
+            while let Some((b, mut table)) = worklist.pop() {
+                if tables[b].is_some() {
+                    continue;
+                }
+                for idx in cfg.blocks[b].range() {
+                    table.number(&mut body[idx], &mut next_value);
+                }
+                tables[b] = Some(table.snapshot(b));
+                for &succ in cfg.blocks[b].successors.iter().rev() {
+                    if !is_root(succ) {
+                        worklist.push((succ, table.clone()));
+                    }
+                }
+            }
+        }
+
+        // Blocks only reachable through cycles of single-predecessor blocks
+        for (b, slot) in tables.iter_mut().enumerate() {
+            if slot.is_none() {
+                let mut table = Table::default();
+                for idx in cfg.blocks[b].range() {
+                    table.number(&mut body[idx], &mut next_value);
+                }
+                *slot = Some(table.snapshot(b));
+            }
+        }
+
+        // Drop the copies that ended up copying a register onto itself
+        let mut idx = 0;
+        while idx < body.len() {
+            let inst = &body[idx];
+            if inst.opcode == "i2i"
+                && opt::register(inst.sources.first()) == opt::single_target(inst)
+            {
+                // A label left behind is no longer an i2i, so this terminates
+                opt::remove(&mut body, idx);
+                continue;
+            }
+            idx += 1;
+        }
+
+        (body, tables.into_iter().map(Option::unwrap).collect())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Table {
+    registers: HashMap<String, usize>,
+    expressions: HashMap<String, usize>,
+    descriptions: HashMap<usize, String>,
+}
+
+impl Table {
+    fn fresh(&mut self, next_value: &mut usize, description: String) -> usize {
+        let value = *next_value;
+        *next_value += 1;
+        self.descriptions.insert(value, description);
+        value
+    }
+
+    fn register(&mut self, reg: &str, next_value: &mut usize) -> usize {
+        if let Some(&value) = self.registers.get(reg) {
+            return value;
+        }
+        let value = self.fresh(next_value, reg.to_string());
+        self.registers.insert(reg.to_string(), value);
+        value
+    }
+
+    fn expression(&mut self, key: String, next_value: &mut usize) -> (usize, bool) {
+        if let Some(&value) = self.expressions.get(&key) {
+            return (value, true);
+        }
+        let value = self.fresh(next_value, key.clone());
+        self.expressions.insert(key, value);
+        (value, false)
+    }
+
+    fn constant(&mut self, value: i64, next_value: &mut usize) -> usize {
+        self.expression(format!("#{}", value), next_value).0
+    }
+
+    /// A register currently holding `value`, preferring `target` itself.
+    fn holder(&self, value: usize, target: &str) -> Option<String> {
+        if self.registers.get(target) == Some(&value) {
+            return Some(target.to_string());
+        }
+        let mut holders: Vec<&String> = self
+            .registers
+            .iter()
+            .filter(|(_, &v)| v == value)
+            .map(|(reg, _)| reg)
+            .collect();
+        holders.sort();
+        holders.first().map(|reg| reg.to_string())
+    }
+
+    fn number(&mut self, inst: &mut Instruction, next_value: &mut usize) {
+        let target = opt::single_target(inst).map(str::to_string);
+        let numbered = match (&target, self.key(inst, next_value)) {
+            (Some(target), Some(key)) => Some((target.clone(), key)),
+            _ => None,
+        };
+
+        match numbered {
+            Some((target, Key::Copy(value))) => {
+                self.registers.insert(target, value);
+            }
+            Some((target, Key::Expression(key))) => {
+                let (value, seen) = self.expression(key, next_value);
+                if seen {
+                    if let Some(holder) = self.holder(value, &target) {
+                        opt::replace(inst, opt::copy(&holder, &target));
+                    }
+                }
+                self.registers.insert(target, value);
+            }
+            None => {
+                for reg in inst.defs() {
+                    let value = self.fresh(next_value, format!("{} ({})", reg, inst.opcode));
+                    self.registers.insert(reg.to_string(), value);
+                }
+            }
+        }
+    }
+
+    fn key(&mut self, inst: &Instruction, next_value: &mut usize) -> Option<Key> {
+        let opcode = inst.opcode.as_str();
+        if inst.is_label_only()
+            || ir::is_load(opcode)
+            || ir::is_store(opcode)
+            || ir::is_branch(opcode)
+        {
+            return None;
+        }
+
+        if opcode == "loadI" {
+            return match inst.sources.first() {
+                Some(Operand::Immediate(value)) => {
+                    Some(Key::Copy(self.constant(*value, next_value)))
+                }
+                _ => None,
+            };
+        }
+        if opcode == "i2i" {
+            let source = opt::register(inst.sources.first())?;
+            return Some(Key::Copy(self.register(source, next_value)));
+        }
+
+        let mut operands = Vec::new();
+        for operand in &inst.sources {
+            operands.push(match operand {
+                Operand::Register(reg) => self.register(reg, next_value),
+                Operand::Immediate(value) => self.constant(*value, next_value),
+                Operand::Float(_) | Operand::Label(_) => return None,
+            });
+        }
+
+        let opcode = match opt::register_form(opcode) {
+            Some((base, reversed)) => {
+                if reversed {
+                    operands.reverse();
+                }
+                base
+            }
+            None => opcode,
+        };
+        if opt::is_commutative(opcode) {
+            operands.sort_unstable();
+        }
+
+        let operands: Vec<String> = operands.iter().map(|value| format!("v{}", value)).collect();
+        Some(Key::Expression(format!(
+            "{} {}",
+            opcode,
+            operands.join(",")
+        )))
+    }
+
+    fn snapshot(&self, block: usize) -> ValueTable {
+        let mut rows: Vec<ValueRow> = self
+            .descriptions
+            .iter()
+            .map(|(&value, description)| {
+                let mut registers: Vec<String> = self
+                    .registers
+                    .iter()
+                    .filter(|(_, &v)| v == value)
+                    .map(|(reg, _)| reg.clone())
+                    .collect();
+                registers.sort();
+                ValueRow {
+                    value,
+                    expression: description.clone(),
+                    registers,
+                }
+            })
+            .collect();
+        rows.sort_by_key(|row| row.value);
+        ValueTable { block, rows }
+    }
+}
+
+enum Key {
+    /// The target receives an existing value unchanged.
+    Copy(usize),
+    /// The target receives the value of an expression over value numbers.
+    Expression(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValueRow {
+    pub value: usize,
+    /// `#4` for constants, a register name for values live on entry, or the
+    /// operation over value numbers such as `add v0,v1`.
+    pub expression: String,
+    /// Registers holding the value at the end of the block.
+    pub registers: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValueTable {
+    pub block: usize,
+    pub rows: Vec<ValueRow>,
+}
+
+impl fmt::Display for ValueTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Block {}:", self.block)?;
+        writeln!(f, "    {:<6}{:<24}registers", "value", "expression")?;
+        for row in &self.rows {
+            let line = format!(
+                "    {:<6}{:<24}{}",
+                format!("v{}", row.value),
+                row.expression,
+                row.registers.join(", ")
+            );
+            writeln!(f, "{}", line.trim_end())?;
+        }
+        Ok(())
+    }
+}
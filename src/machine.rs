@@ -0,0 +1,242 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::literal;
+
+/// Width of the integer registers and of a word in memory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum WordSize {
+    #[default]
+    W32,
+    W64,
+}
+
+/// Order of the bytes of a word or float in memory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Endianness {
+    #[default]
+    Little,
+    Big,
+}
+
+/// What arithmetic does with a result that does not fit in a word.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum OverflowPolicy {
+    /// Keep the low bits, two's complement.
+    #[default]
+    Wrap,
+    /// Fault with `Fault::Overflow`.
+    Trap,
+    /// Clamp to the most negative or most positive word.
+    Saturate,
+}
+
+/// What shifts do with an amount outside `0..bits`, where `bits` is the
+/// word size. Shifts are bit operations, so the overflow policy does not
+/// apply to the bits they shift out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ShiftPolicy {
+    /// Use the amount modulo `bits`, as hardware shifters do.
+    #[default]
+    Mask,
+    /// Fault with `Fault::ShiftOutOfRange`.
+    Trap,
+    /// Shift every bit out: left and logical right shifts give 0, and
+    /// arithmetic right shifts give 0 or -1 by the sign.
+    ZeroFill,
+}
+
+/// The machine a program is assembled for and runs on. The default is the
+/// classic ILOC machine: 32-bit little-endian words.
+///
+/// The parser lays out `.word` data and checks immediates for a machine,
+/// and the VM must be configured for the same one to run the result.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct MachineConfig {
+    pub word_size: WordSize,
+    pub endianness: Endianness,
+}
+
+impl WordSize {
+    /// Bytes a word takes in memory.
+    pub fn bytes(&self) -> usize {
+        match self {
+            WordSize::W32 => 4,
+            WordSize::W64 => 8,
+        }
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.bytes() as u32 * 8
+    }
+
+    /// The most negative word.
+    pub fn min(&self) -> i64 {
+        self.wrap(1 << (self.bits() - 1))
+    }
+
+    /// The most positive word.
+    pub fn max(&self) -> i64 {
+        !self.min()
+    }
+
+    /// The word `value` read as unsigned, for logical shifts.
+    pub fn unsigned(&self, value: i64) -> u64 {
+        match self {
+            WordSize::W32 => value as u32 as u64,
+            WordSize::W64 => value as u64,
+        }
+    }
+
+    /// `value` wrapped to a word, two's complement, and sign-extended back.
+    pub fn wrap(&self, value: i64) -> i64 {
+        match self {
+            WordSize::W32 => value as i32 as i64,
+            WordSize::W64 => value,
+        }
+    }
+
+    /// `value` as an immediate of this size. A 32-bit word also accepts
+    /// values up to `u32::MAX`, which wrap to negative words.
+    pub fn immediate(&self, value: i64) -> Result<i64, String> {
+        match self {
+            WordSize::W32 => literal::to_word(value).map(i64::from),
+            WordSize::W64 => Ok(value),
+        }
+    }
+
+    /// `value` truncated toward zero to a word, saturating at the word's
+    /// limits; NaN converts to 0.
+    pub fn from_float(&self, value: f64) -> i64 {
+        match self {
+            WordSize::W32 => value as i32 as i64,
+            WordSize::W64 => value as i64,
+        }
+    }
+}
+
+impl Endianness {
+    /// Puts little-endian `bytes` in this order.
+    pub fn order(&self, mut bytes: Vec<u8>) -> Vec<u8> {
+        if *self == Endianness::Big {
+            bytes.reverse();
+        }
+        bytes
+    }
+}
+
+impl MachineConfig {
+    /// The bytes of the word `value` in memory.
+    pub fn encode(&self, value: i64) -> Vec<u8> {
+        let bytes = value.to_le_bytes()[..self.word_size.bytes()].to_vec();
+        self.endianness.order(bytes)
+    }
+
+    /// The word stored in `bytes`, which hold exactly one word.
+    pub fn decode(&self, bytes: &[u8]) -> i64 {
+        let little = self.endianness.order(bytes.to_vec());
+        // Sign-extend from the top byte
+        let fill = if little.last().is_some_and(|byte| byte & 0x80 != 0) {
+            0xff
+        } else {
+            0
+        };
+        let mut word = [fill; 8];
+        word[..little.len()].copy_from_slice(&little);
+        i64::from_le_bytes(word)
+    }
+}
+
+impl fmt::Display for WordSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.bits())
+    }
+}
+
+impl FromStr for WordSize {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        match text {
+            "32" => Ok(WordSize::W32),
+            "64" => Ok(WordSize::W64),
+            _ => Err(format!("Unknown word size '{}', expected 32 or 64", text)),
+        }
+    }
+}
+
+impl fmt::Display for Endianness {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Endianness::Little => write!(f, "little"),
+            Endianness::Big => write!(f, "big"),
+        }
+    }
+}
+
+impl FromStr for Endianness {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        match text {
+            "little" => Ok(Endianness::Little),
+            "big" => Ok(Endianness::Big),
+            _ => Err(format!(
+                "Unknown endianness '{}', expected little or big",
+                text
+            )),
+        }
+    }
+}
+
+impl fmt::Display for OverflowPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OverflowPolicy::Wrap => write!(f, "wrap"),
+            OverflowPolicy::Trap => write!(f, "trap"),
+            OverflowPolicy::Saturate => write!(f, "saturate"),
+        }
+    }
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        match text {
+            "wrap" => Ok(OverflowPolicy::Wrap),
+            "trap" => Ok(OverflowPolicy::Trap),
+            "saturate" => Ok(OverflowPolicy::Saturate),
+            _ => Err(format!(
+                "Unknown overflow policy '{}', expected wrap, trap or saturate",
+                text
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ShiftPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShiftPolicy::Mask => write!(f, "mask"),
+            ShiftPolicy::Trap => write!(f, "trap"),
+            ShiftPolicy::ZeroFill => write!(f, "zero-fill"),
+        }
+    }
+}
+
+impl FromStr for ShiftPolicy {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        match text {
+            "mask" => Ok(ShiftPolicy::Mask),
+            "trap" => Ok(ShiftPolicy::Trap),
+            "zero-fill" => Ok(ShiftPolicy::ZeroFill),
+            _ => Err(format!(
+                "Unknown shift policy '{}', expected mask, trap or zero-fill",
+                text
+            )),
+        }
+    }
+}
@@ -1,17 +1,686 @@
-mod parser;
+// The TUI predates the clippy gate and keeps its original style
+#[allow(
+    clippy::unit_arg,
+    clippy::unnecessary_sort_by,
+    clippy::unnecessary_cast
+)]
 mod tui;
-mod vm;
 
+use iloc::coverage::{self, Coverage};
+use iloc::dialect::{self, Dialect};
+use iloc::diffexec::{diff_exec_programs, DiffConfig};
+use iloc::latency::{CycleModel, LatencyTable};
+use iloc::lvn::ValueNumbering;
+use iloc::machine::{MachineConfig, OverflowPolicy, ShiftPolicy};
+use iloc::memory::{DenseMemory, Memory, MemoryMap, PagedMemory, ADDRESS_SPACE};
+use iloc::{expect, format, golden, grade, ir, opt, parser, profile, ssa, vm};
 use std::sync::{Arc, Mutex};
 use tui::run_tui;
 
+const USAGE: &str = "Usage:
+    iloc_emulator [program.iloc] [--latencies FILE] [--pipeline] [--units N]
+        [--dialect eac2|eac3|lenient] [--f64] [--word 32|64] [--endian little|big]
+        [--overflow wrap|trap|saturate] [--shift mask|trap|zero-fill]
+        [--layout static=N,code=N,stack=N] [--align] [--sparse]
+    iloc_emulator opt <program.iloc> [--passes fold,lvn,svn,copy,dce,strength,peephole]
+        [--live-out r1,r2] [--verbose]
+    iloc_emulator ssa <program.iloc> [--out-of-ssa]
+    iloc_emulator diff-exec <a.iloc> <b.iloc> [--registers r1,r2] [--set r1=5]
+        [--seed N] [--trials N] [--max-steps N]
+    iloc_emulator test <program.iloc | directory>... [--bless] [--jobs N] [--max-steps N]
+        [--timeout MS] [--detect-loops]
+    iloc_emulator profile <program.iloc> [--latencies FILE] [--pipeline] [--max-steps N]
+    iloc_emulator coverage <program.iloc | directory>... [--lcov FILE] [--html FILE]
+        [--max-steps N]
+    iloc_emulator grade <rubric> <submission.iloc | directory>... [--out DIR]
+    iloc_emulator fmt <program.iloc>... [--write | --check]
+    iloc_emulator translate <program.iloc> --to eac2|eac3|lenient [--from DIALECT]";
+
+/// Bytes of dense memory the interactive VM runs with.
+const MEMORY_SIZE: usize = 1024;
+
 fn main() {
-    let program = std::fs::read_to_string("program.iloc").expect("Failed to read program file");
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("opt") => opt_command(&args[1..]),
+        Some("ssa") => ssa_command(&args[1..]),
+        Some("diff-exec") => diff_exec_command(&args[1..]),
+        Some("test") => test_command(&args[1..]),
+        Some("profile") => profile_command(&args[1..]),
+        Some("coverage") => coverage_command(&args[1..]),
+        Some("grade") => grade_command(&args[1..]),
+        Some("fmt") => fmt_command(&args[1..]),
+        Some("translate") => translate_command(&args[1..]),
+        _ => run_command(&args),
+    }
+}
+
+fn run_command(args: &[String]) {
+    let mut path = "program.iloc".to_string();
+    let mut latencies = LatencyTable::default();
+    let mut pipelined = false;
+    let mut units = None;
+    let mut dialect = Dialect::default();
+    let mut float_width = vm::FloatWidth::default();
+    let mut machine = MachineConfig::default();
+    let mut overflow = OverflowPolicy::default();
+    let mut shift = ShiftPolicy::default();
+    let mut layout = None;
+    let mut align = false;
+    let mut sparse = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--latencies" => {
+                let file = args.next().unwrap_or_else(|| exit_with(USAGE));
+                latencies = LatencyTable::load(file).unwrap_or_else(|e| exit_with(&e));
+            }
+            "--pipeline" => pipelined = true,
+            "--f64" => float_width = vm::FloatWidth::F64,
+            "--units" => {
+                let count = args.next().unwrap_or_else(|| exit_with(USAGE));
+                units = Some(count.parse().unwrap_or_else(|_| exit_with(USAGE)));
+            }
+            "--dialect" => {
+                let name = args.next().unwrap_or_else(|| exit_with(USAGE));
+                dialect = name.parse().unwrap_or_else(|e: String| exit_with(&e));
+            }
+            "--word" => {
+                let size = args.next().unwrap_or_else(|| exit_with(USAGE));
+                machine.word_size = size.parse().unwrap_or_else(|e: String| exit_with(&e));
+            }
+            "--endian" => {
+                let order = args.next().unwrap_or_else(|| exit_with(USAGE));
+                machine.endianness = order.parse().unwrap_or_else(|e: String| exit_with(&e));
+            }
+            "--overflow" => {
+                let policy = args.next().unwrap_or_else(|| exit_with(USAGE));
+                overflow = policy.parse().unwrap_or_else(|e: String| exit_with(&e));
+            }
+            "--shift" => {
+                let policy = args.next().unwrap_or_else(|| exit_with(USAGE));
+                shift = policy.parse().unwrap_or_else(|e: String| exit_with(&e));
+            }
+            "--layout" => {
+                let spec = args.next().unwrap_or_else(|| exit_with(USAGE));
+                layout = Some(parse_layout(spec).unwrap_or_else(|e| exit_with(&e)));
+            }
+            "--align" => align = true,
+            "--sparse" => sparse = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if arg.starts_with('-') => exit_with(USAGE),
+            _ => path = arg.clone(),
+        }
+    }
+
+    let source = std::fs::read_to_string(&path).expect("Failed to read program file");
+    let program = parser::parse_program_for(&source, &path, dialect, machine)
+        .unwrap_or_else(|e| exit_with(&format!("{}: {}", path, e)));
+
+    // Sparse memory spans the whole 32-bit address space
+    let memory_size = match sparse {
+        true => usize::try_from(ADDRESS_SPACE)
+            .unwrap_or_else(|_| exit_with("--sparse needs a 64-bit host")),
+        false => MEMORY_SIZE,
+    };
+    let memory: Box<dyn Memory> = match sparse {
+        true => Box::new(PagedMemory::new(memory_size)),
+        false => Box::new(DenseMemory::new(memory_size)),
+    };
+    let vm = Arc::new(Mutex::new(vm::VM::with_memory(memory)));
+    {
+        let mut vm = vm.lock().unwrap();
+        vm.set_machine_config(machine);
+        vm.load(&program).unwrap_or_else(|e| exit_with(&e));
+        vm.set_float_width(float_width);
+        vm.set_overflow_policy(overflow);
+        vm.set_shift_policy(shift);
+        let mut memory_map = match layout {
+            // The static region defaults to the data section
+            Some([static_size, code, stack]) => MemoryMap::standard(
+                memory_size,
+                static_size.unwrap_or(program.data.bytes.len()),
+                code.unwrap_or(0),
+                stack.unwrap_or(0),
+            )
+            .unwrap_or_else(|e| exit_with(&e)),
+            None => MemoryMap::flat(memory_size),
+        };
+        memory_map.check_alignment = align;
+        vm.set_memory_map(memory_map)
+            .unwrap_or_else(|e| exit_with(&e));
+        vm.set_cycle_model(CycleModel::new(latencies, pipelined));
+        if let Some(units) = units {
+            vm.set_functional_units(units);
+        }
+    }
+
+    run_tui(vm, &path, &source, &program.code).unwrap();
+}
+
+/// Parses `static=N,code=N,stack=N`, any of which may be left out, into
+/// the sizes of those regions.
+fn parse_layout(spec: &str) -> Result<[Option<usize>; 3], String> {
+    let mut sizes = [None; 3];
+    for part in spec.split(',') {
+        let (name, size) = part
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid region size '{}'", part))?;
+        let size = size
+            .parse()
+            .map_err(|_| format!("Invalid region size '{}'", part))?;
+        match name {
+            "static" => sizes[0] = Some(size),
+            "code" => sizes[1] = Some(size),
+            "stack" => sizes[2] = Some(size),
+            _ => return Err(format!("Unknown region '{}'", name)),
+        }
+    }
+    Ok(sizes)
+}
+
+fn opt_command(args: &[String]) {
+    let mut path = None;
+    let mut pass_names = None;
+    let mut live_out = None;
+    let mut verbose = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--passes" => pass_names = Some(args.next().unwrap_or_else(|| exit_with(USAGE))),
+            "--live-out" => live_out = Some(args.next().unwrap_or_else(|| exit_with(USAGE))),
+            "--verbose" => verbose = true,
+            _ if arg.starts_with('-') => exit_with(USAGE),
+            _ => path = Some(arg.clone()),
+        }
+    }
+
+    let path = path.unwrap_or_else(|| exit_with(USAGE));
+    let mut passes = match pass_names {
+        Some(names) => opt::parse_passes(names).unwrap_or_else(|e| exit_with(&e)),
+        None => opt::default_passes(),
+    };
+    if let Some(registers) = live_out {
+        // Only memory and the listed registers are the program's result
+        let registers: Vec<&str> = registers.split(',').filter(|r| !r.is_empty()).collect();
+        for pass in passes.iter_mut() {
+            if pass.name() == "dce" {
+                *pass = Box::new(opt::DeadCodeElimination::keeping(&registers));
+            }
+        }
+    }
+    let source = std::fs::read_to_string(&path).unwrap_or_else(|e| exit_with(&e.to_string()));
+    let program = parser::parse_program(&source, &path).unwrap_or_else(|e| exit_with(&e));
+    let before = program.text();
+    let after = opt::optimize(&before, &passes).unwrap_or_else(|e| exit_with(&e));
+
+    println!("Before:");
+    for line in &before {
+        println!("    {}", line);
+    }
+    println!();
+    if verbose {
+        // Value numbers as superlocal numbering sees the input program
+        let instructions = ir::parse_program(&before).unwrap_or_else(|e| exit_with(&e));
+        let numbering = ValueNumbering { extended: true };
+        println!("Value numbers:");
+        for table in numbering.tables(&instructions) {
+            print!("{}", table);
+        }
+        println!();
+    }
+    println!("After:");
+    for line in &after {
+        println!("    {}", line);
+    }
+    println!();
+
+    // Instructions executed, and how the run ended if it did not finish
+    let data = &program.data;
+    let executed = |program: &[String]| {
+        let mut vm = vm::VM::new(1024);
+        vm.load_program(program.to_vec());
+        vm.write_memory(0, &data.bytes);
+        let limits = vm::Limits {
+            max_steps: Some(expect::MAX_STEPS),
+            ..vm::Limits::default()
+        };
+        let termination = match vm.run_catching(&limits) {
+            Ok(vm::Termination::Finished) => None,
+            Ok(termination) => Some(termination.to_string()),
+            Err(message) => Some(format!("crashed: {}", message)),
+        };
+        (vm.get_instruction_count(), termination)
+    };
+    println!(
+        "Static instructions:   {} -> {} ({:+})",
+        before.len(),
+        after.len(),
+        after.len() as i64 - before.len() as i64
+    );
+    let (executed_before, ended_before) = executed(&before);
+    let (executed_after, ended_after) = executed(&after);
+    println!(
+        "Executed instructions: {} -> {} ({:+})",
+        executed_before,
+        executed_after,
+        executed_after as i64 - executed_before as i64
+    );
+    for (name, ended) in [("Before", ended_before), ("After", ended_after)] {
+        if let Some(ended) = ended {
+            println!("{} run did not finish: {}", name, ended);
+        }
+    }
+}
+
+fn ssa_command(args: &[String]) {
+    let mut path = None;
+    let mut out_of_ssa = false;
+    for arg in args {
+        match arg.as_str() {
+            "--out-of-ssa" => out_of_ssa = true,
+            _ if arg.starts_with('-') => exit_with(USAGE),
+            _ => path = Some(arg.clone()),
+        }
+    }
+
+    let path = path.unwrap_or_else(|| exit_with(USAGE));
+    let source = std::fs::read_to_string(&path).unwrap_or_else(|e| exit_with(&e.to_string()));
+    let program = parser::parse_program(&source, &path).unwrap_or_else(|e| exit_with(&e));
+    let mut result = ssa::to_ssa(&program.text()).unwrap_or_else(|e| exit_with(&e));
+    if out_of_ssa {
+        result = ssa::from_ssa(&result).unwrap_or_else(|e| exit_with(&e));
+    }
+    // The data section comes first, as the canonical formatter writes it
+    let data = parser::Program {
+        code: Vec::new(),
+        data: program.data,
+    };
+    print!("{}", data);
+    for line in result {
+        println!("{}", line);
+    }
+}
+
+fn diff_exec_command(args: &[String]) {
+    let mut paths = Vec::new();
+    let mut config = DiffConfig::default();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| exit_with(USAGE));
+        match arg.as_str() {
+            "--registers" => {
+                config.result_registers = value()
+                    .split(',')
+                    .filter(|r| !r.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            }
+            "--set" => {
+                let (reg, number) = value().split_once('=').unwrap_or_else(|| exit_with(USAGE));
+                let number = number.parse().unwrap_or_else(|_| exit_with(USAGE));
+                config.inputs.insert(reg.to_string(), number);
+            }
+            "--seed" => config.seed = Some(value().parse().unwrap_or_else(|_| exit_with(USAGE))),
+            "--trials" => config.trials = value().parse().unwrap_or_else(|_| exit_with(USAGE)),
+            "--max-steps" => {
+                config.max_steps = value().parse().unwrap_or_else(|_| exit_with(USAGE))
+            }
+            _ if arg.starts_with('-') => exit_with(USAGE),
+            _ => paths.push(arg.clone()),
+        }
+    }
+
+    let [left, right] = paths.as_slice() else {
+        exit_with(USAGE)
+    };
+    let load = |path: &String| {
+        let source = std::fs::read_to_string(path).unwrap_or_else(|e| exit_with(&e.to_string()));
+        parser::parse_program(&source, path)
+            .unwrap_or_else(|e| exit_with(&format!("{}: {}", path, e)))
+    };
+    let report = diff_exec_programs(&load(left), &load(right), &config);
+
+    match report.divergence {
+        None => println!("No divergence in {} trial(s)", report.agreeing_trials),
+        Some((seed, divergence)) => {
+            match seed {
+                Some(seed) => println!("Divergence with seed {}:", seed),
+                None => println!("Divergence:"),
+            }
+            println!("    {}", divergence);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn test_command(args: &[String]) {
+    let mut paths = Vec::new();
+    let mut config = golden::GoldenConfig::default();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| exit_with(USAGE));
+        match arg.as_str() {
+            "--bless" => config.bless = true,
+            "--jobs" => config.jobs = value().parse().unwrap_or_else(|_| exit_with(USAGE)),
+            "--max-steps" => {
+                config.max_steps = value().parse().unwrap_or_else(|_| exit_with(USAGE))
+            }
+            "--timeout" => {
+                let millis = value().parse().unwrap_or_else(|_| exit_with(USAGE));
+                config.timeout = Some(std::time::Duration::from_millis(millis));
+            }
+            "--detect-loops" => config.detect_repeats = true,
+            _ if arg.starts_with('-') => exit_with(USAGE),
+            _ => paths.push(arg.clone()),
+        }
+    }
+    if paths.is_empty() {
+        exit_with(USAGE);
+    }
+
+    let (mut passed, mut failed) = (0, 0);
+    for path in &paths {
+        // A directory holds golden files; a single program checks its own directives
+        if std::path::Path::new(path).is_dir() {
+            let results =
+                golden::run_directory(path.as_ref(), &config).unwrap_or_else(|e| exit_with(&e));
+            for result in results {
+                println!("{}", result);
+                if result.passed() {
+                    passed += 1;
+                } else {
+                    failed += 1;
+                }
+            }
+            continue;
+        }
 
-    let vm = Arc::new(Mutex::new(vm::VM::new(1024)));
-    vm.lock()
-        .unwrap()
-        .load_program(parser::parse_iloc(&program).unwrap());
+        let result = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|source| expect::check(&source, path));
+        match result {
+            Ok(failures) if failures.is_empty() => {
+                passed += 1;
+                println!("PASS {}", path);
+            }
+            Ok(failures) => {
+                failed += 1;
+                println!("FAIL {}", path);
+                for failure in failures {
+                    println!("    {}", failure);
+                }
+            }
+            Err(e) => {
+                failed += 1;
+                println!("ERROR {}", path);
+                println!("    {}", e);
+            }
+        }
+    }
+
+    println!();
+    println!("{} passed, {} failed", passed, failed);
+    if failed > 0 {
+        std::process::exit(1);
+    }
+}
+
+fn profile_command(args: &[String]) {
+    let mut path = None;
+    let mut latencies = LatencyTable::default();
+    let mut pipelined = false;
+    let mut max_steps = expect::MAX_STEPS;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| exit_with(USAGE));
+        match arg.as_str() {
+            "--latencies" => {
+                latencies = LatencyTable::load(value()).unwrap_or_else(|e| exit_with(&e))
+            }
+            "--pipeline" => pipelined = true,
+            "--max-steps" => max_steps = value().parse().unwrap_or_else(|_| exit_with(USAGE)),
+            _ if arg.starts_with('-') => exit_with(USAGE),
+            _ => path = Some(arg.clone()),
+        }
+    }
+
+    let path = path.unwrap_or_else(|| exit_with(USAGE));
+    let source = std::fs::read_to_string(&path).unwrap_or_else(|e| exit_with(&e.to_string()));
+    let program = parser::parse_program(&source, &path).unwrap_or_else(|e| exit_with(&e));
+    let mut vm = vm::VM::new(1024);
+    vm.load(&program).unwrap_or_else(|e| exit_with(&e));
+    vm.set_cycle_model(CycleModel::new(latencies, pipelined));
+    let termination = vm.run_limited(max_steps);
+
+    println!(
+        "Run {} after {} cycles",
+        termination,
+        vm.get_cycle_stats().cycles
+    );
+    println!();
+    print!("{}", profile::Profile::from_vm(&vm));
+}
+
+fn coverage_command(args: &[String]) {
+    let mut paths = Vec::new();
+    let mut lcov = "coverage.info".to_string();
+    let mut html = None;
+    let mut max_steps = expect::MAX_STEPS;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| exit_with(USAGE));
+        match arg.as_str() {
+            "--lcov" => lcov = value().clone(),
+            "--html" => html = Some(value().clone()),
+            "--max-steps" => max_steps = value().parse().unwrap_or_else(|_| exit_with(USAGE)),
+            _ if arg.starts_with('-') => exit_with(USAGE),
+            _ => paths.push(std::path::PathBuf::from(arg)),
+        }
+    }
+    if paths.is_empty() {
+        exit_with(USAGE);
+    }
+
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let entries = std::fs::read_dir(&path).unwrap_or_else(|e| exit_with(&e.to_string()));
+            let mut programs: Vec<_> = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "iloc"))
+                .collect();
+            programs.sort();
+            files.extend(programs);
+        } else {
+            files.push(path);
+        }
+    }
+
+    // Runs of the same file add up
+    let mut results: std::collections::BTreeMap<String, (String, Coverage)> = Default::default();
+    for file in files {
+        let name = file.display().to_string();
+        let source = std::fs::read_to_string(&file).unwrap_or_else(|e| exit_with(&e.to_string()));
+        let program = parser::parse_program(&source, &name)
+            .unwrap_or_else(|e| exit_with(&format!("{}: {}", name, e)));
+
+        let mut vm = vm::VM::new(1024);
+        vm.load(&program).unwrap_or_else(|e| exit_with(&e));
+        let limit = expect::step_limit(&source).unwrap_or_else(|e| exit_with(&e));
+        let termination = vm.run_limited(limit.unwrap_or(max_steps));
+        if termination != vm::Termination::Finished {
+            eprintln!("{}: {}", name, termination);
+        }
+
+        // Included files get records of their own
+        for (file, run) in Coverage::by_file(&vm, &program.code) {
+            let entry = results.entry(file.clone()).or_insert_with(|| {
+                let text = match file == name {
+                    true => source.clone(),
+                    false => std::fs::read_to_string(&file).unwrap_or_default(),
+                };
+                (text, Coverage::default())
+            });
+            entry.1.merge(&run);
+        }
+    }
+
+    let mut info = String::new();
+    let mut sections = Vec::new();
+    for (name, (source, coverage)) in &results {
+        let hit = coverage.lines.values().filter(|&&count| count > 0).count();
+        println!(
+            "{}: {} of {} lines covered",
+            name,
+            hit,
+            coverage.lines.len()
+        );
+        info.push_str(&coverage.to_lcov(name));
+        sections.push(coverage.to_html(name, source));
+    }
+    std::fs::write(&lcov, info).unwrap_or_else(|e| exit_with(&format!("{}: {}", lcov, e)));
+    if let Some(html) = html {
+        std::fs::write(&html, coverage::html_page(&sections))
+            .unwrap_or_else(|e| exit_with(&format!("{}: {}", html, e)));
+    }
+}
+
+fn grade_command(args: &[String]) {
+    let mut paths = Vec::new();
+    let mut out = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--out" => out = Some(args.next().unwrap_or_else(|| exit_with(USAGE))),
+            _ if arg.starts_with('-') => exit_with(USAGE),
+            _ => paths.push(arg.clone()),
+        }
+    }
+    let Some((rubric, submissions)) = paths.split_first() else {
+        exit_with(USAGE)
+    };
+    if submissions.is_empty() {
+        exit_with(USAGE);
+    }
+    let rubric = grade::Rubric::load(rubric).unwrap_or_else(|e| exit_with(&e));
+
+    // A directory holds one submission per student, named after the file
+    let mut files = Vec::new();
+    for path in submissions {
+        let path = std::path::PathBuf::from(path);
+        if path.is_dir() {
+            let entries = std::fs::read_dir(&path).unwrap_or_else(|e| exit_with(&e.to_string()));
+            let mut programs: Vec<_> = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "iloc"))
+                .collect();
+            programs.sort();
+            files.extend(programs);
+        } else {
+            files.push(path);
+        }
+    }
+
+    for file in files {
+        let student = file
+            .file_stem()
+            .map_or("submission".into(), |stem| stem.to_string_lossy());
+        let report = match std::fs::read_to_string(&file) {
+            Ok(source) => grade::grade(&student, &source, &file.display().to_string(), &rubric),
+            Err(e) => exit_with(&format!("{}: {}", file.display(), e)),
+        };
+        match out {
+            Some(dir) => {
+                let path = std::path::Path::new(dir).join(format!("{}.json", student));
+                std::fs::create_dir_all(dir)
+                    .and_then(|_| std::fs::write(&path, report.to_json()))
+                    .unwrap_or_else(|e| exit_with(&format!("{}: {}", path.display(), e)));
+                println!("{}: {}/{}", student, report.score, report.total);
+            }
+            None => print!("{}", report.to_json()),
+        }
+    }
+}
+
+fn fmt_command(args: &[String]) {
+    let mut paths = Vec::new();
+    let mut write = false;
+    let mut check = false;
+    for arg in args {
+        match arg.as_str() {
+            "--write" => write = true,
+            "--check" => check = true,
+            _ if arg.starts_with('-') => exit_with(USAGE),
+            _ => paths.push(arg.clone()),
+        }
+    }
+    if paths.is_empty() || (write && check) {
+        exit_with(USAGE);
+    }
+
+    // With --check, list the files that are not formatted and fail if any
+    let mut unformatted = 0;
+    for path in &paths {
+        let source = std::fs::read_to_string(path).unwrap_or_else(|e| exit_with(&e.to_string()));
+        let formatted = format::format_source(&source, path)
+            .unwrap_or_else(|e| exit_with(&format!("{}: {}", path, e)));
+        if check {
+            if formatted != source {
+                unformatted += 1;
+                println!("{}", path);
+            }
+        } else if write {
+            if formatted != source {
+                std::fs::write(path, formatted).unwrap_or_else(|e| exit_with(&e.to_string()));
+            }
+        } else {
+            print!("{}", formatted);
+        }
+    }
+    if unformatted > 0 {
+        std::process::exit(1);
+    }
+}
+
+fn translate_command(args: &[String]) {
+    let mut path = None;
+    let mut from = Dialect::default();
+    let mut to = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut dialect = || -> Dialect {
+            let name = args.next().unwrap_or_else(|| exit_with(USAGE));
+            name.parse().unwrap_or_else(|e: String| exit_with(&e))
+        };
+        match arg.as_str() {
+            "--from" => from = dialect(),
+            "--to" => to = Some(dialect()),
+            _ if arg.starts_with('-') => exit_with(USAGE),
+            _ => path = Some(arg.clone()),
+        }
+    }
+
+    let path = path.unwrap_or_else(|| exit_with(USAGE));
+    let to = to.unwrap_or_else(|| exit_with(USAGE));
+    let source = std::fs::read_to_string(&path).unwrap_or_else(|e| exit_with(&e.to_string()));
+    let translated = dialect::translate(&source, &path, from, to)
+        .unwrap_or_else(|e| exit_with(&format!("{}: {}", path, e)));
+    print!("{}", translated);
+}
 
-    run_tui(vm).unwrap();
+fn exit_with(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
 }
@@ -0,0 +1,297 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+
+use crate::vm::Fault;
+
+/// Bytes in a page, the unit `PagedMemory` allocates and `Memory::pages`
+/// reports.
+pub const PAGE_SIZE: usize = 4096;
+
+/// Bytes in the full 32-bit address space. A `u64`, since it does not fit
+/// in the `usize` of a 32-bit host.
+pub const ADDRESS_SPACE: u64 = 1 << 32;
+
+/// Byte-addressed storage behind the VM. Addresses run from 0 up to
+/// `size`; callers check them against the memory map first, so accesses
+/// past `size` are bugs and may panic.
+pub trait Memory: Send {
+    /// Bytes of address space.
+    fn size(&self) -> usize;
+
+    fn read(&self, address: usize, buffer: &mut [u8]);
+
+    fn write(&mut self, address: usize, bytes: &[u8]);
+
+    /// The pages that hold storage, in address order, as their start
+    /// address and bytes. Unlisted pages read as zeros.
+    fn pages(&self) -> Vec<(usize, &[u8])>;
+
+    /// All of memory as one slice, if it is stored that way.
+    fn as_slice(&self) -> Option<&[u8]> {
+        None
+    }
+}
+
+/// Memory as one vector, allocated up front. Best for small memories,
+/// which is what `VM::new` creates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DenseMemory {
+    bytes: Vec<u8>,
+}
+
+impl DenseMemory {
+    pub fn new(size: usize) -> Self {
+        DenseMemory {
+            bytes: vec![0; size],
+        }
+    }
+}
+
+impl Memory for DenseMemory {
+    fn size(&self) -> usize {
+        self.bytes.len()
+    }
+
+    fn read(&self, address: usize, buffer: &mut [u8]) {
+        buffer.copy_from_slice(&self.bytes[address..address + buffer.len()]);
+    }
+
+    fn write(&mut self, address: usize, bytes: &[u8]) {
+        self.bytes[address..address + bytes.len()].copy_from_slice(bytes);
+    }
+
+    fn pages(&self) -> Vec<(usize, &[u8])> {
+        self.bytes
+            .chunks(PAGE_SIZE)
+            .enumerate()
+            .map(|(idx, page)| (idx * PAGE_SIZE, page))
+            .collect()
+    }
+
+    fn as_slice(&self) -> Option<&[u8]> {
+        Some(&self.bytes)
+    }
+}
+
+/// Memory allocated a page at a time on first write, so that a program can
+/// use a large address space, such as a stack at `0x8000_0000`, while only
+/// the pages it writes take up room.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PagedMemory {
+    size: usize,
+    /// Pages by page number.
+    pages: BTreeMap<usize, Box<[u8]>>,
+}
+
+impl PagedMemory {
+    pub fn new(size: usize) -> Self {
+        PagedMemory {
+            size,
+            pages: BTreeMap::new(),
+        }
+    }
+}
+
+impl Memory for PagedMemory {
+    fn size(&self) -> usize {
+        self.size
+    }
+
+    fn read(&self, address: usize, buffer: &mut [u8]) {
+        for (offset, byte) in buffer.iter_mut().enumerate() {
+            let address = address + offset;
+            *byte = self
+                .pages
+                .get(&(address / PAGE_SIZE))
+                .map_or(0, |page| page[address % PAGE_SIZE]);
+        }
+    }
+
+    fn write(&mut self, address: usize, bytes: &[u8]) {
+        assert!(
+            address + bytes.len() <= self.size,
+            "Write past end of memory"
+        );
+        for (offset, byte) in bytes.iter().enumerate() {
+            let address = address + offset;
+            let page = self
+                .pages
+                .entry(address / PAGE_SIZE)
+                .or_insert_with(|| vec![0; PAGE_SIZE].into_boxed_slice());
+            page[address % PAGE_SIZE] = *byte;
+        }
+    }
+
+    fn pages(&self) -> Vec<(usize, &[u8])> {
+        self.pages
+            .iter()
+            .map(|(number, page)| (number * PAGE_SIZE, &page[..]))
+            .collect()
+    }
+}
+
+/// A kind of memory access, checked against a region's permissions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Access::Read => write!(f, "Read"),
+            Access::Write => write!(f, "Write"),
+        }
+    }
+}
+
+/// A named range of addresses and what programs may do with it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub name: String,
+    pub range: Range<usize>,
+    pub read: bool,
+    pub write: bool,
+}
+
+impl Region {
+    pub fn new(name: &str, range: Range<usize>, read: bool, write: bool) -> Self {
+        Region {
+            name: name.to_string(),
+            range,
+            read,
+            write,
+        }
+    }
+
+    pub fn allows(&self, access: Access) -> bool {
+        match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+        }
+    }
+}
+
+/// The regions of the VM's memory. Every byte a load or store touches must
+/// lie in a region that permits the access, or the program faults with the
+/// address of the first byte it may not touch.
+///
+/// Addresses are unsigned words: a base plus a negative offset wraps
+/// around the word, so with 32-bit words `-4` is `0xFFFF_FFFC`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryMap {
+    regions: Vec<Region>,
+    /// Whether word and float accesses must be aligned to their size.
+    pub check_alignment: bool,
+}
+
+impl MemoryMap {
+    /// A map without regions, in which every access faults.
+    pub fn empty() -> Self {
+        MemoryMap {
+            regions: Vec::new(),
+            check_alignment: false,
+        }
+    }
+
+    /// One readable and writable region, `memory`, covering `size` bytes.
+    pub fn flat(size: usize) -> Self {
+        let mut map = MemoryMap::empty();
+        map.regions.push(Region::new("memory", 0..size, true, true));
+        map
+    }
+
+    /// The conventional layout of `size` bytes, from address 0 up:
+    ///
+    /// - `static`: `static_size` bytes for the data section, which the VM
+    ///   loads at address 0; readable and writable.
+    /// - `code`: `code_size` bytes standing for the program's instructions,
+    ///   which the VM keeps apart from memory; read-only, so that stores
+    ///   into it fault.
+    /// - `heap`: everything between the code and the stack; readable and
+    ///   writable.
+    /// - `stack`: the top `stack_size` bytes; readable and writable.
+    pub fn standard(
+        size: usize,
+        static_size: usize,
+        code_size: usize,
+        stack_size: usize,
+    ) -> Result<Self, String> {
+        let heap_start = static_size + code_size;
+        let Some(stack_start) = size.checked_sub(stack_size).filter(|&s| s >= heap_start) else {
+            return Err(format!(
+                "Regions of {} bytes do not fit in {} bytes of memory",
+                heap_start + stack_size,
+                size
+            ));
+        };
+        let mut map = MemoryMap::empty();
+        map.add(Region::new("static", 0..static_size, true, true))?;
+        map.add(Region::new("code", static_size..heap_start, true, false))?;
+        map.add(Region::new("heap", heap_start..stack_start, true, true))?;
+        map.add(Region::new("stack", stack_start..size, true, true))?;
+        Ok(map)
+    }
+
+    /// Adds `region`, which may not overlap another. Empty regions are
+    /// left out.
+    pub fn add(&mut self, region: Region) -> Result<(), String> {
+        if region.range.is_empty() {
+            return Ok(());
+        }
+        if let Some(other) = self.regions.iter().find(|other| {
+            other.range.start < region.range.end && region.range.start < other.range.end
+        }) {
+            return Err(format!(
+                "Region '{}' overlaps region '{}'",
+                region.name, other.name
+            ));
+        }
+        self.regions.push(region);
+        self.regions.sort_by_key(|region| region.range.start);
+        Ok(())
+    }
+
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    /// The region holding `address`, if any.
+    pub fn region(&self, address: usize) -> Option<&Region> {
+        self.regions
+            .iter()
+            .find(|region| region.range.contains(&address))
+    }
+
+    /// The end of the highest region.
+    pub fn end(&self) -> usize {
+        self.regions.last().map_or(0, |region| region.range.end)
+    }
+
+    /// The bytes of an access of `size` bytes at `address`, or the fault it
+    /// raises.
+    pub fn check(&self, address: u64, size: usize, access: Access) -> Result<Range<usize>, Fault> {
+        if self.check_alignment && size > 1 && !address.is_multiple_of(size as u64) {
+            return Err(Fault::Misaligned { address, size });
+        }
+        let start = usize::try_from(address).map_err(|_| Fault::MemoryViolation {
+            access,
+            address,
+            region: None,
+        })?;
+        let end = start.saturating_add(size);
+        for byte in start..end {
+            let region = self.region(byte);
+            if !region.is_some_and(|region| region.allows(access)) {
+                return Err(Fault::MemoryViolation {
+                    access,
+                    address: byte as u64,
+                    region: region.map(|region| region.name.clone()),
+                });
+            }
+        }
+        Ok(start..end)
+    }
+}
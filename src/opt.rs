@@ -0,0 +1,496 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::cfg::{Cfg, Liveness};
+use crate::ir::{self, Instruction, Operand};
+use crate::lvn::ValueNumbering;
+
+/// A transformation over a parsed program.
+pub trait Pass {
+    fn name(&self) -> &'static str;
+
+    /// Rewrites `instructions` in place and reports whether anything changed.
+    fn run(&self, instructions: &mut Vec<Instruction>) -> bool;
+}
+
+/// Runs `passes` in order, repeating the sequence until none of them changes
+/// the program any further.
+pub fn optimize(program: &[String], passes: &[Box<dyn Pass>]) -> Result<Vec<String>, String> {
+    let mut instructions = ir::parse_program(program)?;
+
+    // Each round only shrinks or simplifies the program, but cap it anyway
+    for _ in 0..32 {
+        let mut changed = false;
+        for pass in passes {
+            changed |= pass.run(&mut instructions);
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    Ok(ir::to_program(&instructions))
+}
+
+/// Every pass, in an order where each one feeds the next.
+pub fn default_passes() -> Vec<Box<dyn Pass>> {
+    vec![
+        Box::new(ConstantFolding),
+        Box::new(ValueNumbering { extended: true }),
+        Box::new(CopyPropagation),
+        Box::new(Peephole),
+        Box::new(StrengthReduction),
+        Box::new(DeadCodeElimination::default()),
+    ]
+}
+
+/// Builds a pass list from comma-separated names such as `fold,copy,dce`.
+pub fn parse_passes(names: &str) -> Result<Vec<Box<dyn Pass>>, String> {
+    names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| -> Result<Box<dyn Pass>, String> {
+            match name {
+                "fold" => Ok(Box::new(ConstantFolding)),
+                "copy" => Ok(Box::new(CopyPropagation)),
+                "dce" => Ok(Box::new(DeadCodeElimination::default())),
+                "strength" => Ok(Box::new(StrengthReduction)),
+                "peephole" => Ok(Box::new(Peephole)),
+                "lvn" => Ok(Box::new(ValueNumbering { extended: false })),
+                "svn" => Ok(Box::new(ValueNumbering { extended: true })),
+                _ => Err(format!("Unknown pass '{}'", name)),
+            }
+        })
+        .collect()
+}
+
+/// Computes `a op b` the way the VM would with its default machine, 32-bit
+/// words that wrap on overflow, or `None` where the VM would trap or the
+/// result depends on the shift policy.
+pub fn evaluate(opcode: &str, a: i32, b: i32) -> Option<i32> {
+    match opcode {
+        "add" => Some(a.wrapping_add(b)),
+        "sub" => Some(a.wrapping_sub(b)),
+        "mult" => Some(a.wrapping_mul(b)),
+        "div" if b != 0 => Some(a.wrapping_div(b)),
+        "lshift" if (0..32).contains(&b) => Some(a << b),
+        "rshift" if (0..32).contains(&b) => Some(a >> b),
+        "rshiftL" if (0..32).contains(&b) => Some(((a as u32) >> b) as i32),
+        "and" => Some(a & b),
+        "or" => Some(a | b),
+        "xor" => Some(a ^ b),
+        _ => None,
+    }
+}
+
+/// Maps an immediate opcode to its register form and whether the constant
+/// is the left operand (`rsubI`, `rdivI`).
+pub fn register_form(opcode: &str) -> Option<(&'static str, bool)> {
+    match opcode {
+        "addI" => Some(("add", false)),
+        "subI" => Some(("sub", false)),
+        "rsubI" => Some(("sub", true)),
+        "multI" => Some(("mult", false)),
+        "divI" => Some(("div", false)),
+        "rdivI" => Some(("div", true)),
+        "lshiftI" => Some(("lshift", false)),
+        "rshiftI" => Some(("rshift", false)),
+        "rshiftLI" => Some(("rshiftL", false)),
+        "andI" => Some(("and", false)),
+        "orI" => Some(("or", false)),
+        "xorI" => Some(("xor", false)),
+        _ => None,
+    }
+}
+
+pub fn is_binary(opcode: &str) -> bool {
+    matches!(
+        opcode,
+        "add" | "sub" | "mult" | "div" | "lshift" | "rshift" | "rshiftL" | "and" | "or" | "xor"
+    )
+}
+
+pub fn is_commutative(opcode: &str) -> bool {
+    matches!(opcode, "add" | "mult" | "and" | "or" | "xor")
+}
+
+pub fn load_immediate(value: i32, target: &str) -> Instruction {
+    Instruction::new(
+        "loadI",
+        vec![Operand::Immediate(value as i64)],
+        vec![Operand::Register(target.to_string())],
+    )
+}
+
+pub fn copy(source: &str, target: &str) -> Instruction {
+    Instruction::new(
+        "i2i",
+        vec![Operand::Register(source.to_string())],
+        vec![Operand::Register(target.to_string())],
+    )
+}
+
+fn immediate_op(opcode: &str, source: &str, value: i32, target: &str) -> Instruction {
+    Instruction::new(
+        opcode,
+        vec![
+            Operand::Register(source.to_string()),
+            Operand::Immediate(value as i64),
+        ],
+        vec![Operand::Register(target.to_string())],
+    )
+}
+
+pub(crate) fn register(operand: Option<&Operand>) -> Option<&str> {
+    match operand {
+        Some(Operand::Register(name)) => Some(name),
+        _ => None,
+    }
+}
+
+pub(crate) fn immediate(operand: Option<&Operand>) -> Option<i32> {
+    match operand {
+        Some(Operand::Immediate(value)) => i32::try_from(*value).ok(),
+        _ => None,
+    }
+}
+
+pub(crate) fn single_target(inst: &Instruction) -> Option<&str> {
+    match inst.targets.as_slice() {
+        [Operand::Register(name)] => Some(name),
+        _ => None,
+    }
+}
+
+/// Replaces `inst` with `replacement`, keeping its label.
+pub(crate) fn replace(inst: &mut Instruction, mut replacement: Instruction) -> bool {
+    replacement.label = inst.label.take();
+    let changed = *inst != replacement;
+    *inst = replacement;
+    changed
+}
+
+/// Removes instruction `idx`. A label it carried moves to the following
+/// instruction, or stays behind on a line of its own if that one has a label.
+pub(crate) fn remove(instructions: &mut Vec<Instruction>, idx: usize) {
+    let label = instructions[idx].label.take();
+    match instructions.get_mut(idx + 1) {
+        Some(next) if label.is_some() && next.label.is_none() => {
+            next.label = label;
+            instructions.remove(idx);
+        }
+        _ if label.is_some() => {
+            instructions[idx] = Instruction {
+                label,
+                opcode: String::new(),
+                sources: Vec::new(),
+                targets: Vec::new(),
+            }
+        }
+        _ => {
+            instructions.remove(idx);
+        }
+    }
+}
+
+/// Folds operations whose operands are known constants within a block into
+/// `loadI`, and turns register operations with one constant operand into
+/// their immediate form.
+pub struct ConstantFolding;
+
+impl Pass for ConstantFolding {
+    fn name(&self) -> &'static str {
+        "fold"
+    }
+
+    fn run(&self, instructions: &mut Vec<Instruction>) -> bool {
+        let cfg = Cfg::build(instructions);
+        let mut changed = false;
+
+        for block in &cfg.blocks {
+            let mut constants: HashMap<String, i32> = HashMap::new();
+
+            for inst in &mut instructions[block.range()] {
+                if let Some(replacement) = fold(inst, &constants) {
+                    changed |= replace(inst, replacement);
+                }
+
+                for reg in inst.defs() {
+                    constants.remove(reg);
+                }
+                if inst.opcode == "loadI" {
+                    if let (Some(value), Some(target)) =
+                        (immediate(inst.sources.first()), single_target(inst))
+                    {
+                        constants.insert(target.to_string(), value);
+                    }
+                }
+            }
+        }
+        changed
+    }
+}
+
+fn fold(inst: &Instruction, constants: &HashMap<String, i32>) -> Option<Instruction> {
+    let target = single_target(inst)?;
+    let constant =
+        |operand: Option<&Operand>| register(operand).and_then(|reg| constants.get(reg).copied());
+
+    if inst.opcode == "i2i" {
+        return constant(inst.sources.first()).map(|value| load_immediate(value, target));
+    }
+
+    if is_binary(&inst.opcode) {
+        let a = register(inst.sources.first())?;
+        let b = register(inst.sources.get(1))?;
+        return match (constants.get(a), constants.get(b)) {
+            (Some(&a), Some(&b)) => {
+                evaluate(&inst.opcode, a, b).map(|value| load_immediate(value, target))
+            }
+            (None, Some(&c)) => match inst.opcode.as_str() {
+                "add" => Some(immediate_op("addI", a, c, target)),
+                "sub" => Some(immediate_op("subI", a, c, target)),
+                "mult" => Some(immediate_op("multI", a, c, target)),
+                "div" if c != 0 => Some(immediate_op("divI", a, c, target)),
+                "lshift" if (0..32).contains(&c) => Some(immediate_op("lshiftI", a, c, target)),
+                "rshift" if (0..32).contains(&c) => Some(immediate_op("rshiftI", a, c, target)),
+                "rshiftL" if (0..32).contains(&c) => Some(immediate_op("rshiftLI", a, c, target)),
+                _ => None,
+            },
+            (Some(&c), None) => match inst.opcode.as_str() {
+                "add" => Some(immediate_op("addI", b, c, target)),
+                "mult" => Some(immediate_op("multI", b, c, target)),
+                "sub" => Some(immediate_op("rsubI", b, c, target)),
+                "div" => Some(immediate_op("rdivI", b, c, target)),
+                _ => None,
+            },
+            (None, None) => None,
+        };
+    }
+
+    let (base, reversed) = register_form(&inst.opcode)?;
+    let a = constant(inst.sources.first())?;
+    let c = immediate(inst.sources.get(1))?;
+    let value = if reversed {
+        evaluate(base, c, a)
+    } else {
+        evaluate(base, a, c)
+    }?;
+    Some(load_immediate(value, target))
+}
+
+/// Replaces reads of the destination of an `i2i` with its source while
+/// neither has been redefined within the block.
+pub struct CopyPropagation;
+
+impl Pass for CopyPropagation {
+    fn name(&self) -> &'static str {
+        "copy"
+    }
+
+    fn run(&self, instructions: &mut Vec<Instruction>) -> bool {
+        let cfg = Cfg::build(instructions);
+        let mut changed = false;
+
+        for block in &cfg.blocks {
+            let mut copies: HashMap<String, String> = HashMap::new();
+
+            for inst in &mut instructions[block.range()] {
+                let before = inst.clone();
+                inst.rename_uses_and_defs(
+                    |reg| copies.get(reg).cloned().unwrap_or_else(|| reg.to_string()),
+                    |reg| reg.to_string(),
+                );
+                changed |= *inst != before;
+
+                for reg in inst.defs() {
+                    copies.remove(reg);
+                    copies.retain(|_, source| source != reg);
+                }
+                if inst.opcode == "i2i" {
+                    if let (Some(source), Some(target)) =
+                        (register(inst.sources.first()), single_target(inst))
+                    {
+                        if source != target {
+                            copies.insert(target.to_string(), source.to_string());
+                        }
+                    }
+                }
+            }
+        }
+        changed
+    }
+}
+
+/// Removes instructions whose results are never read.
+///
+/// Stores and branches are always kept, as are divisions that might trap.
+/// By default every register counts as read when the program ends, so the
+/// final register state is preserved; `keeping` narrows the program's result
+/// to memory plus the listed registers.
+#[derive(Default)]
+pub struct DeadCodeElimination {
+    pub live_at_exit: Option<HashSet<String>>,
+}
+
+impl DeadCodeElimination {
+    pub fn keeping(registers: &[&str]) -> Self {
+        Self {
+            live_at_exit: Some(registers.iter().map(|reg| reg.to_string()).collect()),
+        }
+    }
+}
+
+impl Pass for DeadCodeElimination {
+    fn name(&self) -> &'static str {
+        "dce"
+    }
+
+    fn run(&self, instructions: &mut Vec<Instruction>) -> bool {
+        let cfg = Cfg::build(instructions);
+        let live_at_exit = match &self.live_at_exit {
+            Some(registers) => registers.clone(),
+            None => instructions
+                .iter()
+                .flat_map(|inst| inst.defs())
+                .map(str::to_string)
+                .collect(),
+        };
+        let liveness = Liveness::compute_with_exit(instructions, &cfg, &live_at_exit);
+
+        let mut dead = Vec::new();
+        for (b, block) in cfg.blocks.iter().enumerate() {
+            let mut live = liveness.live_out[b].clone();
+            for idx in block.range().rev() {
+                let inst = &instructions[idx];
+                let defs = inst.defs();
+                if !defs.is_empty()
+                    && !has_side_effects(inst)
+                    && defs.iter().all(|reg| !live.contains(*reg))
+                {
+                    dead.push(idx);
+                    continue;
+                }
+                for reg in defs {
+                    live.remove(reg);
+                }
+                for reg in inst.uses() {
+                    live.insert(reg.to_string());
+                }
+            }
+        }
+
+        dead.sort_unstable();
+        for &idx in dead.iter().rev() {
+            remove(instructions, idx);
+        }
+        !dead.is_empty()
+    }
+}
+
+fn has_side_effects(inst: &Instruction) -> bool {
+    match inst.opcode.as_str() {
+        "divI" => immediate(inst.sources.get(1)) == Some(0),
+        "div" | "rdivI" => true,
+        opcode => ir::is_store(opcode) || ir::is_branch(opcode) || ir::is_output(opcode),
+    }
+}
+
+/// Replaces multiplications by powers of two with shifts.
+pub struct StrengthReduction;
+
+impl Pass for StrengthReduction {
+    fn name(&self) -> &'static str {
+        "strength"
+    }
+
+    fn run(&self, instructions: &mut Vec<Instruction>) -> bool {
+        let mut changed = false;
+        for inst in instructions.iter_mut() {
+            if inst.opcode != "multI" {
+                continue;
+            }
+            let (Some(source), Some(c), Some(target)) = (
+                register(inst.sources.first()),
+                immediate(inst.sources.get(1)),
+                single_target(inst),
+            ) else {
+                continue;
+            };
+            if c > 1 && (c as u32).is_power_of_two() {
+                let shift = immediate_op("lshiftI", source, c.trailing_zeros() as i32, target);
+                changed |= replace(inst, shift);
+            }
+        }
+        changed
+    }
+}
+
+/// Algebraic simplifications of single instructions: identities such as
+/// `addI r, 0`, annihilators such as `multI r, 0`, self-cancelling
+/// operations such as `sub r, r`, and removal of `nop`, self-copies and
+/// jumps to the next instruction.
+pub struct Peephole;
+
+impl Pass for Peephole {
+    fn name(&self) -> &'static str {
+        "peephole"
+    }
+
+    fn run(&self, instructions: &mut Vec<Instruction>) -> bool {
+        let mut changed = false;
+
+        let mut idx = 0;
+        while idx < instructions.len() {
+            if is_useless(instructions, idx) {
+                let before = instructions.len();
+                remove(instructions, idx);
+                changed = true;
+                if instructions.len() < before {
+                    continue;
+                }
+            } else if let Some(replacement) = simplify(&instructions[idx]) {
+                changed |= replace(&mut instructions[idx], replacement);
+            }
+            idx += 1;
+        }
+        changed
+    }
+}
+
+fn is_useless(instructions: &[Instruction], idx: usize) -> bool {
+    let inst = &instructions[idx];
+    match inst.opcode.as_str() {
+        "nop" => true,
+        "i2i" => register(inst.sources.first()) == single_target(inst),
+        "jumpI" => match (inst.targets.first(), instructions.get(idx + 1)) {
+            (Some(Operand::Label(target)), Some(next)) => next.label.as_deref() == Some(target),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn simplify(inst: &Instruction) -> Option<Instruction> {
+    let target = single_target(inst)?;
+    let a = register(inst.sources.first())?;
+
+    if let Some(c) = immediate(inst.sources.get(1)) {
+        return match (inst.opcode.as_str(), c) {
+            ("addI" | "subI" | "lshiftI" | "rshiftI" | "rshiftLI", 0) => Some(copy(a, target)),
+            ("multI" | "divI", 1) => Some(copy(a, target)),
+            ("multI", 0) => Some(load_immediate(0, target)),
+            _ => None,
+        };
+    }
+
+    let b = register(inst.sources.get(1))?;
+    if a != b {
+        return None;
+    }
+    match inst.opcode.as_str() {
+        "sub" | "xor" => Some(load_immediate(0, target)),
+        "and" | "or" => Some(copy(a, target)),
+        _ => None,
+    }
+}
@@ -1,56 +1,515 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+
+use crate::dialect::Dialect;
+use crate::format;
+use crate::ir;
+use crate::literal;
+use crate::machine::MachineConfig;
+use crate::memory::ADDRESS_SPACE;
+use crate::preprocess;
+
+/// Where a parsed instruction came from in the user's source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLine {
+    /// The normalized instruction, as `parse_iloc` returns it.
+    pub text: String,
+    pub file: String,
+    /// 1-based line number.
+    pub line: usize,
+    /// 1-based character columns of the instruction, end exclusive, with
+    /// surrounding whitespace and comments left out.
+    pub columns: Range<usize>,
+    /// Text of the comments on the same line, without their markers.
+    pub comment: Option<String>,
+}
+
+/// Initial memory contents laid out by the `.data` sections of a program,
+/// starting at address 0.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DataSegment {
+    pub bytes: Vec<u8>,
+    /// Address of every data label.
+    pub labels: BTreeMap<String, usize>,
+}
+
+/// A parsed program: its instructions and the data to load before it runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    pub code: Vec<SourceLine>,
+    pub data: DataSegment,
+}
+
+impl Program {
+    /// The normalized instructions, as `parse_iloc` returns them.
+    pub fn text(&self) -> Vec<String> {
+        self.code.iter().map(|line| line.text.clone()).collect()
+    }
+}
+
+/// Prints the program in canonical form, see `format::format_source`, which
+/// `parse_program` reads back to the same instructions and data. The data
+/// segment is written as `.byte` rows.
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format::layout(&format::program_rows(self)))
+    }
+}
+
+/// Parses `program` into normalized instruction lines. Data directives are
+/// laid out but their bytes are dropped; use `parse_program` and
+/// `VM::load` to run a program that has a `.data` section.
 pub fn parse_iloc(program: &str) -> Result<Vec<String>, String> {
-    let mut lines = Vec::new();
-    let mut in_block_comment = false;
+    Ok(parse_program(program, "")?.text())
+}
 
-    for line in program.lines() {
-        let mut trimmed_line = line.trim().to_string();
+/// Like `parse_iloc`, but pairs every normalized line with the 1-based
+/// source line it came from.
+pub fn parse_with_lines(program: &str) -> Result<Vec<(usize, String)>, String> {
+    Ok(parse_source(program, "")?
+        .into_iter()
+        .map(|line| (line.line, line.text))
+        .collect())
+}
 
-        if in_block_comment {
-            if let Some(end_pos) = trimmed_line.find("*/") {
-                trimmed_line = trimmed_line[end_pos + 2..].to_string(); // Skip past the block comment
-                in_block_comment = false;
-            } else {
-                continue; // Ignore lines within block comments
-            }
+/// Parses `program`, read from `file`, keeping the position and comments
+/// of every instruction.
+pub fn parse_source(program: &str, file: &str) -> Result<Vec<SourceLine>, String> {
+    Ok(parse_program(program, file)?.code)
+}
+
+/// Parses `program`, read from `file`. `//` and `#` comment out the rest of
+/// a line and `/* */` may span lines. Includes, macros and `.equ` constants
+/// are expanded first, see `preprocess::preprocess`.
+///
+/// Between `.data` and `.text` the program holds data directives instead
+/// of instructions: `.word 1, 2, 3`, `.byte 255`, `.string "hi"` (with a
+/// terminating zero byte), `.space 64` and `.align 4`, each optionally
+/// labeled with `name:`. A data label stands for its address in `.word`
+/// values and as the immediate of `loadI`.
+///
+/// Immediates and data values may be written in any form `literal::evaluate`
+/// accepts, such as `0x10`, `'A'` or `4*8+2`. They are checked to fit in a
+/// word and normalized to decimal. Words are 32-bit little-endian; see
+/// `parse_program_for` for other machines.
+pub fn parse_program(program: &str, file: &str) -> Result<Program, String> {
+    parse_program_in(program, file, Dialect::default())
+}
+
+/// Like `parse_program`, for a program written in `dialect`. The result is
+/// normalized the same way whatever the dialect.
+pub fn parse_program_in(program: &str, file: &str, dialect: Dialect) -> Result<Program, String> {
+    parse_program_for(program, file, dialect, MachineConfig::default())
+}
+
+/// Like `parse_program_in`, for `machine`: immediates must fit in its
+/// words, and `.word` data takes its word size and byte order.
+pub fn parse_program_for(
+    program: &str,
+    file: &str,
+    dialect: Dialect,
+    machine: MachineConfig,
+) -> Result<Program, String> {
+    parse_within(program, file, dialect, machine, ADDRESS_SPACE)
+}
+
+/// Like `parse_program`, for a memory of `memory_size` bytes: a data
+/// section that would not fit is an error before it is laid out, so that
+/// a hostile program cannot exhaust the host.
+pub fn parse_program_sized(
+    program: &str,
+    file: &str,
+    memory_size: usize,
+) -> Result<Program, String> {
+    parse_within(
+        program,
+        file,
+        Dialect::default(),
+        MachineConfig::default(),
+        memory_size as u64,
+    )
+}
+
+/// `parse_program_for` with a data section of at most `max_data` bytes.
+fn parse_within(
+    program: &str,
+    file: &str,
+    dialect: Dialect,
+    machine: MachineConfig,
+    max_data: u64,
+) -> Result<Program, String> {
+    let mut code = Vec::new();
+    let mut data = DataSegment::default();
+    // `.word` values naming a label, filled in once every label is known
+    let mut fixups = Vec::new();
+    let mut in_data = false;
+
+    for scanned in preprocess::preprocess(program, file)? {
+        if scanned.code.trim().is_empty() {
+            continue;
         }
+        let at = preprocess::location(file, &scanned.file, scanned.line);
+        let error = |e: String| format!("{}: {}", at, e);
+        let source = literal::replace_chars(&scanned.code).map_err(error)?;
+        let statement = source.trim();
 
-        if let Some(start_pos) = trimmed_line.find("/*") {
-            if let Some(end_pos) = trimmed_line[start_pos..].find("*/") {
-                let formatted_line = format!(
-                    "{}{}",
-                    &trimmed_line[..start_pos],
-                    &trimmed_line[start_pos + end_pos + 2..]
-                );
-                let formatted_line = formatted_line.trim().to_string();
-                trimmed_line = formatted_line;
-            } else {
-                trimmed_line = trimmed_line[..start_pos].to_string();
-                in_block_comment = true;
+        let (label, rest) = match statement.split_once(':') {
+            Some((label, rest)) if ir::is_identifier(label.trim()) => {
+                (Some(label.trim()), rest.trim())
+            }
+            _ => (None, statement),
+        };
+        if in_data || rest.starts_with('.') {
+            if let Some(label) = label {
+                if !in_data {
+                    return Err(error(format!("Data label '{}' outside of .data", label)));
+                }
+                if data
+                    .labels
+                    .insert(label.to_string(), data.bytes.len())
+                    .is_some()
+                {
+                    return Err(error(format!("Duplicate data label '{}'", label)));
+                }
+            }
+            if !rest.is_empty() {
+                directive(
+                    rest,
+                    &mut in_data,
+                    &mut data,
+                    &mut fixups,
+                    &at,
+                    machine,
+                    max_data,
+                )
+                .map_err(error)?;
             }
+            continue;
         }
 
-        let without_comments = trimmed_line
-            .split('#')
-            .next()
-            .unwrap_or("")
-            .split("//")
+        let normalized_line = source
+            .split_whitespace()
+            .collect::<Vec<&str>>()
+            .join(" ")
+            .replace(", ", ",");
+        let text = if normalized_line.contains('[') || normalized_line.contains(']') {
+            normalize_bundle(&normalized_line, dialect, machine).map_err(error)?
+        } else {
+            normalize_operation(&normalized_line, dialect, machine).map_err(error)?
+        };
+        let comment = scanned
+            .comments
+            .iter()
+            .map(|comment| comment.trim())
+            .filter(|comment| !comment.is_empty())
+            .collect::<Vec<&str>>()
+            .join(" ");
+        code.push(SourceLine {
+            text,
+            file: scanned.file,
+            line: scanned.line,
+            columns: scanned.columns,
+            comment: (!comment.is_empty()).then_some(comment),
+        });
+    }
+
+    if code.is_empty() {
+        return Err("Program is empty".to_string());
+    }
+
+    for (offset, label, at) in fixups {
+        let address = *data
+            .labels
+            .get(&label)
+            .ok_or_else(|| format!("{}: Unknown data label '{}'", at, label))?;
+        let word = machine.encode(address as i64);
+        data.bytes[offset..offset + word.len()].copy_from_slice(&word);
+    }
+    for line in code.iter_mut() {
+        if let Some(label) = line
+            .text
+            .split_whitespace()
             .next()
-            .unwrap_or("")
-            .trim();
+            .and_then(|t| t.strip_suffix(':'))
+        {
+            if data.labels.contains_key(label) {
+                return Err(format!(
+                    "{}: '{}' is both a code and a data label",
+                    preprocess::location(file, &line.file, line.line),
+                    label
+                ));
+            }
+        }
+        line.text = resolve_data_labels(&line.text, &data.labels).map_err(|e| {
+            format!(
+                "{}: {}",
+                preprocess::location(file, &line.file, line.line),
+                e
+            )
+        })?;
+    }
+    check_branch_targets(&code, file)?;
 
-        if !without_comments.is_empty() {
-            let normalized_line = without_comments
-                .split_whitespace()
-                .collect::<Vec<&str>>()
-                .join(" ")
-                .replace(", ", ",");
-            lines.push(normalized_line);
+    Ok(Program { code, data })
+}
+
+/// Checks that every label `jumpI` and `cbr` branch to is defined.
+fn check_branch_targets(code: &[SourceLine], file: &str) -> Result<(), String> {
+    let labels: Vec<&str> = code
+        .iter()
+        .filter_map(|line| line.text.split_whitespace().next()?.strip_suffix(':'))
+        .collect();
+    for line in code {
+        let operations = match ir::split_bundle(&line.text) {
+            Some((_, operations)) => operations,
+            None => vec![line.text.as_str()],
+        };
+        for operation in operations {
+            let Ok(instruction) = ir::Instruction::parse(operation) else {
+                continue;
+            };
+            if !matches!(instruction.opcode.as_str(), "jumpI" | "cbr") {
+                continue;
+            }
+            for target in &instruction.targets {
+                if let ir::Operand::Label(label) = target {
+                    if !labels.contains(&label.as_str()) {
+                        return Err(format!(
+                            "{}: Unknown label '{}'",
+                            preprocess::location(file, &line.file, line.line),
+                            label
+                        ));
+                    }
+                }
+            }
         }
     }
+    Ok(())
+}
 
-    if lines.is_empty() {
-        return Err("Program is empty".to_string());
+/// Lays out one data directive, or switches sections.
+fn directive(
+    statement: &str,
+    in_data: &mut bool,
+    data: &mut DataSegment,
+    fixups: &mut Vec<(usize, String, String)>,
+    at: &str,
+    machine: MachineConfig,
+    max_data: u64,
+) -> Result<(), String> {
+    let (name, args) = match statement.find(char::is_whitespace) {
+        Some(pos) => (&statement[..pos], statement[pos..].trim()),
+        None => (statement, ""),
+    };
+    let values = || args.split(',').map(str::trim);
+
+    match name {
+        ".data" | ".text" if !args.is_empty() => {
+            return Err(format!("{} takes no arguments", name));
+        }
+        ".data" => *in_data = true,
+        ".text" => *in_data = false,
+        ".word" | ".byte" | ".string" | ".space" | ".align" if !*in_data => {
+            return Err(format!("{} outside of .data", name));
+        }
+        ".word" => {
+            for value in values() {
+                if ir::is_identifier(value) {
+                    fixups.push((data.bytes.len(), value.to_string(), at.to_string()));
+                    data.bytes.extend(machine.encode(0));
+                    continue;
+                }
+                let word = machine.word_size.immediate(literal::evaluate(value)?)?;
+                data.bytes.extend(machine.encode(word));
+            }
+        }
+        ".byte" => {
+            for value in values() {
+                let byte = literal::evaluate(value)?;
+                if !(-128..=255).contains(&byte) {
+                    return Err(format!("Value {} does not fit in a byte", byte));
+                }
+                data.bytes.push(byte as u8);
+            }
+        }
+        ".string" => {
+            let text = crate::expect::parse_string(args)?;
+            data.bytes.extend_from_slice(text.as_bytes());
+            data.bytes.push(0);
+        }
+        ".space" => {
+            let size = parse_size(args)?;
+            pad(data, size, max_data)?;
+        }
+        ".align" => {
+            let alignment = parse_size(args)?;
+            if alignment == 0 {
+                return Err("Alignment must be at least 1".to_string());
+            }
+            let padding = (alignment - data.bytes.len() % alignment) % alignment;
+            pad(data, padding, max_data)?;
+        }
+        _ if name.starts_with('.') => return Err(format!("Unknown directive '{}'", name)),
+        _ => return Err(format!("Instruction '{}' inside .data", statement)),
+    }
+    Ok(())
+}
+
+/// Appends `size` zero bytes to `data`, unless that would take it past
+/// `max_data` bytes, which memory cannot hold and would only exhaust the
+/// host.
+fn pad(data: &mut DataSegment, size: usize, max_data: u64) -> Result<(), String> {
+    let len = data
+        .bytes
+        .len()
+        .checked_add(size)
+        .filter(|&len| len as u64 <= max_data)
+        .ok_or_else(|| format!("Data section too large: it must fit in {} bytes", max_data))?;
+    data.bytes.resize(len, 0);
+    Ok(())
+}
+
+fn parse_size(text: &str) -> Result<usize, String> {
+    let size = literal::evaluate(text)?;
+    usize::try_from(size).map_err(|_| format!("Invalid size '{}'", text))
+}
+
+/// Normalizes one operation written in `dialect`, and evaluates its
+/// immediates to decimal words, keeping registers and labels as they are.
+fn normalize_operation(
+    operation: &str,
+    dialect: Dialect,
+    machine: MachineConfig,
+) -> Result<String, String> {
+    let (label, rest) = match operation.split_once(':') {
+        Some((label, rest)) if ir::is_identifier(label) => (Some(label), rest.trim()),
+        _ => (None, operation),
+    };
+    if rest.is_empty() {
+        return Ok(operation.to_string());
+    }
+    let rest = dialect.normalize(rest)?;
+    let Some((opcode, operands)) = rest.split_once(' ') else {
+        return Ok(match label {
+            Some(label) => format!("{}: {}", label, rest),
+            None => rest,
+        });
+    };
+
+    let arrow = ["=>", "->"]
+        .into_iter()
+        .find(|arrow| operands.contains(arrow));
+    // `loadI` into a float register is `loadF`, whose immediate is a float
+    let opcode = match arrow.and_then(|arrow| operands.split_once(arrow)) {
+        Some((_, target)) if opcode == "loadI" && ir::is_float_register(target.trim()) => "loadF",
+        _ => opcode,
+    };
+    let evaluate = |side: &str| -> Result<String, String> {
+        side.split(',')
+            .map(|operand| {
+                let operand = operand.trim();
+                if !literal::is_literal(operand) {
+                    return Ok(operand.to_string());
+                }
+                if opcode == "loadF" {
+                    return Ok(format!("{:?}", literal::evaluate_float(operand)?));
+                }
+                let value = literal::evaluate(operand)?;
+                Ok(machine.word_size.immediate(value)?.to_string())
+            })
+            .collect::<Result<Vec<String>, String>>()
+            .map(|operands| operands.join(","))
+    };
+    let (sources, arrow, targets) = match arrow {
+        Some(arrow) => {
+            let (sources, targets) = operands.split_once(arrow).unwrap();
+            (evaluate(sources)?, arrow, evaluate(targets)?)
+        }
+        None => (evaluate(operands)?, "", String::new()),
+    };
+    check_immediate(opcode, &sources, &targets)?;
+    let operation = [opcode, &sources, arrow, &targets]
+        .into_iter()
+        .filter(|field| !field.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" ");
+    Ok(match label {
+        Some(label) => format!("{}: {}", label, operation),
+        None => operation,
+    })
+}
+
+/// Checks that the immediate of `opcode`, already evaluated, is a number,
+/// or a data label for `loadI`, so that the VM never has to parse a
+/// register or a stray name as one.
+fn check_immediate(opcode: &str, sources: &str, targets: &str) -> Result<(), String> {
+    let last = |side: &str| side.rsplit(',').next().unwrap_or("").trim().to_string();
+    let immediate = match opcode {
+        "storeAI" | "cstoreAI" | "fstoreAI" => last(targets),
+        "jumpI" => return Ok(()),
+        "loadF" => last(sources),
+        _ if opcode.ends_with('I') => last(sources),
+        _ => return Ok(()),
+    };
+    let valid = match opcode {
+        "loadI" => {
+            (ir::is_identifier(&immediate) && !ir::is_register(&immediate))
+                || immediate.parse::<i64>().is_ok()
+        }
+        "loadF" => immediate.parse::<f64>().is_ok(),
+        _ => immediate.parse::<i64>().is_ok(),
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "Expected an immediate operand for {}, got '{}'",
+            opcode, immediate
+        ))
+    }
+}
+
+/// Replaces a data label used as the immediate of `loadI` by its address.
+fn resolve_data_labels(text: &str, labels: &BTreeMap<String, usize>) -> Result<String, String> {
+    let mut tokens: Vec<String> = text.split(' ').map(str::to_string).collect();
+    for idx in 1..tokens.len() {
+        if tokens[idx - 1] == "loadI" && ir::is_identifier(&tokens[idx]) {
+            let address = labels
+                .get(&tokens[idx])
+                .ok_or_else(|| format!("Unknown data label '{}'", tokens[idx]))?;
+            tokens[idx] = address.to_string();
+        }
+    }
+    Ok(tokens.join(" "))
+}
+
+/// Normalizes a bundle line to `[ op1 ; op2 ]`, keeping any leading label.
+fn normalize_bundle(
+    line: &str,
+    dialect: Dialect,
+    machine: MachineConfig,
+) -> Result<String, String> {
+    let (label, operations) =
+        ir::split_bundle(line).ok_or_else(|| format!("Malformed bundle '{}'", line))?;
+
+    if operations.is_empty() {
+        return Err("Empty bundle".to_string());
+    }
+    if operations
+        .iter()
+        .any(|op| op.contains('[') || op.contains(']'))
+    {
+        return Err("Bundles cannot be nested".to_string());
     }
 
-    Ok(lines)
+    let operations = operations
+        .iter()
+        .map(|op| normalize_operation(op, dialect, machine))
+        .collect::<Result<Vec<String>, String>>()?;
+    let bundle = format!("[ {} ]", operations.join(" ; "));
+    Ok(match label {
+        Some(label) => format!("{}: {}", label, bundle),
+        None => bundle,
+    })
 }
@@ -0,0 +1,415 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::ir;
+use crate::literal;
+
+/// Macro expansions may nest this deep before expansion gives up.
+const MAX_EXPANSION_DEPTH: usize = 64;
+
+/// A source line split into code and comments, after includes, macros and
+/// constants have been expanded.
+#[derive(Debug, Clone)]
+pub(crate) struct Line {
+    pub file: String,
+    /// 1-based line number in `file`. Lines expanded from a macro carry the
+    /// position of the macro call.
+    pub line: usize,
+    pub code: String,
+    /// 1-based character columns of the code, end exclusive.
+    pub columns: Range<usize>,
+    pub comments: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Line>,
+}
+
+/// Expands `source`, read from `file`:
+///
+/// - `#include "lib.iloc"` or `.include "lib.iloc"` inserts another file,
+///   found relative to the including one;
+/// - `.macro NAME a, b` ... `.endm` defines a macro, called as `NAME x, y`.
+///   Identifiers equal to a parameter are replaced by the argument, and
+///   `\@` by a number unique to each expansion, for labels;
+/// - `.equ NAME value` replaces the identifier `NAME` from then on.
+pub(crate) fn preprocess(source: &str, file: &str) -> Result<Vec<Line>, String> {
+    let mut preprocessor = Preprocessor {
+        root: file.to_string(),
+        stack: Vec::new(),
+        macros: HashMap::new(),
+        constants: HashMap::new(),
+        expansions: 0,
+        lines: Vec::new(),
+    };
+    if let Ok(path) = Path::new(file).canonicalize() {
+        preprocessor.stack.push(path);
+    }
+    preprocessor.file(source, file)?;
+    Ok(preprocessor.lines)
+}
+
+/// Where `line` is, for error messages: `Line N` in the file being parsed,
+/// or `FILE: Line N` in an included one.
+pub(crate) fn location(root: &str, file: &str, line: usize) -> String {
+    if file == root {
+        format!("Line {}", line)
+    } else {
+        format!("{}: Line {}", file, line)
+    }
+}
+
+struct Preprocessor {
+    root: String,
+    /// Files being included, outermost first, to detect cycles.
+    stack: Vec<PathBuf>,
+    macros: HashMap<String, Macro>,
+    constants: HashMap<String, String>,
+    expansions: usize,
+    lines: Vec<Line>,
+}
+
+impl Preprocessor {
+    fn file(&mut self, source: &str, file: &str) -> Result<(), String> {
+        let mut in_block_comment = false;
+        let mut definition: Option<(String, usize, Macro)> = None;
+        let root = self.root.clone();
+
+        for (idx, text) in source.lines().enumerate() {
+            let line_number = idx + 1;
+            let error = |e: String| format!("{}: {}", location(&root, file, line_number), e);
+
+            // `#` starts a comment, so look for `#include` before scanning
+            let trimmed = text.trim_start();
+            let include = match trimmed.strip_prefix("#include") {
+                Some(rest) if !in_block_comment => Some(rest),
+                _ => None,
+            };
+            let line = match include {
+                Some(rest) => Line {
+                    file: file.to_string(),
+                    line: line_number,
+                    code: format!(".include {}", rest.trim()),
+                    columns: 0..0,
+                    comments: Vec::new(),
+                },
+                None => scan_line(text, &mut in_block_comment, file, line_number),
+            };
+            let statement = line.code.trim();
+            let (keyword, args) = split_keyword(statement);
+
+            if let Some((_, _, body)) = definition.as_mut() {
+                match keyword {
+                    ".endm" => {
+                        let (name, _, body) = definition.take().unwrap();
+                        self.macros.insert(name, body);
+                    }
+                    ".macro" => {
+                        return Err(error("Macros cannot be defined inside a macro".into()))
+                    }
+                    _ => body.body.push(line),
+                }
+                continue;
+            }
+
+            match keyword {
+                ".include" => {
+                    let name = crate::expect::parse_string(args).map_err(error)?;
+                    self.include(&name, file, line_number)?;
+                }
+                ".macro" => {
+                    let mut words = args.split([',', ' ', '\t']).filter(|w| !w.is_empty());
+                    let name = words
+                        .next()
+                        .filter(|name| ir::is_identifier(name))
+                        .ok_or_else(|| error("Expected '.macro NAME params'".into()))?;
+                    let params: Vec<String> = words.map(str::to_string).collect();
+                    if let Some(param) = params.iter().find(|p| !ir::is_identifier(p)) {
+                        return Err(error(format!("Invalid macro parameter '{}'", param)));
+                    }
+                    definition = Some((
+                        name.to_string(),
+                        line_number,
+                        Macro {
+                            params,
+                            body: Vec::new(),
+                        },
+                    ));
+                }
+                ".endm" => return Err(error(".endm without .macro".into())),
+                _ => self.statement(line, 0).map_err(error)?,
+            }
+        }
+
+        if let Some((name, line_number, _)) = definition {
+            return Err(format!(
+                "{}: .macro {} has no .endm",
+                location(&self.root, file, line_number),
+                name
+            ));
+        }
+        Ok(())
+    }
+
+    /// Expands the file `name`, included from line `line` of `from`. Errors
+    /// inside the included file carry their own location.
+    fn include(&mut self, name: &str, from: &str, line: usize) -> Result<(), String> {
+        let at = location(&self.root, from, line);
+        let path = Path::new(from).parent().unwrap_or(Path::new("")).join(name);
+        let canonical = path
+            .canonicalize()
+            .map_err(|e| format!("{}: Cannot include '{}': {}", at, path.display(), e))?;
+        if let Some(start) = self.stack.iter().position(|file| *file == canonical) {
+            let cycle: Vec<String> = self.stack[start..]
+                .iter()
+                .chain(std::iter::once(&canonical))
+                .map(|file| file.display().to_string())
+                .collect();
+            return Err(format!("{}: Include cycle: {}", at, cycle.join(" -> ")));
+        }
+        let source = std::fs::read_to_string(&path)
+            .map_err(|e| format!("{}: Cannot include '{}': {}", at, path.display(), e))?;
+
+        self.stack.push(canonical);
+        let result = self.file(&source, &path.display().to_string());
+        self.stack.pop();
+        result
+    }
+
+    /// Handles `.equ` and macro calls; everything else is passed on with
+    /// constants replaced.
+    fn statement(&mut self, mut line: Line, depth: usize) -> Result<(), String> {
+        let statement = line.code.trim().to_string();
+        let (keyword, args) = split_keyword(&statement);
+
+        if keyword == ".equ" {
+            let (name, value) = args
+                .split_once([',', ' ', '\t'])
+                .map(|(name, value)| (name.trim(), value.trim()))
+                .filter(|(name, value)| ir::is_identifier(name) && !value.is_empty())
+                .ok_or_else(|| "Expected '.equ NAME value'".to_string())?;
+            // Numeric values are evaluated once, so that `.equ N 1+2` then
+            // `N*3` gives 9; anything else, such as a register, is kept as text
+            let value = substitute(value, &self.constants, &HashMap::new());
+            let value = match literal::is_literal(&value) {
+                true => literal::evaluate(&value)?.to_string(),
+                false => value,
+            };
+            self.constants.insert(name.to_string(), value);
+            return Ok(());
+        }
+
+        // A macro call may follow a label, which stays on a line of its own
+        let (label, call) = match statement.split_once(':') {
+            Some((label, rest)) if ir::is_identifier(label.trim()) => {
+                (Some(label.trim()), rest.trim())
+            }
+            _ => (None, statement.as_str()),
+        };
+        let (name, args) = split_keyword(call);
+        let Some(definition) = self.macros.get(name).cloned() else {
+            line.code = substitute(&line.code, &self.constants, &HashMap::new());
+            self.lines.push(line);
+            return Ok(());
+        };
+
+        if depth >= MAX_EXPANSION_DEPTH {
+            return Err(format!("Macro '{}' expands too deeply", name));
+        }
+        let args: Vec<&str> = args
+            .split(',')
+            .map(str::trim)
+            .filter(|arg| !arg.is_empty())
+            .collect();
+        if args.len() != definition.params.len() {
+            return Err(format!(
+                "Macro '{}' takes {} arguments but {} were given",
+                name,
+                definition.params.len(),
+                args.len()
+            ));
+        }
+        let mut bindings: HashMap<String, String> = definition
+            .params
+            .iter()
+            .cloned()
+            .zip(args.iter().map(|arg| arg.to_string()))
+            .collect();
+        self.expansions += 1;
+        bindings.insert("\\@".to_string(), self.expansions.to_string());
+
+        if let Some(label) = label {
+            self.lines.push(Line {
+                code: format!("{}:", label),
+                comments: Vec::new(),
+                ..line.clone()
+            });
+        }
+        for body_line in definition.body {
+            let expanded = Line {
+                code: substitute(&body_line.code, &HashMap::new(), &bindings),
+                comments: body_line.comments,
+                ..line.clone()
+            };
+            self.statement(expanded, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// Splits `.word 1, 2` into `.word` and `1, 2`.
+fn split_keyword(statement: &str) -> (&str, &str) {
+    match statement.find(char::is_whitespace) {
+        Some(pos) => (&statement[..pos], statement[pos..].trim()),
+        None => (statement, ""),
+    }
+}
+
+/// Replaces every identifier in `code` found in `constants` or `bindings`,
+/// and every `\@` bound in `bindings`, leaving string literals alone.
+fn substitute(
+    code: &str,
+    constants: &HashMap<String, String>,
+    bindings: &HashMap<String, String>,
+) -> String {
+    if constants.is_empty() && bindings.is_empty() {
+        return code.to_string();
+    }
+    let mut result = String::new();
+    let mut word = String::new();
+    let mut in_string = false;
+    let mut chars = code.chars().peekable();
+
+    let flush = |word: &mut String, result: &mut String| {
+        let replacement = bindings
+            .get(word.as_str())
+            .or_else(|| constants.get(word.as_str()));
+        result.push_str(replacement.map_or(word.as_str(), String::as_str));
+        word.clear();
+    };
+    while let Some(c) = chars.next() {
+        if in_string {
+            result.push(c);
+            match c {
+                '\\' => result.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        if c.is_ascii_alphanumeric() || c == '_' || c == '.' {
+            word.push(c);
+            continue;
+        }
+        flush(&mut word, &mut result);
+        match c {
+            '"' => {
+                in_string = true;
+                result.push(c);
+            }
+            '\'' => {
+                // Keep `'N'` even when `N` is a constant
+                result.push(c);
+                let escaped = chars.peek() == Some(&'\\');
+                result.extend(chars.next());
+                if escaped {
+                    result.extend(chars.next());
+                }
+                if chars.peek() == Some(&'\'') {
+                    result.extend(chars.next());
+                }
+            }
+            '\\' if chars.peek() == Some(&'@') => {
+                chars.next();
+                result.push_str(bindings.get("\\@").map_or("\\@", String::as_str));
+            }
+            _ => result.push(c),
+        }
+    }
+    flush(&mut word, &mut result);
+    result
+}
+
+/// Length of the character literal at the start of `chars`, such as `'#'`
+/// or `'\''`, or 1 if there is none.
+fn char_literal_width(chars: &[char]) -> usize {
+    match chars {
+        ['\'', '\\', _, '\'', ..] => 4,
+        ['\'', c, '\'', ..] if *c != '\\' => 3,
+        _ => 1,
+    }
+}
+
+/// Splits one source line into code and comments. `in_block_comment` carries
+/// an open `/* */` comment over to the next line.
+pub(crate) fn scan_line(text: &str, in_block_comment: &mut bool, file: &str, line: usize) -> Line {
+    let chars: Vec<char> = text.chars().collect();
+    let mut code = String::new();
+    let mut code_columns = Vec::new();
+    let mut comments = Vec::new();
+    let mut comment = String::new();
+    let mut in_string = false;
+
+    let mut idx = 0;
+    while idx < chars.len() {
+        let pair = (chars[idx], chars.get(idx + 1).copied());
+        if *in_block_comment {
+            if pair == ('*', Some('/')) {
+                *in_block_comment = false;
+                comments.push(std::mem::take(&mut comment));
+                idx += 2;
+            } else {
+                comment.push(chars[idx]);
+                idx += 1;
+            }
+            continue;
+        }
+        if !in_string && pair == ('/', Some('*')) {
+            *in_block_comment = true;
+            idx += 2;
+            continue;
+        }
+        if !in_string && (pair == ('/', Some('/')) || pair.0 == '#') {
+            let marker = if pair.0 == '#' { 1 } else { 2 };
+            comments.push(chars[idx + marker..].iter().collect());
+            break;
+        }
+
+        // Comment markers inside a string or character literal are part of it
+        let width = if in_string && pair.0 == '\\' && pair.1.is_some() {
+            2
+        } else if !in_string && pair.0 == '\'' {
+            char_literal_width(&chars[idx..])
+        } else {
+            1
+        };
+        if pair.0 == '"' {
+            in_string = !in_string;
+        }
+        for (offset, c) in chars[idx..idx + width].iter().enumerate() {
+            if !c.is_whitespace() || in_string {
+                code_columns.push(idx + offset + 1);
+            }
+            code.push(*c);
+        }
+        idx += width;
+    }
+    if *in_block_comment {
+        comments.push(comment);
+    }
+
+    let columns = match (code_columns.first(), code_columns.last()) {
+        (Some(&first), Some(&last)) => first..last + 1,
+        _ => 0..0,
+    };
+    Line {
+        file: file.to_string(),
+        line,
+        code,
+        columns,
+        comments,
+    }
+}
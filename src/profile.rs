@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::cfg::Cfg;
+use crate::ir::{self, Instruction};
+use crate::vm::VM;
+
+/// Execution counts of one program line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineProfile {
+    /// 0-based index into the program.
+    pub line: usize,
+    pub text: String,
+    pub count: u64,
+    pub cycles: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockProfile {
+    /// Program lines `start..end`.
+    pub start: usize,
+    pub end: usize,
+    pub label: Option<String>,
+    /// Times control entered the block, i.e. executions of its first line.
+    pub entries: u64,
+    pub cycles: u64,
+}
+
+/// Where a run spent its time, collected from a VM after (or during) a run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    /// Every program line, in program order.
+    pub lines: Vec<LineProfile>,
+    /// Executions per opcode, most executed first. Each operation of a
+    /// bundle counts separately.
+    pub opcodes: Vec<(String, u64)>,
+    /// Basic blocks, most expensive first.
+    pub blocks: Vec<BlockProfile>,
+    pub total_cycles: u64,
+}
+
+impl Profile {
+    pub fn from_vm(vm: &VM) -> Self {
+        let program = vm.get_program();
+        let counts = vm.get_line_counts();
+        let cycles = vm.get_line_cycles();
+
+        let lines: Vec<LineProfile> = program
+            .iter()
+            .enumerate()
+            .map(|(line, text)| LineProfile {
+                line,
+                text: text.clone(),
+                count: counts.get(line).copied().unwrap_or(0),
+                cycles: cycles.get(line).copied().unwrap_or(0),
+            })
+            .collect();
+
+        let mut opcodes: HashMap<String, u64> = HashMap::new();
+        for line in lines.iter().filter(|line| line.count > 0) {
+            for operation in operations(&line.text) {
+                if !operation.is_label_only() {
+                    *opcodes.entry(operation.opcode).or_default() += line.count;
+                }
+            }
+        }
+        let mut opcodes: Vec<(String, u64)> = opcodes.into_iter().collect();
+        opcodes.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        // A bundle stands in the CFG as its branch, or else its first operation
+        let representatives: Vec<Instruction> = program
+            .iter()
+            .map(|text| {
+                let mut operations = operations(text);
+                let idx = operations
+                    .iter()
+                    .position(|op| ir::is_branch(&op.opcode))
+                    .unwrap_or(0);
+                let mut instruction = if operations.is_empty() {
+                    Instruction::new("nop", Vec::new(), Vec::new())
+                } else {
+                    operations.swap_remove(idx)
+                };
+                instruction.label = label(text);
+                instruction
+            })
+            .collect();
+        let mut blocks: Vec<BlockProfile> = Cfg::build(&representatives)
+            .blocks
+            .iter()
+            .map(|block| BlockProfile {
+                start: block.start,
+                end: block.end,
+                label: label(&program[block.start]),
+                entries: lines[block.start].count,
+                cycles: lines[block.range()].iter().map(|line| line.cycles).sum(),
+            })
+            .collect();
+        blocks.sort_by(|a, b| b.cycles.cmp(&a.cycles).then(a.start.cmp(&b.start)));
+
+        Self {
+            total_cycles: lines.iter().map(|line| line.cycles).sum(),
+            lines,
+            opcodes,
+            blocks,
+        }
+    }
+
+    /// Executed lines, most cycles first, then most executions.
+    pub fn hotspots(&self) -> Vec<&LineProfile> {
+        let mut hot: Vec<&LineProfile> = self.lines.iter().filter(|line| line.count > 0).collect();
+        hot.sort_by(|a, b| {
+            b.cycles
+                .cmp(&a.cycles)
+                .then(b.count.cmp(&a.count))
+                .then(a.line.cmp(&b.line))
+        });
+        hot
+    }
+
+    /// How hot `line` is relative to the hottest line, from 0.0 for a line
+    /// that never ran to 1.0.
+    pub fn heat(&self, line: usize) -> f64 {
+        let max = self.lines.iter().map(|l| l.cycles).max().unwrap_or(0);
+        match self.lines.get(line) {
+            Some(l) if max > 0 => l.cycles as f64 / max as f64,
+            _ => 0.0,
+        }
+    }
+
+    fn share(&self, cycles: u64) -> f64 {
+        if self.total_cycles == 0 {
+            return 0.0;
+        }
+        cycles as f64 * 100.0 / self.total_cycles as f64
+    }
+}
+
+fn operations(text: &str) -> Vec<Instruction> {
+    match ir::split_bundle(text) {
+        Some((_, operations)) => operations
+            .iter()
+            .filter_map(|op| Instruction::parse(op).ok())
+            .collect(),
+        None => Instruction::parse(text).ok().into_iter().collect(),
+    }
+}
+
+fn label(text: &str) -> Option<String> {
+    let first = text.split_whitespace().next()?;
+    first.strip_suffix(':').map(str::to_string)
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Hotspots:")?;
+        writeln!(
+            f,
+            "    {:>8} {:>6} {:>8} {:>5}  instruction",
+            "cycles", "%", "count", "line"
+        )?;
+        for line in self.hotspots() {
+            writeln!(
+                f,
+                "    {:>8} {:>5.1}% {:>8} {:>5}  {}",
+                line.cycles,
+                self.share(line.cycles),
+                line.count,
+                line.line + 1,
+                line.text
+            )?;
+        }
+
+        writeln!(f, "Opcodes:")?;
+        for (opcode, count) in &self.opcodes {
+            writeln!(f, "    {:>8}  {}", count, opcode)?;
+        }
+
+        writeln!(f, "Blocks:")?;
+        writeln!(
+            f,
+            "    {:>8} {:>6} {:>8}  {:<9} label",
+            "cycles", "%", "entries", "lines"
+        )?;
+        for block in &self.blocks {
+            let lines = format!("{}-{}", block.start + 1, block.end);
+            let label = block.label.as_deref().unwrap_or("");
+            let row = format!(
+                "    {:>8} {:>5.1}% {:>8}  {:<9} {}",
+                block.cycles,
+                self.share(block.cycles),
+                block.entries,
+                lines,
+                label
+            );
+            writeln!(f, "{}", row.trim_end())?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,696 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::cfg::{Cfg, Liveness};
+use crate::ir::{self, Instruction, Operand};
+use crate::machine::WordSize;
+
+/// Register reserved for the activation record pointer. It is never renamed
+/// and does not count against the `k` physical registers.
+pub const ARP: &str = "rarp";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// Local allocator that dedicates registers to the most frequently used
+    /// values of each block and spills the rest around every reference.
+    TopDown,
+    /// Local allocator that assigns registers on demand and evicts the value
+    /// whose next use is farthest away.
+    BottomUp,
+    /// Global Chaitin-Briggs allocator over the interference graph.
+    GraphColoring,
+}
+
+#[derive(Debug, Clone)]
+pub struct AllocatorConfig {
+    pub registers: usize,
+    pub strategy: Strategy,
+    /// Address loaded into `rarp` when spill code is needed but the program
+    /// never defines `rarp` itself. Spill slots are at `rarp + 0`, `rarp + 4`, ...
+    /// for 32-bit words, and 8 bytes apart for 64-bit ones.
+    pub spill_base: i64,
+    /// Word size of the machine the allocated code runs on, which is the
+    /// size of a spill slot.
+    pub word_size: WordSize,
+}
+
+impl AllocatorConfig {
+    pub fn new(registers: usize, strategy: Strategy) -> Self {
+        Self {
+            registers,
+            strategy,
+            spill_base: 512,
+            word_size: WordSize::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Allocation {
+    pub program: Vec<String>,
+    /// Number of distinct virtual registers that needed spill code.
+    pub spilled: usize,
+    pub spill_loads: usize,
+    pub spill_stores: usize,
+}
+
+/// Rewrites `program` to use at most `config.registers` physical registers,
+/// named `r0` to `r{k-1}`, inserting `loadAI`/`storeAI` spill code relative
+/// to `rarp` where values do not fit.
+pub fn allocate(program: &[String], config: &AllocatorConfig) -> Result<Allocation, String> {
+    let instructions = ir::parse_program(program)?;
+    let k = config.registers;
+
+    let demand = instructions.iter().map(register_demand).max().unwrap_or(0);
+    if k < demand.max(1) {
+        return Err(format!(
+            "At least {} registers are required, got {}",
+            demand.max(1),
+            k
+        ));
+    }
+
+    let mut spill = SpillCode {
+        slot_size: config.word_size.bytes() as i64,
+        ..SpillCode::default()
+    };
+    let mut allocated = match config.strategy {
+        Strategy::TopDown => allocate_local(&instructions, k, &mut spill, top_down_block),
+        Strategy::BottomUp => allocate_local(&instructions, k, &mut spill, bottom_up_block),
+        Strategy::GraphColoring => graph_coloring(instructions.clone(), k, &mut spill),
+    };
+
+    let defines_arp = instructions.iter().any(|inst| inst.defs().contains(&ARP));
+    if !spill.spilled.is_empty() && !defines_arp {
+        allocated.insert(
+            0,
+            Instruction::new(
+                "loadI",
+                vec![Operand::Immediate(config.spill_base)],
+                vec![Operand::Register(ARP.to_string())],
+            ),
+        );
+    }
+
+    Ok(Allocation {
+        program: ir::to_program(&allocated),
+        spilled: spill.spilled.len(),
+        spill_loads: spill.loads,
+        spill_stores: spill.stores,
+    })
+}
+
+fn physical(index: usize) -> String {
+    format!("r{}", index)
+}
+
+/// Float registers are a class of their own and keep their names.
+fn allocatable(reg: &str) -> bool {
+    reg != ARP && !ir::is_float_register(reg)
+}
+
+/// Distinct allocatable registers an instruction touches at once.
+fn register_demand(inst: &Instruction) -> usize {
+    let mut regs: HashSet<&str> = inst.uses().into_iter().collect();
+    regs.extend(inst.defs());
+    regs.into_iter().filter(|reg| allocatable(reg)).count()
+}
+
+fn distinct_uses(inst: &Instruction) -> Vec<String> {
+    let mut seen = Vec::new();
+    for reg in inst.uses() {
+        if allocatable(reg) && !seen.iter().any(|s: &String| s == reg) {
+            seen.push(reg.to_string());
+        }
+    }
+    seen
+}
+
+fn allocatable_defs(inst: &Instruction) -> Vec<String> {
+    inst.defs()
+        .into_iter()
+        .filter(|reg| allocatable(reg))
+        .map(str::to_string)
+        .collect()
+}
+
+#[derive(Default)]
+struct SpillCode {
+    /// Bytes between consecutive slots.
+    slot_size: i64,
+    slots: HashMap<String, i64>,
+    spilled: HashSet<String>,
+    loads: usize,
+    stores: usize,
+}
+
+impl SpillCode {
+    fn slot(&mut self, vreg: &str) -> i64 {
+        let next = self.slots.len() as i64 * self.slot_size;
+        *self.slots.entry(vreg.to_string()).or_insert(next)
+    }
+
+    fn load(&mut self, vreg: &str, preg: &str) -> Instruction {
+        let slot = self.slot(vreg);
+        self.spilled.insert(vreg.to_string());
+        self.loads += 1;
+        Instruction::new(
+            "loadAI",
+            vec![Operand::Register(ARP.to_string()), Operand::Immediate(slot)],
+            vec![Operand::Register(preg.to_string())],
+        )
+    }
+
+    fn store(&mut self, vreg: &str, preg: &str) -> Instruction {
+        let slot = self.slot(vreg);
+        self.spilled.insert(vreg.to_string());
+        self.stores += 1;
+        Instruction::new(
+            "storeAI",
+            vec![Operand::Register(preg.to_string())],
+            vec![Operand::Register(ARP.to_string()), Operand::Immediate(slot)],
+        )
+    }
+}
+
+/// Appends `inst` with its surrounding spill code to `out`, moving any label
+/// on `inst` to the first emitted instruction so branches still reach it.
+fn emit(
+    out: &mut Vec<Instruction>,
+    before: Vec<Instruction>,
+    mut inst: Instruction,
+    after: Vec<Instruction>,
+) {
+    let mut group = before;
+    let label = if group.is_empty() {
+        None
+    } else {
+        inst.label.take()
+    };
+    group.push(inst);
+    group.extend(after);
+    if label.is_some() {
+        group[0].label = label;
+    }
+    out.extend(group);
+}
+
+/// Whether the value in `vreg` after instruction `idx` is read again, either
+/// later in the block (before being redefined) or by a successor block.
+fn needed_after(
+    instructions: &[Instruction],
+    idx: usize,
+    end: usize,
+    vreg: &str,
+    live_out: &HashSet<String>,
+) -> bool {
+    for inst in &instructions[idx + 1..end] {
+        if inst.uses().contains(&vreg) {
+            return true;
+        }
+        if inst.defs().contains(&vreg) {
+            return false;
+        }
+    }
+    live_out.contains(vreg)
+}
+
+/// Distance to the next use of `vreg` after instruction `idx`, or `usize::MAX`.
+fn next_use(instructions: &[Instruction], idx: usize, end: usize, vreg: &str) -> usize {
+    instructions[idx + 1..end]
+        .iter()
+        .position(|inst| inst.uses().contains(&vreg))
+        .unwrap_or(usize::MAX)
+}
+
+type BlockAllocator = fn(
+    &[Instruction],
+    std::ops::Range<usize>,
+    &HashSet<String>,
+    usize,
+    &mut SpillCode,
+    &mut Vec<Instruction>,
+);
+
+/// Runs a local allocator over every block. Registers are empty on block
+/// entry and values live across blocks travel through their spill slots.
+fn allocate_local(
+    instructions: &[Instruction],
+    k: usize,
+    spill: &mut SpillCode,
+    block_allocator: BlockAllocator,
+) -> Vec<Instruction> {
+    let cfg = Cfg::build(instructions);
+    let liveness = Liveness::compute(instructions, &cfg);
+    let mut out = Vec::new();
+    for (b, block) in cfg.blocks.iter().enumerate() {
+        block_allocator(
+            instructions,
+            block.range(),
+            &liveness.live_out[b],
+            k,
+            spill,
+            &mut out,
+        );
+    }
+    out
+}
+
+fn top_down_block(
+    instructions: &[Instruction],
+    range: std::ops::Range<usize>,
+    live_out: &HashSet<String>,
+    k: usize,
+    spill: &mut SpillCode,
+    out: &mut Vec<Instruction>,
+) {
+    // Rank the block's values by how often they are referenced
+    let mut order: Vec<String> = Vec::new();
+    let mut frequency: HashMap<String, usize> = HashMap::new();
+    for inst in &instructions[range.clone()] {
+        for reg in inst.uses().into_iter().chain(inst.defs()) {
+            if !allocatable(reg) {
+                continue;
+            }
+            if !frequency.contains_key(reg) {
+                order.push(reg.to_string());
+            }
+            *frequency.entry(reg.to_string()).or_insert(0) += 1;
+        }
+    }
+
+    let feasible = if order.len() <= k {
+        0
+    } else {
+        instructions[range.clone()]
+            .iter()
+            .map(register_demand)
+            .max()
+            .unwrap_or(0)
+    };
+    let mut ranked = order.clone();
+    ranked.sort_by_key(|reg| std::cmp::Reverse(frequency[reg]));
+
+    let dedicated: HashMap<String, String> = ranked
+        .iter()
+        .take(k - feasible)
+        .enumerate()
+        .map(|(idx, reg)| (reg.clone(), physical(idx)))
+        .collect();
+    let scratch: Vec<String> = (k - feasible..k).map(physical).collect();
+
+    let mut loaded: HashSet<String> = HashSet::new();
+    let mut dirty: HashSet<String> = HashSet::new();
+    let last = range.end - 1;
+
+    for idx in range.clone() {
+        let inst = &instructions[idx];
+        let mut before = Vec::new();
+        let mut after = Vec::new();
+        let mut use_map: HashMap<String, String> = HashMap::new();
+        let mut def_map: HashMap<String, String> = HashMap::new();
+
+        let mut next_scratch = 0;
+        for vreg in distinct_uses(inst) {
+            if let Some(preg) = dedicated.get(&vreg) {
+                if !loaded.contains(&vreg) {
+                    before.push(spill.load(&vreg, preg));
+                    loaded.insert(vreg.clone());
+                }
+                use_map.insert(vreg, preg.clone());
+            } else {
+                let preg = scratch[next_scratch].clone();
+                next_scratch += 1;
+                before.push(spill.load(&vreg, &preg));
+                use_map.insert(vreg, preg);
+            }
+        }
+
+        for vreg in allocatable_defs(inst) {
+            if let Some(preg) = dedicated.get(&vreg) {
+                loaded.insert(vreg.clone());
+                dirty.insert(vreg.clone());
+                def_map.insert(vreg, preg.clone());
+            } else {
+                let preg = scratch[0].clone();
+                if needed_after(instructions, idx, range.end, &vreg, live_out) {
+                    after.push(spill.store(&vreg, &preg));
+                }
+                def_map.insert(vreg, preg);
+            }
+        }
+
+        let mut rewritten = inst.clone();
+        rewritten.rename_uses_and_defs(
+            |reg| use_map.get(reg).cloned().unwrap_or_else(|| reg.to_string()),
+            |reg| def_map.get(reg).cloned().unwrap_or_else(|| reg.to_string()),
+        );
+
+        if idx == last {
+            let write_back: Vec<Instruction> = order
+                .iter()
+                .filter(|vreg| dirty.contains(*vreg) && live_out.contains(*vreg))
+                .map(|vreg| spill.store(vreg, &dedicated[vreg]))
+                .collect();
+            if ir::is_branch(&inst.opcode) {
+                before.extend(write_back);
+            } else {
+                after.extend(write_back);
+            }
+        }
+
+        emit(out, before, rewritten, after);
+    }
+}
+
+fn bottom_up_block(
+    instructions: &[Instruction],
+    range: std::ops::Range<usize>,
+    live_out: &HashSet<String>,
+    k: usize,
+    spill: &mut SpillCode,
+    out: &mut Vec<Instruction>,
+) {
+    // Physical register contents and whether they differ from the spill slot
+    let mut contents: Vec<Option<String>> = vec![None; k];
+    let mut dirty = vec![false; k];
+    let last = range.end - 1;
+
+    for idx in range.clone() {
+        let inst = &instructions[idx];
+        let mut before = Vec::new();
+        let mut after = Vec::new();
+        let mut use_map: HashMap<String, String> = HashMap::new();
+        let mut def_map: HashMap<String, String> = HashMap::new();
+        // Operands already in registers must survive until the instruction executes
+        let uses = distinct_uses(inst);
+        let mut pinned: HashSet<usize> = (0..k)
+            .filter(|&preg| {
+                contents[preg]
+                    .as_ref()
+                    .is_some_and(|vreg| uses.contains(vreg))
+            })
+            .collect();
+
+        for vreg in uses {
+            let preg = match contents
+                .iter()
+                .position(|c| c.as_deref() == Some(vreg.as_str()))
+            {
+                Some(preg) => preg,
+                None => {
+                    let preg = bottom_up_claim(
+                        instructions,
+                        idx,
+                        range.end,
+                        live_out,
+                        &mut contents,
+                        &mut dirty,
+                        &pinned,
+                        spill,
+                        &mut before,
+                    );
+                    before.push(spill.load(&vreg, &physical(preg)));
+                    contents[preg] = Some(vreg.clone());
+                    dirty[preg] = false;
+                    preg
+                }
+            };
+            pinned.insert(preg);
+            use_map.insert(vreg, physical(preg));
+        }
+
+        // Operands that die here free their registers for the result
+        for preg in 0..k {
+            if let Some(vreg) = contents[preg].clone() {
+                if pinned.contains(&preg)
+                    && !needed_after(instructions, idx, range.end, &vreg, live_out)
+                {
+                    contents[preg] = None;
+                    dirty[preg] = false;
+                    pinned.remove(&preg);
+                }
+            }
+        }
+
+        for vreg in allocatable_defs(inst) {
+            let preg = match contents
+                .iter()
+                .position(|c| c.as_deref() == Some(vreg.as_str()))
+            {
+                Some(preg) => preg,
+                None => bottom_up_claim(
+                    instructions,
+                    idx,
+                    range.end,
+                    live_out,
+                    &mut contents,
+                    &mut dirty,
+                    &pinned,
+                    spill,
+                    &mut before,
+                ),
+            };
+            contents[preg] = Some(vreg.clone());
+            dirty[preg] = true;
+            pinned.insert(preg);
+            def_map.insert(vreg.clone(), physical(preg));
+
+            if !needed_after(instructions, idx, range.end, &vreg, live_out) {
+                contents[preg] = None;
+                dirty[preg] = false;
+            }
+        }
+
+        let mut rewritten = inst.clone();
+        rewritten.rename_uses_and_defs(
+            |reg| use_map.get(reg).cloned().unwrap_or_else(|| reg.to_string()),
+            |reg| def_map.get(reg).cloned().unwrap_or_else(|| reg.to_string()),
+        );
+
+        if idx == last {
+            let mut write_back = Vec::new();
+            for preg in 0..k {
+                if let Some(vreg) = &contents[preg] {
+                    if dirty[preg] && live_out.contains(vreg) {
+                        write_back.push(spill.store(vreg, &physical(preg)));
+                    }
+                }
+            }
+            if ir::is_branch(&inst.opcode) {
+                before.extend(write_back);
+            } else {
+                after.extend(write_back);
+            }
+        }
+
+        emit(out, before, rewritten, after);
+    }
+}
+
+/// Finds a free physical register, evicting the unpinned value whose next
+/// use is farthest away when none is free.
+#[allow(clippy::too_many_arguments)]
+fn bottom_up_claim(
+    instructions: &[Instruction],
+    idx: usize,
+    end: usize,
+    live_out: &HashSet<String>,
+    contents: &mut [Option<String>],
+    dirty: &mut [bool],
+    pinned: &HashSet<usize>,
+    spill: &mut SpillCode,
+    before: &mut Vec<Instruction>,
+) -> usize {
+    if let Some(free) = contents.iter().position(Option::is_none) {
+        return free;
+    }
+
+    let victim = (0..contents.len())
+        .filter(|preg| !pinned.contains(preg))
+        .max_by_key(|&preg| next_use(instructions, idx, end, contents[preg].as_deref().unwrap()))
+        .expect("register demand exceeds available registers");
+
+    let vreg = contents[victim].take().unwrap();
+    if dirty[victim] && needed_after(instructions, idx, end, &vreg, live_out) {
+        before.push(spill.store(&vreg, &physical(victim)));
+    }
+    dirty[victim] = false;
+    victim
+}
+
+fn graph_coloring(
+    mut instructions: Vec<Instruction>,
+    k: usize,
+    spill: &mut SpillCode,
+) -> Vec<Instruction> {
+    let mut unspillable: HashSet<String> = HashSet::new();
+    let mut temp_count = 0;
+
+    loop {
+        let cfg = Cfg::build(&instructions);
+        let liveness = Liveness::compute(&instructions, &cfg);
+        let graph = interference_graph(&instructions, &cfg, &liveness);
+
+        let mut cost: HashMap<&str, usize> = HashMap::new();
+        for inst in &instructions {
+            for reg in inst.uses().into_iter().chain(inst.defs()) {
+                *cost.entry(reg).or_insert(0) += 1;
+            }
+        }
+
+        let (colors, spills) = color(&graph, k, |reg| {
+            if unspillable.contains(reg) {
+                usize::MAX
+            } else {
+                cost.get(reg).copied().unwrap_or(0)
+            }
+        });
+
+        if spills.is_empty() {
+            for inst in instructions.iter_mut() {
+                inst.rename_uses_and_defs(
+                    |reg| {
+                        colors
+                            .get(reg)
+                            .map(|&c| physical(c))
+                            .unwrap_or_else(|| reg.to_string())
+                    },
+                    |reg| {
+                        colors
+                            .get(reg)
+                            .map(|&c| physical(c))
+                            .unwrap_or_else(|| reg.to_string())
+                    },
+                );
+            }
+            return instructions;
+        }
+
+        // Spill everywhere: each reference gets its own short-lived temporary
+        let mut rewritten = Vec::new();
+        for inst in instructions {
+            let mut before = Vec::new();
+            let mut after = Vec::new();
+            let mut use_map: HashMap<String, String> = HashMap::new();
+            let mut def_map: HashMap<String, String> = HashMap::new();
+
+            for vreg in distinct_uses(&inst) {
+                if spills.contains(&vreg) {
+                    let temp = format!("r_spill{}", temp_count);
+                    temp_count += 1;
+                    before.push(spill.load(&vreg, &temp));
+                    unspillable.insert(temp.clone());
+                    use_map.insert(vreg, temp);
+                }
+            }
+            for vreg in allocatable_defs(&inst) {
+                if spills.contains(&vreg) {
+                    let temp = format!("r_spill{}", temp_count);
+                    temp_count += 1;
+                    after.push(spill.store(&vreg, &temp));
+                    unspillable.insert(temp.clone());
+                    def_map.insert(vreg, temp);
+                }
+            }
+
+            let mut inst = inst;
+            inst.rename_uses_and_defs(
+                |reg| use_map.get(reg).cloned().unwrap_or_else(|| reg.to_string()),
+                |reg| def_map.get(reg).cloned().unwrap_or_else(|| reg.to_string()),
+            );
+            emit(&mut rewritten, before, inst, after);
+        }
+        instructions = rewritten;
+    }
+}
+
+fn interference_graph(
+    instructions: &[Instruction],
+    cfg: &Cfg,
+    liveness: &Liveness,
+) -> HashMap<String, HashSet<String>> {
+    let mut graph: HashMap<String, HashSet<String>> = HashMap::new();
+    for inst in instructions {
+        for reg in inst.uses().into_iter().chain(inst.defs()) {
+            if allocatable(reg) {
+                graph.entry(reg.to_string()).or_default();
+            }
+        }
+    }
+
+    for b in 0..cfg.blocks.len() {
+        let live_after = liveness.live_after(instructions, cfg, b);
+        for (offset, idx) in cfg.blocks[b].range().enumerate() {
+            let inst = &instructions[idx];
+            // A copy does not make its source and destination interfere
+            let copy_source = if inst.opcode == "i2i" {
+                inst.uses().first().copied()
+            } else {
+                None
+            };
+            for def in allocatable_defs(inst) {
+                for live in &live_after[offset] {
+                    if *live == def || !allocatable(live) || Some(live.as_str()) == copy_source {
+                        continue;
+                    }
+                    graph.get_mut(&def).unwrap().insert(live.clone());
+                    graph.get_mut(live).unwrap().insert(def.clone());
+                }
+            }
+        }
+    }
+    graph
+}
+
+/// Briggs-style optimistic coloring. Returns the colors assigned and the
+/// registers that must be spilled.
+fn color<F: Fn(&str) -> usize>(
+    graph: &HashMap<String, HashSet<String>>,
+    k: usize,
+    spill_cost: F,
+) -> (HashMap<String, usize>, Vec<String>) {
+    let mut remaining: HashSet<&str> = graph.keys().map(String::as_str).collect();
+    let mut stack: Vec<&str> = Vec::new();
+
+    let degree = |node: &str, remaining: &HashSet<&str>| {
+        graph[node]
+            .iter()
+            .filter(|n| remaining.contains(n.as_str()))
+            .count()
+    };
+
+    while !remaining.is_empty() {
+        let mut nodes: Vec<&str> = remaining.iter().copied().collect();
+        nodes.sort_unstable();
+        let node = match nodes.iter().find(|node| degree(node, &remaining) < k) {
+            Some(node) => *node,
+            None => *nodes
+                .iter()
+                .min_by(|a, b| {
+                    let ratio =
+                        |n: &str| spill_cost(n) as f64 / degree(n, &remaining).max(1) as f64;
+                    ratio(a).total_cmp(&ratio(b))
+                })
+                .unwrap(),
+        };
+        remaining.remove(node);
+        stack.push(node);
+    }
+
+    let mut colors: HashMap<String, usize> = HashMap::new();
+    let mut spills = Vec::new();
+    while let Some(node) = stack.pop() {
+        let taken: HashSet<usize> = graph[node]
+            .iter()
+            .filter_map(|n| colors.get(n).copied())
+            .collect();
+        match (0..k).find(|c| !taken.contains(c)) {
+            Some(c) => {
+                colors.insert(node.to_string(), c);
+            }
+            None => spills.push(node.to_string()),
+        }
+    }
+    spills.sort();
+    (colors, spills)
+}
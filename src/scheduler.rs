@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+
+use crate::cfg::Cfg;
+use crate::ir::{self, Instruction};
+use crate::latency::LatencyTable;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dependence {
+    /// Read after write: the consumer needs the producer's result.
+    True,
+    /// Write after read: the writer must not clobber a value still to be read.
+    Anti,
+    /// Write after write: the later write must land last.
+    Output,
+    /// Ordering between memory operations, which may alias.
+    Memory,
+    /// Everything in a block must issue before its closing branch.
+    Control,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    pub kind: Dependence,
+    /// Minimum number of cycles between issuing `from` and issuing `to`.
+    pub latency: u64,
+}
+
+/// Dependence DAG over the instructions of one basic block. Node `i` is the
+/// block's `i`-th instruction.
+#[derive(Debug, Clone)]
+pub struct DependenceGraph {
+    pub edges: Vec<Edge>,
+    /// Latency-weighted length of the longest path from each node to a root.
+    pub priorities: Vec<u64>,
+}
+
+impl DependenceGraph {
+    pub fn build(block: &[Instruction], latencies: &LatencyTable) -> Self {
+        let mut edges = Vec::new();
+        let mut last_def: HashMap<&str, usize> = HashMap::new();
+        let mut uses_since_def: HashMap<&str, Vec<usize>> = HashMap::new();
+        let mut last_store: Option<usize> = None;
+        let mut loads_since_store: Vec<usize> = Vec::new();
+
+        let mut add = |from: usize, to: usize, kind: Dependence, latency: u64| {
+            if from != to {
+                edges.push(Edge {
+                    from,
+                    to,
+                    kind,
+                    latency,
+                });
+            }
+        };
+
+        for (idx, inst) in block.iter().enumerate() {
+            for reg in inst.uses() {
+                if let Some(&def) = last_def.get(reg) {
+                    add(
+                        def,
+                        idx,
+                        Dependence::True,
+                        latencies.get(&block[def].opcode),
+                    );
+                }
+            }
+
+            if ir::is_load(&inst.opcode) {
+                if let Some(store) = last_store {
+                    add(
+                        store,
+                        idx,
+                        Dependence::Memory,
+                        latencies.get(&block[store].opcode),
+                    );
+                }
+            }
+            if ir::is_store(&inst.opcode) || ir::is_output(&inst.opcode) {
+                for &load in &loads_since_store {
+                    add(load, idx, Dependence::Memory, 0);
+                }
+                if let Some(store) = last_store {
+                    add(store, idx, Dependence::Memory, 1);
+                }
+            }
+
+            for reg in inst.defs() {
+                for &reader in uses_since_def.get(reg).into_iter().flatten() {
+                    add(reader, idx, Dependence::Anti, 0);
+                }
+                if let Some(&def) = last_def.get(reg) {
+                    add(def, idx, Dependence::Output, 1);
+                }
+            }
+
+            if ir::is_branch(&inst.opcode) {
+                for earlier in 0..idx {
+                    add(earlier, idx, Dependence::Control, 0);
+                }
+            }
+
+            // Record this instruction's effects for the ones that follow
+            for reg in inst.uses() {
+                uses_since_def.entry(reg).or_default().push(idx);
+            }
+            for reg in inst.defs() {
+                last_def.insert(reg, idx);
+                uses_since_def.insert(reg, Vec::new());
+            }
+            if ir::is_load(&inst.opcode) {
+                loads_since_store.push(idx);
+            }
+            if ir::is_store(&inst.opcode) || ir::is_output(&inst.opcode) {
+                last_store = Some(idx);
+                loads_since_store.clear();
+            }
+        }
+
+        let mut priorities: Vec<u64> = block
+            .iter()
+            .map(|inst| latencies.get(&inst.opcode))
+            .collect();
+        // Edges always point forward, so a reverse sweep visits successors first
+        for idx in (0..block.len()).rev() {
+            for edge in edges.iter().filter(|edge| edge.from == idx) {
+                priorities[idx] = priorities[idx].max(edge.latency + priorities[edge.to]);
+            }
+        }
+
+        Self { edges, priorities }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    pub program: Vec<String>,
+    /// Issue cycle of each instruction of `program`, relative to its block.
+    pub issue_cycles: Vec<u64>,
+    /// Estimated cycles to run every block once, back to back.
+    pub length: u64,
+}
+
+impl Schedule {
+    /// The schedule as VLIW bundles: instructions of a block that issue in
+    /// the same cycle are grouped into one `[ op1 ; op2 ]` line.
+    pub fn bundled_program(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut group: Vec<Instruction> = Vec::new();
+        let mut group_cycle = 0;
+
+        for (line, &cycle) in self.program.iter().zip(&self.issue_cycles) {
+            let inst = Instruction::parse(line).expect("scheduled programs are well formed");
+            let starts_block = inst.label.is_some()
+                || group
+                    .last()
+                    .is_some_and(|prev| ir::is_branch(&prev.opcode) || prev.is_label_only());
+            if !group.is_empty() && (starts_block || cycle != group_cycle) {
+                lines.push(bundle_line(std::mem::take(&mut group)));
+            }
+            group_cycle = cycle;
+            group.push(inst);
+        }
+        if !group.is_empty() {
+            lines.push(bundle_line(group));
+        }
+        lines
+    }
+}
+
+fn bundle_line(mut group: Vec<Instruction>) -> String {
+    if group.len() == 1 {
+        return group[0].to_string();
+    }
+
+    let label = group[0].label.take();
+    let operations: Vec<String> = group.iter().map(|inst| inst.to_string()).collect();
+    let bundle = format!("[ {} ]", operations.join(" ; "));
+    match label {
+        Some(label) => format!("{}: {}", label, bundle),
+        None => bundle,
+    }
+}
+
+/// Reorders the instructions of every basic block of `program` with a list
+/// scheduler that issues up to `units` instructions per cycle, preferring the
+/// ready instruction on the longest latency-weighted path.
+pub fn schedule(
+    program: &[String],
+    latencies: &LatencyTable,
+    units: usize,
+) -> Result<Schedule, String> {
+    if units == 0 {
+        return Err("At least one functional unit is required".to_string());
+    }
+
+    let instructions = ir::parse_program(program)?;
+    let cfg = Cfg::build(&instructions);
+
+    let mut scheduled = Vec::new();
+    let mut issue_cycles = Vec::new();
+    let mut length = 0;
+
+    for block in &cfg.blocks {
+        let mut body: Vec<Instruction> = instructions[block.range()].to_vec();
+
+        // The block label stays on whichever instruction ends up first
+        let mut label = body[0].label.take();
+        if body[0].is_label_only() {
+            let mut marker = body.remove(0);
+            marker.label = label.take();
+            scheduled.push(marker);
+            issue_cycles.push(0);
+        }
+        if body.is_empty() {
+            continue;
+        }
+
+        let (order, cycles, block_length) = schedule_block(&body, latencies, units);
+        push_block(
+            &mut scheduled,
+            &mut issue_cycles,
+            &body,
+            &order,
+            &cycles,
+            label,
+        );
+        length += block_length;
+    }
+
+    Ok(Schedule {
+        program: ir::to_program(&scheduled),
+        issue_cycles,
+        length,
+    })
+}
+
+fn push_block(
+    scheduled: &mut Vec<Instruction>,
+    issue_cycles: &mut Vec<u64>,
+    body: &[Instruction],
+    order: &[usize],
+    cycles: &[u64],
+    label: Option<String>,
+) {
+    let start = scheduled.len();
+    for &idx in order {
+        scheduled.push(body[idx].clone());
+        issue_cycles.push(cycles[idx]);
+    }
+    scheduled[start].label = label;
+}
+
+/// Returns the issue order, each node's issue cycle and the block's length.
+fn schedule_block(
+    body: &[Instruction],
+    latencies: &LatencyTable,
+    units: usize,
+) -> (Vec<usize>, Vec<u64>, u64) {
+    let graph = DependenceGraph::build(body, latencies);
+    let count = body.len();
+
+    let mut predecessors = vec![0usize; count];
+    for edge in &graph.edges {
+        predecessors[edge.to] += 1;
+    }
+
+    let mut earliest = vec![0u64; count];
+    let mut cycles = vec![0u64; count];
+    let mut ready: Vec<usize> = (0..count).filter(|&idx| predecessors[idx] == 0).collect();
+    let mut order = Vec::with_capacity(count);
+    let mut cycle = 0;
+
+    while order.len() < count {
+        let mut issued = 0;
+        loop {
+            // Highest priority first; ties keep the original program order
+            let candidate = ready
+                .iter()
+                .copied()
+                .filter(|&idx| earliest[idx] <= cycle)
+                .max_by_key(|&idx| (graph.priorities[idx], std::cmp::Reverse(idx)));
+            let Some(idx) = candidate else { break };
+            if issued == units {
+                break;
+            }
+
+            ready.retain(|&other| other != idx);
+            order.push(idx);
+            cycles[idx] = cycle;
+            issued += 1;
+
+            // Successors joined by zero-latency edges may issue in this same cycle
+            for edge in graph.edges.iter().filter(|edge| edge.from == idx) {
+                earliest[edge.to] = earliest[edge.to].max(cycle + edge.latency);
+                predecessors[edge.to] -= 1;
+                if predecessors[edge.to] == 0 {
+                    ready.push(edge.to);
+                }
+            }
+        }
+        cycle += 1;
+    }
+
+    let length = (0..count)
+        .map(|idx| cycles[idx] + latencies.get(&body[idx].opcode))
+        .max()
+        .unwrap_or(0);
+    (order, cycles, length)
+}
@@ -0,0 +1,384 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use crate::cfg::{Cfg, Dominators, Liveness};
+use crate::ir::{self, Instruction, Operand};
+use crate::opt;
+
+/// Converts `program` to pruned SSA form.
+///
+/// Every register is renamed so that it has exactly one definition, and
+/// `phi` instructions merge the versions reaching a join point. A `phi` pairs
+/// each incoming register with the label of the predecessor block it flows
+/// in from:
+///
+/// ```text
+/// L2: phi r1_1,L0,r1_2,L1 => r1_3
+/// ```
+///
+/// Predecessors without a label get a fresh one, and a program whose entry
+/// block is a branch target gets a leading `nop` so that the entry has no
+/// predecessors. Registers read before any definition keep their original
+/// name. The VM executes the result directly.
+pub fn to_ssa(program: &[String]) -> Result<Vec<String>, String> {
+    let mut instructions = ir::parse_program(program)?;
+    if Cfg::build(&instructions)
+        .blocks
+        .first()
+        .is_some_and(|entry| !entry.predecessors.is_empty())
+    {
+        instructions.insert(0, Instruction::new("nop", Vec::new(), Vec::new()));
+    }
+
+    let cfg = Cfg::build(&instructions);
+    let dominators = Dominators::compute(&cfg);
+    let liveness = Liveness::compute(&instructions, &cfg);
+    let mut names = Names::new(&instructions);
+
+    // Place a phi for a register at each join point in the iterated
+    // dominance frontier of its definitions where it is still live
+    let mut defined_in: BTreeMap<String, BTreeSet<usize>> = BTreeMap::new();
+    for (b, block) in cfg.blocks.iter().enumerate() {
+        if dominators.idom[b].is_none() {
+            continue;
+        }
+        for inst in &instructions[block.range()] {
+            for reg in inst.defs() {
+                defined_in.entry(reg.to_string()).or_default().insert(b);
+            }
+        }
+    }
+    let mut phis: Vec<Vec<String>> = vec![Vec::new(); cfg.blocks.len()];
+    for (reg, blocks) in &defined_in {
+        let mut worklist: Vec<usize> = blocks.iter().copied().collect();
+        let mut queued: HashSet<usize> = blocks.iter().copied().collect();
+        while let Some(b) = worklist.pop() {
+            for &join in &dominators.frontiers[b] {
+                if phis[join].contains(reg) || !liveness.live_in[join].contains(reg) {
+                    continue;
+                }
+                phis[join].push(reg.clone());
+                if queued.insert(join) {
+                    worklist.push(join);
+                }
+            }
+        }
+    }
+
+    // Every predecessor of a block with phis needs a label to be named by them
+    let mut labels: Vec<Option<String>> = cfg
+        .blocks
+        .iter()
+        .map(|block| instructions[block.start].label.clone())
+        .collect();
+    for (b, block) in cfg.blocks.iter().enumerate() {
+        if phis[b].is_empty() {
+            continue;
+        }
+        for &pred in &block.predecessors {
+            if labels[pred].is_none() {
+                let label = names.label(pred);
+                instructions[cfg.blocks[pred].start].label = Some(label.clone());
+                labels[pred] = Some(label);
+            }
+        }
+    }
+
+    let mut blocks: Vec<Vec<Instruction>> = Vec::new();
+    for (b, block) in cfg.blocks.iter().enumerate() {
+        let mut body: Vec<Instruction> = phis[b]
+            .iter()
+            .map(|reg| {
+                let mut sources = Vec::new();
+                for &pred in &cfg.blocks[b].predecessors {
+                    sources.push(Operand::Register(reg.clone()));
+                    sources.push(Operand::Label(labels[pred].clone().unwrap()));
+                }
+                Instruction::new("phi", sources, vec![Operand::Register(reg.clone())])
+            })
+            .collect();
+        let mut original = instructions[block.range()].to_vec();
+        if !body.is_empty() {
+            // The block's label moves up to its first phi
+            body[0].label = original[0].label.take();
+            if original[0].is_label_only() {
+                original.remove(0);
+            }
+        }
+        body.extend(original);
+        blocks.push(body);
+    }
+
+    let mut renamer = Renamer {
+        cfg: &cfg,
+        dominators: &dominators,
+        labels: &labels,
+        names: &mut names,
+        stacks: HashMap::new(),
+    };
+    if !blocks.is_empty() {
+        renamer.rename(0, &mut blocks);
+    }
+
+    Ok(ir::to_program(&blocks.concat()))
+}
+
+/// Translates a program containing `phi` instructions back to plain ILOC.
+///
+/// Each `phi` becomes a copy at the end of the predecessor it names. Edges
+/// from a block with several successors into a block with `phi`s are split
+/// first, so a copy never runs on a path that leaves for another block (the
+/// lost-copy problem). The copies on one edge are ordered so that no register
+/// is overwritten before every copy has read it, using a temporary to break
+/// cycles such as a swap. Copies of registers the program never defines are
+/// dropped, since their value is undefined on that edge.
+pub fn from_ssa(program: &[String]) -> Result<Vec<String>, String> {
+    let instructions = ir::parse_program(program)?;
+    let cfg = Cfg::build(&instructions);
+    let mut names = Names::new(&instructions);
+    let defined: HashSet<&str> = instructions.iter().flat_map(|inst| inst.defs()).collect();
+
+    let mut blocks: Vec<Vec<Instruction>> = cfg
+        .blocks
+        .iter()
+        .map(|block| instructions[block.range()].to_vec())
+        .collect();
+    let block_of_label: HashMap<&str, usize> = cfg
+        .blocks
+        .iter()
+        .enumerate()
+        .filter_map(|(b, block)| Some((instructions[block.start].label.as_deref()?, b)))
+        .collect();
+
+    // Parallel copies per edge, keyed by (predecessor, successor)
+    let mut copies: BTreeMap<(usize, usize), Vec<(String, String)>> = BTreeMap::new();
+    for (b, body) in blocks.iter_mut().enumerate() {
+        let start = usize::from(body[0].is_label_only());
+        while body.get(start).is_some_and(|inst| inst.opcode == "phi") {
+            let phi = body[start].clone();
+            opt::remove(body, start);
+            let target = opt::single_target(&phi)
+                .ok_or_else(|| format!("Malformed phi '{}'", phi))?
+                .to_string();
+            for pair in phi.sources.chunks(2) {
+                let (Some(Operand::Register(source)), Some(Operand::Label(label))) =
+                    (pair.first(), pair.get(1))
+                else {
+                    return Err(format!("Malformed phi '{}'", phi));
+                };
+                let pred = *block_of_label
+                    .get(label.as_str())
+                    .ok_or_else(|| format!("phi '{}' names unknown block '{}'", phi, label))?;
+                if defined.contains(source.as_str()) {
+                    copies
+                        .entry((pred, b))
+                        .or_default()
+                        .push((target.clone(), source.clone()));
+                }
+            }
+        }
+    }
+
+    let mut split: Vec<Vec<Instruction>> = vec![Vec::new(); blocks.len()];
+    for ((pred, succ), edge) in copies {
+        let sequence = sequentialize(edge, &mut names);
+        if sequence.is_empty() {
+            continue;
+        }
+
+        if cfg.blocks[pred].successors.len() > 1 {
+            // Critical edge: route it through a new block holding the copies
+            let target = blocks[succ][0]
+                .label
+                .clone()
+                .ok_or("Block with phi has no label")?;
+            let branch = blocks[pred].last_mut().unwrap();
+            if branch.opcode != "cbr" {
+                return Err(format!("Cannot split the edge from '{}'", branch));
+            }
+            let label = names.label(pred);
+            for operand in branch.targets.iter_mut() {
+                if *operand == Operand::Label(target.clone()) {
+                    *operand = Operand::Label(label.clone());
+                }
+            }
+            let mut block = sequence;
+            block[0].label = Some(label);
+            block.push(Instruction::new(
+                "jumpI",
+                Vec::new(),
+                vec![Operand::Label(target)],
+            ));
+            split[pred].extend(block);
+        } else {
+            let body = &mut blocks[pred];
+            let at = match body.last() {
+                Some(last) if ir::is_branch(&last.opcode) => body.len() - 1,
+                _ => body.len(),
+            };
+            let label = if at == 0 { body[0].label.take() } else { None };
+            body.splice(at..at, sequence);
+            if label.is_some() {
+                body[0].label = label;
+            }
+        }
+    }
+
+    // A split block sits right after its predecessor, which ends in a branch
+    // and so never falls through into it
+    let mut result = Vec::new();
+    for (body, extra) in blocks.into_iter().zip(split) {
+        result.extend(body);
+        result.extend(extra);
+    }
+    Ok(ir::to_program(&result))
+}
+
+/// Orders the parallel copy `copies` (pairs of target and source) so that it
+/// can run one copy at a time.
+fn sequentialize(copies: Vec<(String, String)>, names: &mut Names) -> Vec<Instruction> {
+    let mut pending: Vec<(String, String)> = copies
+        .into_iter()
+        .filter(|(target, source)| target != source)
+        .collect();
+    let mut sequence = Vec::new();
+    while !pending.is_empty() {
+        let ready = pending
+            .iter()
+            .position(|(target, _)| !pending.iter().any(|(_, source)| source == target));
+        match ready {
+            Some(idx) => {
+                let (target, source) = pending.remove(idx);
+                sequence.push(opt::copy(&source, &target));
+            }
+            None => {
+                // Every remaining target is still to be read: save one of
+                // them in a temporary to break the cycle
+                let saved = pending[0].1.clone();
+                let temp = names.temporary();
+                sequence.push(opt::copy(&saved, &temp));
+                for (_, source) in pending.iter_mut() {
+                    if *source == saved {
+                        *source = temp.clone();
+                    }
+                }
+            }
+        }
+    }
+    sequence
+}
+
+/// Fresh register and label names that do not clash with the program's own.
+struct Names {
+    taken: HashSet<String>,
+    versions: HashMap<String, usize>,
+}
+
+impl Names {
+    fn new(instructions: &[Instruction]) -> Self {
+        let mut taken = HashSet::new();
+        for inst in instructions {
+            taken.extend(inst.label.iter().cloned());
+            for operand in inst.sources.iter().chain(&inst.targets) {
+                match operand {
+                    Operand::Register(name) | Operand::Label(name) => {
+                        taken.insert(name.clone());
+                    }
+                    Operand::Immediate(_) | Operand::Float(_) => {}
+                }
+            }
+        }
+        Self {
+            taken,
+            versions: HashMap::new(),
+        }
+    }
+
+    fn fresh(&mut self, base: &str) -> String {
+        loop {
+            let version = self.versions.entry(base.to_string()).or_insert(0);
+            *version += 1;
+            let name = format!("{}_{}", base, version);
+            if self.taken.insert(name.clone()) {
+                return name;
+            }
+        }
+    }
+
+    fn label(&mut self, block: usize) -> String {
+        let label = format!("B{}", block);
+        if self.taken.insert(label.clone()) {
+            return label;
+        }
+        self.fresh(&label)
+    }
+
+    fn temporary(&mut self) -> String {
+        self.fresh("r_copy")
+    }
+}
+
+struct Renamer<'a> {
+    cfg: &'a Cfg,
+    dominators: &'a Dominators,
+    labels: &'a [Option<String>],
+    names: &'a mut Names,
+    stacks: HashMap<String, Vec<String>>,
+}
+
+impl Renamer<'_> {
+    fn current(&self, reg: &str) -> String {
+        self.stacks
+            .get(reg)
+            .and_then(|stack| stack.last())
+            .cloned()
+            .unwrap_or_else(|| reg.to_string())
+    }
+
+    /// Renames block `b` and the blocks it dominates, walking the dominator
+    /// tree with a stack of versions per register.
+    fn rename(&mut self, b: usize, blocks: &mut [Vec<Instruction>]) {
+        let mut pushed = Vec::new();
+        for inst in blocks[b].iter_mut() {
+            if inst.opcode != "phi" {
+                inst.rename_uses_and_defs(|reg| self.current(reg), |reg| reg.to_string());
+            }
+            let mut defined = Vec::new();
+            inst.rename_uses_and_defs(
+                |reg| reg.to_string(),
+                |reg| {
+                    let version = self.names.fresh(reg);
+                    defined.push((reg.to_string(), version.clone()));
+                    version
+                },
+            );
+            for (reg, version) in defined {
+                self.stacks.entry(reg.clone()).or_default().push(version);
+                pushed.push(reg);
+            }
+        }
+
+        // Each edge into a join point supplies the operands paired with this block
+        if let Some(label) = &self.labels[b] {
+            let label = Operand::Label(label.clone());
+            for &succ in &self.cfg.blocks[b].successors {
+                for phi in blocks[succ].iter_mut().filter(|inst| inst.opcode == "phi") {
+                    for pair in phi.sources.chunks_mut(2) {
+                        if let [Operand::Register(reg), from] = pair {
+                            if *from == label {
+                                *reg = self.current(reg);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        for &child in &self.dominators.children[b] {
+            self.rename(child, blocks);
+        }
+
+        for reg in pushed {
+            self.stacks.get_mut(&reg).unwrap().pop();
+        }
+    }
+}
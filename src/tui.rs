@@ -2,6 +2,7 @@ use ratatui::{
     backend::CrosstermBackend,
     layout::{Constraint, Direction, Layout},
     style::{Color, Style},
+    text::Span,
     widgets::{Block, Borders, Paragraph},
     Terminal,
 };
@@ -11,23 +12,40 @@ use crossterm::event::{self, Event, KeyCode, KeyEventKind};
 use std::io::{self};
 use std::sync::{Arc, Mutex};
 
-use crate::vm::VM;
+use iloc::parser::SourceLine;
+use iloc::vm::{Fault, VM};
 
-pub fn run_tui(vm: Arc<Mutex<VM>>) -> Result<(), io::Error> {
+/// Runs the TUI on `vm`, whose program was parsed from `source`, read from
+/// `file`, into `listing`. Pressing `c` switches the Program panel between
+/// the parsed instructions and the original source with its comments. A
+/// fault stops the program and is shown in the status line.
+pub fn run_tui(
+    vm: Arc<Mutex<VM>>,
+    file: &str,
+    source: &str,
+    listing: &[SourceLine],
+) -> Result<(), io::Error> {
     let stdout = io::stdout();
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
     let mut running = false;
+    let mut show_source = false;
+    let mut fault: Option<Fault> = None;
 
     terminal.clear()?;
 
     Ok(loop {
         terminal.draw(|f| {
+            let outer_chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(0), Constraint::Length(1)])
+                .split(f.area());
+
             let chunks = Layout::default()
                 .direction(Direction::Horizontal)
                 .constraints([Constraint::Percentage(40), Constraint::Percentage(60)])
-                .split(f.area());
+                .split(outer_chunks[0]);
 
             let left_chunks = Layout::default()
                 .direction(Direction::Vertical)
@@ -40,39 +58,108 @@ pub fn run_tui(vm: Arc<Mutex<VM>>) -> Result<(), io::Error> {
                 .split(chunks[1]);
 
             let vm = vm.lock().unwrap();
-            let (registers, memory, pc) = vm.get_state();
+            let (registers, memory, pc) = (vm.get_registers(), vm.get_memory(), vm.get_pc());
 
-            let program_text: Vec<ratatui::prelude::Line> = vm
-                .get_program()
-                .iter()
-                .enumerate()
-                .map(|(idx, inst)| {
-                    if idx == pc {
-                        ratatui::prelude::Line::styled(
-                            inst.clone(),
-                            Style::default().fg(Color::Yellow).bg(Color::Blue),
-                        )
+            // Each line starts with its execution count, colored by how much
+            // of the run's time it took relative to the hottest line
+            let counts = vm.get_line_counts();
+            let line_cycles = vm.get_line_cycles();
+            let hottest = line_cycles.iter().copied().max().unwrap_or(0).max(1);
+            // Rows of the panel, with the program line each one shows if any
+            let rows: Vec<(Option<usize>, String)> = if show_source {
+                source
+                    .lines()
+                    .enumerate()
+                    .map(|(idx, text)| {
+                        let line = listing
+                            .iter()
+                            .position(|line| line.file == file && line.line == idx + 1);
+                        (line, text.to_string())
+                    })
+                    .collect()
+            } else {
+                vm.get_program()
+                    .into_iter()
+                    .enumerate()
+                    .map(|(idx, inst)| (Some(idx), inst))
+                    .collect()
+            };
+            let program_text: Vec<ratatui::prelude::Line> = rows
+                .into_iter()
+                .map(|(line, inst)| {
+                    let Some(idx) = line else {
+                        return ratatui::prelude::Line::styled(
+                            format!("{:>6} {}", "", inst),
+                            Style::default().fg(Color::DarkGray),
+                        );
+                    };
+                    let count = counts.get(idx).copied().unwrap_or(0);
+                    let heat = line_cycles.get(idx).copied().unwrap_or(0) as f64 / hottest as f64;
+                    let heat_color = match heat {
+                        _ if count == 0 => Color::DarkGray,
+                        h if h < 0.25 => Color::Blue,
+                        h if h < 0.5 => Color::Green,
+                        h if h < 0.75 => Color::Yellow,
+                        _ => Color::Red,
+                    };
+                    let count =
+                        Span::styled(format!("{:>6} ", count), Style::default().fg(heat_color));
+                    let text = if idx == pc {
+                        Span::styled(inst, Style::default().fg(Color::Yellow).bg(Color::Blue))
                     } else {
-                        ratatui::prelude::Line::from(inst.clone())
-                    }
+                        Span::raw(inst)
+                    };
+                    ratatui::prelude::Line::from(vec![count, text])
                 })
                 .collect();
+            let cycle_stats = vm.get_cycle_stats();
+            let bundle_stats = vm.get_bundle_stats();
+            let mut program_title = format!(
+                "Program (cycles: {}, stalls: {}",
+                cycle_stats.cycles, cycle_stats.stalls
+            );
+            if bundle_stats.bundles > 0 {
+                program_title.push_str(&format!(
+                    ", bundle utilization: {:.0}%",
+                    bundle_stats.utilization() * 100.0
+                ));
+            }
+            program_title.push(')');
             let program_panel = Paragraph::new(program_text)
-                .block(Block::default().borders(Borders::ALL).title("Program"));
+                .block(Block::default().borders(Borders::ALL).title(program_title));
 
             let mut reg_text: Vec<ratatui::prelude::Line> = registers
                 .iter()
                 .map(|(reg, val)| ratatui::prelude::Line::from(format!("{}: {}\n", reg, val)))
                 .collect();
             reg_text.sort_by(|a, b| a.to_string().cmp(&b.to_string()));
+            // Float registers follow the integer ones
+            let mut float_text: Vec<ratatui::prelude::Line> = vm
+                .get_float_registers()
+                .iter()
+                .map(|(reg, val)| ratatui::prelude::Line::from(format!("{}: {}\n", reg, val)))
+                .collect();
+            float_text.sort_by(|a, b| a.to_string().cmp(&b.to_string()));
+            reg_text.extend(float_text);
             let registers_panel = Paragraph::new(reg_text)
                 .block(Block::default().borders(Borders::ALL).title("Registers"));
 
+            // Only pages with storage are shown, so that a sparse memory
+            // lists just the pages the program has written
+            let wide = memory.size() > 0x10000;
             let mem_text: Vec<ratatui::prelude::Line> = memory
-                .chunks(8)
-                .enumerate()
-                .map(|(i, chunk)| {
-                    let address = format!("0x{:04X}: ", i * 8);
+                .pages()
+                .into_iter()
+                .flat_map(|(start, page)| {
+                    page.chunks(8)
+                        .enumerate()
+                        .map(move |(i, chunk)| (start + i * 8, chunk))
+                })
+                .map(|(at, chunk)| {
+                    let address = match wide {
+                        true => format!("0x{:08X}: ", at),
+                        false => format!("0x{:04X}: ", at),
+                    };
 
                     // Split chunk into two groups of 4 bytes each
                     let (left, right) = chunk.split_at(4);
@@ -116,12 +203,22 @@ pub fn run_tui(vm: Arc<Mutex<VM>>) -> Result<(), io::Error> {
                 })
                 .collect();
 
+            let memory_title = format!("Memory ({} pages mapped)", memory.pages().len());
             let memory_panel = Paragraph::new(mem_text)
-                .block(Block::default().borders(Borders::ALL).title("Memory"));
+                .block(Block::default().borders(Borders::ALL).title(memory_title));
 
             f.render_widget(program_panel, left_chunks[0]);
             f.render_widget(registers_panel, right_chunks[0]);
             f.render_widget(memory_panel, right_chunks[1]);
+
+            let status = match &fault {
+                Some(fault) => Span::styled(
+                    format!("Fault {}: {}", fault.name(), fault),
+                    Style::default().fg(Color::Red),
+                ),
+                None => Span::raw("s: step  r: run  c: source  q: quit"),
+            };
+            f.render_widget(Paragraph::new(status), outer_chunks[1]);
         })?;
 
         if event::poll(std::time::Duration::from_millis(100))? {
@@ -131,13 +228,16 @@ pub fn run_tui(vm: Arc<Mutex<VM>>) -> Result<(), io::Error> {
                         KeyCode::Char('q') => {
                             break;
                         }
-                        KeyCode::Char('s') => {
+                        KeyCode::Char('s') if fault.is_none() => {
                             let mut vm = vm.lock().unwrap();
-                            vm.step();
+                            fault = vm.try_step().err();
                         }
-                        KeyCode::Char('r') => {
+                        KeyCode::Char('r') if fault.is_none() => {
                             running = !running;
                         }
+                        KeyCode::Char('c') => {
+                            show_source = !show_source;
+                        }
                         _ => {}
                     }
                 }
@@ -146,8 +246,13 @@ pub fn run_tui(vm: Arc<Mutex<VM>>) -> Result<(), io::Error> {
 
         if running {
             let mut vm = vm.lock().unwrap();
-            if !vm.step() {
-                running = false;
+            match vm.try_step() {
+                Ok(true) => {}
+                Ok(false) => running = false,
+                Err(error) => {
+                    fault = Some(error);
+                    running = false;
+                }
             }
         }
     })
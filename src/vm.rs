@@ -1,35 +1,465 @@
+use std::cell::Cell;
 use std::collections::HashMap;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Once;
+use std::time::{Duration, Instant};
+
+use crate::ir::{self, Instruction};
+use crate::latency::{CycleModel, CycleStats};
+use crate::machine::{MachineConfig, OverflowPolicy, ShiftPolicy};
+use crate::memory::{Access, DenseMemory, Memory, MemoryMap};
+use crate::parser;
+
+/// Usage of the issue slots of executed bundles.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BundleStats {
+    pub bundles: u64,
+    /// Operations other than `nop` issued from bundles.
+    pub operations: u64,
+    /// Issue slots available to those bundles: the functional-unit limit per
+    /// bundle, or the bundle's own width when no limit is set.
+    pub slots: u64,
+}
+
+impl BundleStats {
+    /// Fraction of issue slots doing useful work.
+    pub fn utilization(&self) -> f64 {
+        if self.slots == 0 {
+            return 0.0;
+        }
+        self.operations as f64 / self.slots as f64
+    }
+}
+
+/// A runtime error that stops the program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+    DivideByZero,
+    /// An instruction the VM does not implement, such as `cstore`, or an
+    /// opcode it does not know; holds the opcode.
+    Unimplemented(String),
+    /// A bundle wider than the configured number of functional units.
+    TooManyOperations {
+        operations: usize,
+        units: usize,
+    },
+    /// A `phi` reached from a block it has no operand for.
+    MissingPhiOperand(String),
+    /// An arithmetic result that does not fit in a word, under
+    /// `OverflowPolicy::Trap`; holds the opcode.
+    Overflow(String),
+    /// A shift amount outside `0..bits`, under `ShiftPolicy::Trap`.
+    ShiftOutOfRange {
+        amount: i64,
+        bits: u32,
+    },
+    /// A load or store touching `address`, which lies in no region or in
+    /// one that does not permit the access.
+    MemoryViolation {
+        access: Access,
+        address: u64,
+        region: Option<String>,
+    },
+    /// A word or float access not aligned to its size, when the memory map
+    /// checks alignment.
+    Misaligned {
+        address: u64,
+        size: usize,
+    },
+}
+
+impl Fault {
+    /// The variant name, e.g. `DivideByZero`, as used by `//! expect fault`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Fault::DivideByZero => "DivideByZero",
+            Fault::Unimplemented(_) => "Unimplemented",
+            Fault::TooManyOperations { .. } => "TooManyOperations",
+            Fault::MissingPhiOperand(_) => "MissingPhiOperand",
+            Fault::Overflow(_) => "Overflow",
+            Fault::ShiftOutOfRange { .. } => "ShiftOutOfRange",
+            Fault::MemoryViolation { .. } => "MemoryViolation",
+            Fault::Misaligned { .. } => "Misaligned",
+        }
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Fault::DivideByZero => write!(f, "Division by zero"),
+            Fault::Unimplemented(opcode) => write!(f, "Not implemented: {}", opcode),
+            Fault::TooManyOperations { operations, units } => write!(
+                f,
+                "Bundle of {} operations exceeds {} functional units",
+                operations, units
+            ),
+            Fault::MissingPhiOperand(block) => {
+                write!(f, "phi has no operand for block '{}'", block)
+            }
+            Fault::Overflow(opcode) => write!(f, "Integer overflow in {}", opcode),
+            Fault::ShiftOutOfRange { amount, bits } => write!(
+                f,
+                "Shift by {} is out of range for {}-bit words",
+                amount, bits
+            ),
+            Fault::MemoryViolation {
+                access,
+                address,
+                region: Some(region),
+            } => write!(
+                f,
+                "{} at address {} not permitted in region '{}'",
+                access, address, region
+            ),
+            Fault::MemoryViolation {
+                access,
+                address,
+                region: None,
+            } => write!(f, "{} at address {} outside memory", access, address),
+            Fault::Misaligned { address, size } => write!(
+                f,
+                "Access of {} bytes at address {} is not aligned",
+                size, address
+            ),
+        }
+    }
+}
+
+/// How a run with `VM::run_limited` or `VM::run_with_limits` ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Termination {
+    Finished,
+    Fault(Fault),
+    StepLimit,
+    /// The run took longer than the wall-clock timeout.
+    Timeout(Duration),
+    /// The whole machine state before the 0-based instruction `pc` repeated
+    /// after `period` steps, so the program can never finish.
+    RepeatedState {
+        pc: usize,
+        period: u64,
+    },
+    /// The VM itself failed, e.g. on a register the program never wrote,
+    /// with the message `run_catching` returned for it.
+    Crashed(String),
+}
+
+impl fmt::Display for Termination {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Termination::Finished => write!(f, "finished"),
+            Termination::Fault(fault) => write!(f, "fault {}: {}", fault.name(), fault),
+            Termination::StepLimit => write!(f, "step limit reached"),
+            Termination::Timeout(limit) => write!(f, "timed out after {:?}", limit),
+            Termination::RepeatedState { pc, period } => write!(
+                f,
+                "infinite loop: state at instruction {} repeats every {} steps",
+                pc + 1,
+                period
+            ),
+            Termination::Crashed(message) => write!(f, "crashed: {}", message),
+        }
+    }
+}
+
+/// Bounds for `VM::run_with_limits`; every limit is off by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    pub max_steps: Option<u64>,
+    pub timeout: Option<Duration>,
+    /// Stop as soon as the registers, memory, program counter and block
+    /// history repeat a state seen before. Costs a comparison of the whole
+    /// machine state per step.
+    pub detect_repeats: bool,
+}
+
+thread_local! {
+    /// Set while `VM::run_catching` runs on this thread, so that the panic
+    /// hook keeps quiet about the panics it catches.
+    static CATCHING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Precision of the float registers, which is also the size of a float in
+/// memory. Floats are stored in the machine's byte order, like words.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FloatWidth {
+    #[default]
+    F32,
+    F64,
+}
+
+impl FloatWidth {
+    /// Bytes a float takes in memory.
+    pub fn bytes(&self) -> usize {
+        match self {
+            FloatWidth::F32 => 4,
+            FloatWidth::F64 => 8,
+        }
+    }
+}
+
+/// Everything that decides what a program does next. Output and the cycle
+/// counts only record the past, so they are left out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct MachineState {
+    pc: usize,
+    registers: HashMap<String, i64>,
+    /// Bit patterns, so that states compare exactly.
+    float_registers: HashMap<String, u64>,
+    /// Every page with storage, by start address.
+    memory: Vec<(usize, Vec<u8>)>,
+    block: Option<String>,
+    previous_block: Option<String>,
+    phi_registers: Option<HashMap<String, i64>>,
+}
+
+/// Register writes and stores made by the operations of a bundle, in the
+/// order they were made, so that a later operation's write wins.
+#[derive(Debug, Default)]
+struct BundleWrites {
+    registers: Vec<(String, i64)>,
+    float_registers: Vec<(String, f64)>,
+    stores: Vec<(usize, Vec<u8>)>,
+}
 
 pub struct VM {
-    registers: HashMap<String, i32>,
-    memory: Vec<u8>,
+    registers: HashMap<String, i64>,
+    /// The `f` registers, rounded to `float_width` on every write.
+    float_registers: HashMap<String, f64>,
+    float_width: FloatWidth,
+    /// Word size and byte order of registers and memory.
+    machine: MachineConfig,
+    overflow_policy: OverflowPolicy,
+    shift_policy: ShiftPolicy,
+    memory: Box<dyn Memory>,
+    /// The regions of `memory` and their permissions.
+    memory_map: MemoryMap,
+    /// Results of the bundle being executed, which land only once the
+    /// whole bundle has run.
+    bundle_writes: Option<BundleWrites>,
     pc: usize,
     program: Vec<String>,
+    labels: HashMap<String, usize>,
+    decoded: Vec<Vec<Instruction>>,
+    cycle_model: CycleModel,
+    functional_units: Option<usize>,
+    bundle_stats: BundleStats,
+    executed: u64,
+    /// Times each program line was executed, and the cycles its issues
+    /// advanced the clock by, for the profiler.
+    line_counts: Vec<u64>,
+    line_cycles: Vec<u64>,
+    /// Times each `cbr` line branched to its first and to its second label.
+    branch_counts: Vec<[u64; 2]>,
+    /// Label of the block being executed and of the one control came from,
+    /// which selects the operand of a `phi`.
+    block: Option<String>,
+    previous_block: Option<String>,
+    /// Registers as they were on entry to the current run of `phi`s, so that
+    /// the run executes as one parallel copy.
+    phi_registers: Option<HashMap<String, i64>>,
+    output: String,
 }
 
 impl VM {
+    /// A VM with `memory_size` bytes of dense memory.
     pub fn new(memory_size: usize) -> Self {
+        Self::with_memory(Box::new(DenseMemory::new(memory_size)))
+    }
+
+    /// A VM on `memory`, such as a `PagedMemory` spanning the whole 32-bit
+    /// address space, with one region covering all of it.
+    pub fn with_memory(memory: Box<dyn Memory>) -> Self {
+        let memory_size = memory.size();
         Self {
             registers: HashMap::new(),
-            memory: vec![0; memory_size],
+            float_registers: HashMap::new(),
+            float_width: FloatWidth::default(),
+            machine: MachineConfig::default(),
+            overflow_policy: OverflowPolicy::default(),
+            shift_policy: ShiftPolicy::default(),
+            memory,
+            memory_map: MemoryMap::flat(memory_size),
+            bundle_writes: None,
             pc: 0,
             program: Vec::new(),
+            labels: HashMap::new(),
+            decoded: Vec::new(),
+            cycle_model: CycleModel::default(),
+            functional_units: None,
+            bundle_stats: BundleStats::default(),
+            executed: 0,
+            line_counts: Vec::new(),
+            line_cycles: Vec::new(),
+            branch_counts: Vec::new(),
+            block: None,
+            previous_block: None,
+            phi_registers: None,
+            output: String::new(),
         }
     }
 
     pub fn load_program(&mut self, program: Vec<String>) {
         self.program = program.clone();
+        self.labels = program
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, line)| {
+                let first = line.split_whitespace().next()?;
+                first
+                    .strip_suffix(':')
+                    .map(|label| (label.to_string(), idx))
+            })
+            .collect();
+        self.decoded = program
+            .iter()
+            .map(|line| match ir::split_bundle(line) {
+                Some((_, operations)) => operations
+                    .iter()
+                    .filter_map(|op| Instruction::parse(op).ok())
+                    .collect(),
+                None => Instruction::parse(line).ok().into_iter().collect(),
+            })
+            .collect();
+        self.cycle_model.reset();
+        self.bundle_stats = BundleStats::default();
+        self.executed = 0;
+        self.line_counts = vec![0; self.program.len()];
+        self.line_cycles = vec![0; self.program.len()];
+        self.branch_counts = vec![[0; 2]; self.program.len()];
+        self.block = None;
+        self.previous_block = None;
+        self.phi_registers = None;
+        self.output.clear();
+    }
+
+    /// Loads a parsed program and copies its data section into memory.
+    pub fn load(&mut self, program: &parser::Program) -> Result<(), String> {
+        let data = &program.data.bytes;
+        if data.len() > self.memory.size() {
+            return Err(format!(
+                "Data section of {} bytes does not fit in {} bytes of memory",
+                data.len(),
+                self.memory.size()
+            ));
+        }
+        self.memory.write(0, data);
+        self.load_program(program.text());
+        Ok(())
+    }
+
+    /// Sets a register before the program runs, e.g. to supply an input.
+    /// The value is wrapped to the machine's word size.
+    pub fn set_register(&mut self, reg: &str, value: i64) {
+        let value = self.word(value);
+        self.registers.insert(reg.to_string(), value);
+    }
+
+    /// Sets a float register before the program runs.
+    pub fn set_float_register(&mut self, reg: &str, value: f64) {
+        self.set_float(reg, value);
+    }
+
+    /// Switches the float registers between f32, the default, and f64.
+    pub fn set_float_width(&mut self, width: FloatWidth) {
+        self.float_width = width;
+    }
+
+    /// Switches the word size and byte order, by default 32-bit
+    /// little-endian. Programs must be parsed for the same machine, with
+    /// `parser::parse_program_for`, so that their data and immediates match.
+    pub fn set_machine_config(&mut self, machine: MachineConfig) {
+        self.machine = machine;
+    }
+
+    /// Divides memory into regions with permissions, replacing the default
+    /// of one region covering all of it. Loading a program still writes its
+    /// data wherever the regions are.
+    pub fn set_memory_map(&mut self, memory_map: MemoryMap) -> Result<(), String> {
+        if memory_map.end() > self.memory.size() {
+            return Err(format!(
+                "Memory map of {} bytes does not fit in {} bytes of memory",
+                memory_map.end(),
+                self.memory.size()
+            ));
+        }
+        self.memory_map = memory_map;
+        Ok(())
+    }
+
+    /// Chooses what arithmetic does on overflow; it wraps by default.
+    pub fn set_overflow_policy(&mut self, policy: OverflowPolicy) {
+        self.overflow_policy = policy;
+    }
+
+    /// Chooses what shifts do with out-of-range amounts; they are masked by
+    /// default.
+    pub fn set_shift_policy(&mut self, policy: ShiftPolicy) {
+        self.shift_policy = policy;
     }
 
+    /// Copies `bytes` into memory starting at `address`; bytes past the end
+    /// of memory are dropped.
+    pub fn write_memory(&mut self, address: usize, bytes: &[u8]) {
+        let end = (address + bytes.len()).min(self.memory.size());
+        if address < end {
+            self.memory.write(address, &bytes[..end - address]);
+        }
+    }
+
+    /// Limits how many operations a bundle may issue in one cycle.
+    pub fn set_functional_units(&mut self, units: usize) {
+        self.functional_units = Some(units);
+    }
+
+    /// Replaces the timing model used to count cycles, resetting the counts.
+    pub fn set_cycle_model(&mut self, cycle_model: CycleModel) {
+        self.cycle_model = cycle_model;
+        self.cycle_model.reset();
+    }
+
+    /// Executes one program line. Panics with the fault's message if the
+    /// program faults; use `try_step` to handle faults instead.
     pub fn step(&mut self) -> bool {
+        self.try_step().unwrap_or_else(|fault| panic!("{}", fault))
+    }
+
+    /// Executes one program line, returning whether there was one to execute.
+    pub fn try_step(&mut self) -> Result<bool, Fault> {
         if self.pc >= self.program.len() {
-            return false;
+            return Ok(false);
         }
 
         let instruction = self.program[self.pc].clone();
-        self.execute(&instruction);
+        if let Some(label) = instruction
+            .split_whitespace()
+            .next()
+            .and_then(|first| first.strip_suffix(':'))
+        {
+            self.previous_block = self.block.replace(label.to_string());
+            self.phi_registers = None;
+        }
+        if !self.decoded[self.pc]
+            .iter()
+            .any(|inst| inst.opcode == "phi")
+        {
+            self.phi_registers = None;
+        } else if self.phi_registers.is_none() {
+            self.phi_registers = Some(self.registers.clone());
+        }
+        let clock = self.cycle_model.clock();
+        self.cycle_model.issue(&self.decoded[self.pc]);
+        self.line_counts[self.pc] += 1;
+        self.line_cycles[self.pc] += self.cycle_model.clock() - clock;
+        self.executed += 1;
         self.pc += 1;
-        true
+        match ir::split_bundle(&instruction) {
+            Some((_, operations)) => self.execute_bundle(&operations)?,
+            None => self.execute(&instruction)?,
+        }
+        Ok(true)
     }
 
     #[allow(dead_code)]
@@ -37,8 +467,177 @@ impl VM {
         while self.step() {}
     }
 
-    fn execute(&mut self, instruction: &str) {
-        let parts: Vec<&str> = instruction.split_whitespace().collect();
+    /// Runs to completion or until the first fault.
+    pub fn try_run(&mut self) -> Result<(), Fault> {
+        while self.try_step()? {}
+        Ok(())
+    }
+
+    /// Runs until the program finishes, faults, or has taken `max_steps`
+    /// steps with instructions still left to execute.
+    pub fn run_limited(&mut self, max_steps: u64) -> Termination {
+        self.run_with_limits(&Limits {
+            max_steps: Some(max_steps),
+            ..Limits::default()
+        })
+    }
+
+    /// Runs until the program finishes, faults, or hits one of `limits`.
+    pub fn run_with_limits(&mut self, limits: &Limits) -> Termination {
+        let started = Instant::now();
+        let mut steps = 0;
+
+        // Brent's cycle detection: compare each state with a saved one, and
+        // save a new one whenever the distance reaches the next power of two
+        let mut saved = limits.detect_repeats.then(|| self.machine_state());
+        let (mut power, mut distance) = (1, 0);
+
+        loop {
+            if self.pc < self.program.len() {
+                if limits.max_steps.is_some_and(|max| steps >= max) {
+                    return Termination::StepLimit;
+                }
+                if let Some(timeout) = limits.timeout {
+                    if started.elapsed() >= timeout {
+                        return Termination::Timeout(timeout);
+                    }
+                }
+            }
+            match self.try_step() {
+                Ok(true) => steps += 1,
+                Ok(false) => return Termination::Finished,
+                Err(fault) => return Termination::Fault(fault),
+            }
+
+            if let Some(state) = saved.as_mut() {
+                distance += 1;
+                if self.is_in_state(state) {
+                    return Termination::RepeatedState {
+                        pc: self.pc,
+                        period: distance,
+                    };
+                }
+                if distance == power {
+                    *state = self.machine_state();
+                    power *= 2;
+                    distance = 0;
+                }
+            }
+        }
+    }
+
+    /// Like `run_with_limits`, but a panic during the run, e.g. from reading
+    /// a register the program never wrote, is caught and returned as its
+    /// message instead of unwinding. Caught panics are not printed; panics
+    /// outside the run and on other threads still are.
+    pub fn run_catching(&mut self, limits: &Limits) -> Result<Termination, String> {
+        static QUIET_HOOK: Once = Once::new();
+        QUIET_HOOK.call_once(|| {
+            let previous = panic::take_hook();
+            panic::set_hook(Box::new(move |info| {
+                if !CATCHING.with(Cell::get) {
+                    previous(info);
+                }
+            }));
+        });
+
+        CATCHING.with(|catching| catching.set(true));
+        let run = panic::catch_unwind(AssertUnwindSafe(|| self.run_with_limits(limits)));
+        CATCHING.with(|catching| catching.set(false));
+        run.map_err(|payload| {
+            payload
+                .downcast_ref::<String>()
+                .cloned()
+                .or_else(|| payload.downcast_ref::<&str>().map(|s| s.to_string()))
+                .unwrap_or_else(|| "unknown error".to_string())
+        })
+    }
+
+    fn machine_state(&self) -> MachineState {
+        MachineState {
+            pc: self.pc,
+            registers: self.registers.clone(),
+            float_registers: self
+                .float_registers
+                .iter()
+                .map(|(reg, value)| (reg.clone(), value.to_bits()))
+                .collect(),
+            memory: self
+                .memory
+                .pages()
+                .into_iter()
+                .map(|(address, page)| (address, page.to_vec()))
+                .collect(),
+            block: self.block.clone(),
+            previous_block: self.previous_block.clone(),
+            phi_registers: self.phi_registers.clone(),
+        }
+    }
+
+    // Compares without cloning, cheapest fields first
+    fn is_in_state(&self, state: &MachineState) -> bool {
+        self.pc == state.pc
+            && self.block == state.block
+            && self.previous_block == state.previous_block
+            && self.registers == state.registers
+            && self.float_registers.len() == state.float_registers.len()
+            && self
+                .float_registers
+                .iter()
+                .all(|(reg, value)| state.float_registers.get(reg) == Some(&value.to_bits()))
+            && self.phi_registers == state.phi_registers
+            && self.memory_is(&state.memory)
+    }
+
+    /// Executes the operations of a bundle as if in parallel: every operation
+    /// reads the registers and memory as they were before the bundle, and all
+    /// writes land together afterwards.
+    fn execute_bundle(&mut self, operations: &[&str]) -> Result<(), Fault> {
+        if let Some(units) = self.functional_units {
+            if operations.len() > units {
+                return Err(Fault::TooManyOperations {
+                    operations: operations.len(),
+                    units,
+                });
+            }
+        }
+
+        self.bundle_writes = Some(BundleWrites::default());
+        for operation in operations {
+            if let Err(fault) = self.execute(operation) {
+                self.bundle_writes = None;
+                return Err(fault);
+            }
+        }
+
+        let writes = self.bundle_writes.take().unwrap();
+        for (reg, value) in writes.registers {
+            self.registers.insert(reg, value);
+        }
+        for (reg, value) in writes.float_registers {
+            self.float_registers.insert(reg, value);
+        }
+        for (address, bytes) in writes.stores {
+            self.memory.write(address, &bytes);
+        }
+
+        self.bundle_stats.bundles += 1;
+        self.bundle_stats.operations += operations.iter().filter(|op| **op != "nop").count() as u64;
+        self.bundle_stats.slots += self.functional_units.unwrap_or(operations.len()) as u64;
+        Ok(())
+    }
+
+    fn execute(&mut self, instruction: &str) -> Result<(), Fault> {
+        let mut parts: Vec<&str> = instruction.split_whitespace().collect();
+
+        // Strip a leading `L1:` label; a label on its own line is a no-op
+        if parts[0].ends_with(':') {
+            parts.remove(0);
+            if parts.is_empty() {
+                return Ok(());
+            }
+        }
+
         match parts[0] {
             "nop" => {
                 // No operation
@@ -50,8 +649,8 @@ impl VM {
                 let r1 = self.registers[operands[0]];
                 let r2 = self.registers[operands[1]];
                 let reg = parts.last().unwrap();
-                let result = r1.wrapping_add(r2);
-                self.registers.insert(reg.to_string(), result);
+                let result = self.arithmetic(parts[0], i128::from(r1) + i128::from(r2))?;
+                self.write_register(reg, result);
             }
             "sub" => {
                 // sub r1, r2 => r3
@@ -60,8 +659,8 @@ impl VM {
                 let r1 = self.registers[operands[0]];
                 let r2 = self.registers[operands[1]];
                 let reg = parts.last().unwrap();
-                let result = r1.wrapping_sub(r2);
-                self.registers.insert(reg.to_string(), result);
+                let result = self.arithmetic(parts[0], i128::from(r1) - i128::from(r2))?;
+                self.write_register(reg, result);
             }
             "mult" => {
                 // mult r1, r2 => r3
@@ -70,8 +669,8 @@ impl VM {
                 let r1 = self.registers[operands[0]];
                 let r2 = self.registers[operands[1]];
                 let reg = parts.last().unwrap();
-                let result = r1.wrapping_mul(r2);
-                self.registers.insert(reg.to_string(), result);
+                let result = self.arithmetic(parts[0], i128::from(r1) * i128::from(r2))?;
+                self.write_register(reg, result);
             }
             "div" => {
                 // div r1, r2 => r3
@@ -84,85 +683,85 @@ impl VM {
                 // Check for division by zero
                 if r2 == 0 {
                     // Halt the program, throw an error
-                    panic!("Division by zero");
+                    return Err(Fault::DivideByZero);
                 }
 
-                let result = r1.wrapping_div(r2);
-                self.registers.insert(reg.to_string(), result);
+                let result = self.arithmetic(parts[0], i128::from(r1) / i128::from(r2))?;
+                self.write_register(reg, result);
             }
             "addI" => {
                 // addI r1, c2 => r3
                 // Meaning: r1 + c2 => r3
                 let operands: Vec<&str> = parts[1].split(',').collect();
                 let r1 = self.registers[operands[0]];
-                let c2: i32 = operands[1].parse().unwrap();
+                let c2: i64 = operands[1].parse().unwrap();
                 let reg = parts.last().unwrap();
-                let result = r1.wrapping_add(c2);
-                self.registers.insert(reg.to_string(), result);
+                let result = self.arithmetic(parts[0], i128::from(r1) + i128::from(c2))?;
+                self.write_register(reg, result);
             }
             "subI" => {
                 // subI r1, c2 => r3
                 // Meaning: r1 - c2 => r3
                 let operands: Vec<&str> = parts[1].split(',').collect();
                 let r1 = self.registers[operands[0]];
-                let c2: i32 = operands[1].parse().unwrap();
+                let c2: i64 = operands[1].parse().unwrap();
                 let reg = parts.last().unwrap();
-                let result = r1.wrapping_sub(c2);
-                self.registers.insert(reg.to_string(), result);
+                let result = self.arithmetic(parts[0], i128::from(r1) - i128::from(c2))?;
+                self.write_register(reg, result);
             }
             "rsubI" => {
                 // rsubI r1, c2 => r3
                 // Meaning: c2 - r1 => r3
                 let operands: Vec<&str> = parts[1].split(',').collect();
                 let r1 = self.registers[operands[0]];
-                let c2: i32 = operands[1].parse().unwrap();
+                let c2: i64 = operands[1].parse().unwrap();
                 let reg = parts.last().unwrap();
-                let result = c2.wrapping_sub(r1);
-                self.registers.insert(reg.to_string(), result);
+                let result = self.arithmetic(parts[0], i128::from(c2) - i128::from(r1))?;
+                self.write_register(reg, result);
             }
             "multI" => {
                 // multI r1, c2 => r3
                 // Meaning: r1 * c2 => r3
                 let operands: Vec<&str> = parts[1].split(',').collect();
                 let r1 = self.registers[operands[0]];
-                let c2: i32 = operands[1].parse().unwrap();
+                let c2: i64 = operands[1].parse().unwrap();
                 let reg = parts.last().unwrap();
-                let result = r1.wrapping_mul(c2);
-                self.registers.insert(reg.to_string(), result);
+                let result = self.arithmetic(parts[0], i128::from(r1) * i128::from(c2))?;
+                self.write_register(reg, result);
             }
             "divI" => {
                 // divI r1, c2 => r3
                 // Meaning: r1 / c2 => r3
                 let operands: Vec<&str> = parts[1].split(',').collect();
                 let r1 = self.registers[operands[0]];
-                let c2: i32 = operands[1].parse().unwrap();
+                let c2: i64 = operands[1].parse().unwrap();
                 let reg = parts.last().unwrap();
 
                 // Check for division by zero
                 if c2 == 0 {
                     // Halt the program, throw an error
-                    panic!("Division by zero");
+                    return Err(Fault::DivideByZero);
                 }
 
-                let result = r1.wrapping_div(c2);
-                self.registers.insert(reg.to_string(), result);
+                let result = self.arithmetic(parts[0], i128::from(r1) / i128::from(c2))?;
+                self.write_register(reg, result);
             }
             "rdivI" => {
                 // rdivI r1, c2 => r3
                 // Meaning: c2 / r1 => r3
                 let operands: Vec<&str> = parts[1].split(',').collect();
                 let r1 = self.registers[operands[0]];
-                let c2: i32 = operands[1].parse().unwrap();
+                let c2: i64 = operands[1].parse().unwrap();
                 let reg = parts.last().unwrap();
 
                 // Check for division by zero
                 if r1 == 0 {
                     // Halt the program, throw an error
-                    panic!("Division by zero");
+                    return Err(Fault::DivideByZero);
                 }
 
-                let result = c2.wrapping_div(r1);
-                self.registers.insert(reg.to_string(), result);
+                let result = self.arithmetic(parts[0], i128::from(c2) / i128::from(r1))?;
+                self.write_register(reg, result);
             }
             // Below are untested instructions
             // Bitwise operations
@@ -173,18 +772,18 @@ impl VM {
                 let r1 = self.registers[operands[0]];
                 let r2 = self.registers[operands[1]];
                 let reg = parts.last().unwrap();
-                let result = r1 << r2;
-                self.registers.insert(reg.to_string(), result);
+                let result = self.shift(parts[0], r1, r2)?;
+                self.write_register(reg, result);
             }
             "lshiftI" => {
                 // lshiftI r1, c2 => r3
                 // Meaning: r1 << c2 => r3
                 let operands: Vec<&str> = parts[1].split(',').collect();
                 let r1 = self.registers[operands[0]];
-                let c2: i32 = operands[1].parse().unwrap();
+                let c2: i64 = operands[1].parse().unwrap();
                 let reg = parts.last().unwrap();
-                let result = r1 << c2;
-                self.registers.insert(reg.to_string(), result);
+                let result = self.shift(parts[0], r1, c2)?;
+                self.write_register(reg, result);
             }
             "rshift" => {
                 // rshift r1, r2 => r3
@@ -193,18 +792,38 @@ impl VM {
                 let r1 = self.registers[operands[0]];
                 let r2 = self.registers[operands[1]];
                 let reg = parts.last().unwrap();
-                let result = r1 >> r2;
-                self.registers.insert(reg.to_string(), result);
+                let result = self.shift(parts[0], r1, r2)?;
+                self.write_register(reg, result);
             }
             "rshiftI" => {
-                // rshiftI r1, c2 r3
+                // rshiftI r1, c2 => r3
                 // Meaning: r1 >> c2 => r3
                 let operands: Vec<&str> = parts[1].split(',').collect();
                 let r1 = self.registers[operands[0]];
-                let c2: i32 = operands[1].parse().unwrap();
+                let c2: i64 = operands[1].parse().unwrap();
+                let reg = parts.last().unwrap();
+                let result = self.shift(parts[0], r1, c2)?;
+                self.write_register(reg, result);
+            }
+            "rshiftL" => {
+                // rshiftL r1, r2 => r3
+                // Meaning: r1 >> r2 => r3, filling with zeros rather than the sign
+                let operands: Vec<&str> = parts[1].split(',').collect();
+                let r1 = self.registers[operands[0]];
+                let r2 = self.registers[operands[1]];
+                let reg = parts.last().unwrap();
+                let result = self.shift(parts[0], r1, r2)?;
+                self.write_register(reg, result);
+            }
+            "rshiftLI" => {
+                // rshiftLI r1, c2 => r3
+                // Meaning: r1 >> c2 => r3, filling with zeros rather than the sign
+                let operands: Vec<&str> = parts[1].split(',').collect();
+                let r1 = self.registers[operands[0]];
+                let c2: i64 = operands[1].parse().unwrap();
                 let reg = parts.last().unwrap();
-                let result = r1 >> c2;
-                self.registers.insert(reg.to_string(), result);
+                let result = self.shift(parts[0], r1, c2)?;
+                self.write_register(reg, result);
             }
             // Bitwise logical operations
             "and" => {
@@ -215,17 +834,17 @@ impl VM {
                 let r2 = self.registers[operands[1]];
                 let reg = parts.last().unwrap();
                 let result = r1 & r2;
-                self.registers.insert(reg.to_string(), result);
+                self.write_register(reg, result);
             }
             "andI" => {
-                // andI r1, c2 r3
+                // andI r1, c2 => r3
                 // Meaning: r1 & c2 => r3
                 let operands: Vec<&str> = parts[1].split(',').collect();
                 let r1 = self.registers[operands[0]];
-                let c2: i32 = parts[2].parse().unwrap();
+                let c2: i64 = operands[1].parse().unwrap();
                 let reg = parts.last().unwrap();
                 let result = r1 & c2;
-                self.registers.insert(reg.to_string(), result);
+                self.write_register(reg, result);
             }
             "or" => {
                 // or r1, r2 r3
@@ -235,17 +854,17 @@ impl VM {
                 let r2 = self.registers[operands[1]];
                 let reg = parts.last().unwrap();
                 let result = r1 | r2;
-                self.registers.insert(reg.to_string(), result);
+                self.write_register(reg, result);
             }
             "orI" => {
-                // orI r1, c2 r3
+                // orI r1, c2 => r3
                 // Meaning: r1 | c2 => r3
                 let operands: Vec<&str> = parts[1].split(',').collect();
                 let r1 = self.registers[operands[0]];
-                let c2: i32 = parts[2].parse().unwrap();
+                let c2: i64 = operands[1].parse().unwrap();
                 let reg = parts.last().unwrap();
                 let result = r1 | c2;
-                self.registers.insert(reg.to_string(), result);
+                self.write_register(reg, result);
             }
             "xor" => {
                 // xor r1, r2 r3
@@ -255,165 +874,481 @@ impl VM {
                 let r2 = self.registers[operands[1]];
                 let reg = parts.last().unwrap();
                 let result = r1 ^ r2;
-                self.registers.insert(reg.to_string(), result);
+                self.write_register(reg, result);
             }
             "xorI" => {
-                // xorI r1, c2 r3
+                // xorI r1, c2 => r3
                 // Meaning: r1 ^ c2 => r3
                 let operands: Vec<&str> = parts[1].split(',').collect();
                 let r1 = self.registers[operands[0]];
-                let c2: i32 = parts[2].parse().unwrap();
+                let c2: i64 = operands[1].parse().unwrap();
                 let reg = parts.last().unwrap();
                 let result = r1 ^ c2;
-                self.registers.insert(reg.to_string(), result);
+                self.write_register(reg, result);
             }
 
             // Data transfer operations
             // Data transfer operations
             "loadI" => {
                 // loadI c1 => r2
-                let value: i32 = parts[1].parse::<i64>().unwrap_or(0) as i32;
+                let value: i64 = parts[1].parse().unwrap();
                 let reg = parts.last().unwrap();
-                self.registers.insert(reg.to_string(), value);
+                self.write_register(reg, value);
             }
 
             "load" => {
                 // load r1 => r2
                 // Meaning: MEMORY[r1] => r2
-                // Load the 4-byte value from the memory location specified by r1 to r2
+                // Load the word from the memory location specified by r1 to r2
                 // If r2 does not exist, create it
-                let r1 = self.registers[parts[1]] as usize;
+                let r1 = self.registers[parts[1]];
                 let reg = parts.last().unwrap();
-                if r1 + 3 < self.memory.len() {
-                    let bytes = [
-                        self.memory[r1] as u8,
-                        self.memory[r1 + 1] as u8,
-                        self.memory[r1 + 2] as u8,
-                        self.memory[r1 + 3] as u8,
-                    ];
-                    let value = i32::from_le_bytes(bytes);
-                    self.registers.insert(reg.to_string(), value);
-                }
+                let value = self.read_word(self.address(r1, 0))?;
+                self.write_register(reg, value);
             }
             "loadAI" => {
                 // loadAI r1, c2 => r3
                 // Meaning: MEMORY[r1 + c2] => r3
-                // Load the 4-byte value from the memory location specified by r1 + c2 to r3
+                // Load the word from the memory location specified by r1 + c2 to r3
                 // If r3 does not exist, create it
-                let r1 = self.registers[parts[1]] as usize;
-                let c2: i32 = parts[3].parse().unwrap();
+                let operands: Vec<&str> = parts[1].split(',').collect();
+                let r1 = self.registers[operands[0]];
+                let c2: i64 = operands[1].parse().unwrap();
                 let reg = parts.last().unwrap();
-                let address = r1 + c2 as usize;
-                if address + 3 < self.memory.len() {
-                    let bytes = [
-                        self.memory[address] as u8,
-                        self.memory[address + 1] as u8,
-                        self.memory[address + 2] as u8,
-                        self.memory[address + 3] as u8,
-                    ];
-                    let value = i32::from_le_bytes(bytes);
-                    self.registers.insert(reg.to_string(), value);
-                }
+                let value = self.read_word(self.address(r1, c2))?;
+                self.write_register(reg, value);
             }
             "loadAO" => {
                 // loadAO r1, r2 => r3
                 // Meaning: MEMORY[r1 + r2] => r3
-                // Load the 4-byte value from the memory location specified by r1 + r2 to r3
+                // Load the word from the memory location specified by r1 + r2 to r3
                 // If r3 does not exist, create it
-                let r1 = self.registers[parts[1]] as usize;
-                let r2 = self.registers[parts[3]] as usize;
+                let operands: Vec<&str> = parts[1].split(',').collect();
+                let r1 = self.registers[operands[0]];
+                let r2 = self.registers[operands[1]];
                 let reg = parts.last().unwrap();
-                let address = r1 + r2;
-                if address + 3 < self.memory.len() {
-                    let bytes = [
-                        self.memory[address] as u8,
-                        self.memory[address + 1] as u8,
-                        self.memory[address + 2] as u8,
-                        self.memory[address + 3] as u8,
-                    ];
-                    let value = i32::from_le_bytes(bytes);
-                    self.registers.insert(reg.to_string(), value);
-                }
+                let value = self.read_word(self.address(r1, r2))?;
+                self.write_register(reg, value);
             }
             "cload" => {
                 // cload r1 => r2
                 // Meaning: MEMORY[r1] => r2
-                // Load the 4-byte character from the memory location specified by r1 to r2
+                // Load the word-sized character from the memory location specified by r1 to r2
                 // If r2 does not exist, create it
-                let r1 = self.registers[parts[1]] as usize;
+                let r1 = self.registers[parts[1]];
                 let reg = parts.last().unwrap();
-                if r1 + 3 < self.memory.len() {
-                    let bytes = [
-                        self.memory[r1] as u8,
-                        self.memory[r1 + 1] as u8,
-                        self.memory[r1 + 2] as u8,
-                        self.memory[r1 + 3] as u8,
-                    ];
-                    let value = i32::from_le_bytes(bytes);
-                    self.registers.insert(reg.to_string(), value);
-                }
-            }
-            "cloadAI" => {
-                panic!("Not implemented");
+                let value = self.read_word(self.address(r1, 0))?;
+                self.write_register(reg, value);
             }
-            "cloadAO" => {
-                panic!("Not implemented");
+            "cloadAI" | "cloadAO" => {
+                return Err(Fault::Unimplemented(parts[0].to_string()));
             }
             "store" => {
                 // store r1 => r2
                 // Meaning: r1 => MEMORY[r2]
-                // Store the 4-byte value in r1 to the memory location specified by r2
+                // Store the word in r1 to the memory location specified by r2
                 let r1 = self.registers[parts[1]];
-                let r2 = self.registers[parts[3]] as usize;
-                if r2 + 3 < self.memory.len() {
-                    let bytes = r1.to_le_bytes();
-                    self.memory[r2] = bytes[0];
-                    self.memory[r2 + 1] = bytes[1];
-                    self.memory[r2 + 2] = bytes[2];
-                    self.memory[r2 + 3] = bytes[3];
-                }
+                let r2 = self.registers[parts[3]];
+                self.write_word(self.address(r2, 0), r1)?;
             }
             "storeAI" => {
                 // storeAI r1 => r2, c3
                 // Meaning: r1 => MEMORY[r2 + c3]
-                // Store the 4-byte value in r1 to the memory location specified by r2 + c3
+                // Store the word in r1 to the memory location specified by r2 + c3
                 let r1 = self.registers[parts[1]];
-                let r2 = self.registers[parts[3]] as usize;
-                let c3: i32 = parts[5].parse().unwrap();
-                let address = r2 + c3 as usize;
-                if address + 3 < self.memory.len() {
-                    let bytes = r1.to_le_bytes();
-                    self.memory[address] = bytes[0];
-                    self.memory[address + 1] = bytes[1];
-                    self.memory[address + 2] = bytes[2];
-                    self.memory[address + 3] = bytes[3];
-                }
+                let operands: Vec<&str> = parts[3].split(',').collect();
+                let r2 = self.registers[operands[0]];
+                let c3: i64 = operands[1].parse().unwrap();
+                self.write_word(self.address(r2, c3), r1)?;
             }
             "storeAO" => {
                 // storeAO r1 => r2, r3
                 // Meaning: r1 => MEMORY[r2 + r3]
-                // Store the 4-byte value in r1 to the memory location specified by r2 + r3
+                // Store the word in r1 to the memory location specified by r2 + r3
                 let r1 = self.registers[parts[1]];
-                let r2 = self.registers[parts[3]] as usize;
-                let r3 = self.registers[parts[5]] as usize;
-                let address = r2 + r3;
-                if address + 3 < self.memory.len() {
-                    let bytes = r1.to_le_bytes();
-                    self.memory[address] = bytes[0];
-                    self.memory[address + 1] = bytes[1];
-                    self.memory[address + 2] = bytes[2];
-                    self.memory[address + 3] = bytes[3];
+                let operands: Vec<&str> = parts[3].split(',').collect();
+                let r2 = self.registers[operands[0]];
+                let r3 = self.registers[operands[1]];
+                self.write_word(self.address(r2, r3), r1)?;
+            }
+
+            // Register copy and comparison operations
+            "i2i" => {
+                // i2i r1 => r2
+                // Meaning: r1 => r2
+                let r1 = self.registers[parts[1]];
+                let reg = parts.last().unwrap();
+                self.write_register(reg, r1);
+            }
+            "cmp_LT" | "cmp_LE" | "cmp_EQ" | "cmp_GE" | "cmp_GT" | "cmp_NE" => {
+                // cmp_LT r1, r2 => r3
+                // Meaning: r1 < r2 => r3 (1 for true, 0 for false)
+                let operands: Vec<&str> = parts[1].split(',').collect();
+                let r1 = self.registers[operands[0]];
+                let r2 = self.registers[operands[1]];
+                let reg = parts.last().unwrap();
+                let result = match parts[0] {
+                    "cmp_LT" => r1 < r2,
+                    "cmp_LE" => r1 <= r2,
+                    "cmp_EQ" => r1 == r2,
+                    "cmp_GE" => r1 >= r2,
+                    "cmp_GT" => r1 > r2,
+                    _ => r1 != r2,
+                };
+                self.write_register(reg, result as i64);
+            }
+
+            // Floating-point operations
+            "loadF" => {
+                // loadF c1 => f2
+                let value: f64 = parts[1].parse().unwrap();
+                self.set_float(parts.last().unwrap(), value);
+            }
+            "fadd" | "fsub" | "fmult" | "fdiv" => {
+                // fadd f1, f2 => f3
+                // Meaning: f1 + f2 => f3, rounded to the float width
+                let operands: Vec<&str> = parts[1].split(',').collect();
+                let f1 = self.float_registers[operands[0]];
+                let f2 = self.float_registers[operands[1]];
+                let result = match parts[0] {
+                    "fadd" => f1 + f2,
+                    "fsub" => f1 - f2,
+                    "fmult" => f1 * f2,
+                    _ => f1 / f2,
+                };
+                self.set_float(parts.last().unwrap(), result);
+            }
+            "fload" | "floadAI" | "floadAO" => {
+                // floadAI r1, c2 => f3
+                // Meaning: MEMORY[r1 + c2] => f3, as a float of the float width
+                let operands: Vec<&str> = parts[1].split(',').collect();
+                let offset = match parts[0] {
+                    "fload" => 0,
+                    "floadAI" => operands[1].parse().unwrap(),
+                    _ => self.registers[operands[1]],
+                };
+                let address = self.address(self.registers[operands[0]], offset);
+                let value = self.read_float(address)?;
+                self.set_float(parts.last().unwrap(), value);
+            }
+            "fstore" | "fstoreAI" | "fstoreAO" => {
+                // fstoreAI f1 => r2, c3
+                // Meaning: f1 => MEMORY[r2 + c3]
+                let f1 = self.float_registers[parts[1]];
+                let operands: Vec<&str> = parts[3].split(',').collect();
+                let offset = match parts[0] {
+                    "fstore" => 0,
+                    "fstoreAI" => operands[1].parse().unwrap(),
+                    _ => self.registers[operands[1]],
+                };
+                let address = self.address(self.registers[operands[0]], offset);
+                self.write_float(address, f1)?;
+            }
+            "i2f" => {
+                // i2f r1 => f2
+                let r1 = self.registers[parts[1]];
+                self.set_float(parts.last().unwrap(), r1 as f64);
+            }
+            "f2i" => {
+                // f2i f1 => r2
+                // Meaning: f1 truncated toward zero => r2, saturating at the
+                // word's limits; NaN converts to 0
+                let f1 = self.float_registers[parts[1]];
+                let value = self.machine.word_size.from_float(f1);
+                self.registers
+                    .insert(parts.last().unwrap().to_string(), value);
+            }
+            "f2f" => {
+                // f2f f1 => f2
+                let f1 = self.float_registers[parts[1]];
+                self.set_float(parts.last().unwrap(), f1);
+            }
+            "fcmp_LT" | "fcmp_LE" | "fcmp_EQ" | "fcmp_GE" | "fcmp_GT" | "fcmp_NE" => {
+                // fcmp_LT f1, f2 => r3
+                // Meaning: f1 < f2 => r3 (1 for true, 0 for false); every
+                // comparison with NaN but fcmp_NE is false
+                let operands: Vec<&str> = parts[1].split(',').collect();
+                let f1 = self.float_registers[operands[0]];
+                let f2 = self.float_registers[operands[1]];
+                let result = match parts[0] {
+                    "fcmp_LT" => f1 < f2,
+                    "fcmp_LE" => f1 <= f2,
+                    "fcmp_EQ" => f1 == f2,
+                    "fcmp_GE" => f1 >= f2,
+                    "fcmp_GT" => f1 > f2,
+                    _ => f1 != f2,
+                };
+                self.registers
+                    .insert(parts.last().unwrap().to_string(), result as i64);
+            }
+
+            // Output operations
+            "output" => {
+                // output c1
+                // Meaning: print MEMORY[c1] as a decimal number on its own line
+                let address: i64 = parts[1].parse().unwrap();
+                let value = self.read_word(self.address(address, 0))?;
+                self.output.push_str(&format!("{}\n", value));
+            }
+
+            // SSA operations
+            "phi" => {
+                // phi r1, l1, r2, l2 => r3
+                // Meaning: r1 => r3 when control came from block l1, r2 => r3 from l2
+                // Every phi at the top of a block reads the registers as they
+                // were on entry to the block; an undefined operand leaves r3 undefined
+                let operands: Vec<&str> = parts[1].split(',').collect();
+                let registers = self.phi_registers.as_ref().unwrap_or(&self.registers);
+                let from = self.previous_block.as_deref().unwrap_or("");
+                let source = operands
+                    .chunks(2)
+                    .find(|pair| pair.get(1) == Some(&from))
+                    .ok_or_else(|| Fault::MissingPhiOperand(from.to_string()))?[0];
+                if let Some(&value) = registers.get(source) {
+                    let reg = parts.last().unwrap();
+                    self.write_register(reg, value);
+                }
+            }
+
+            // Control flow operations
+            "jumpI" => {
+                // jumpI -> l1
+                // Meaning: l1 => PC
+                let label = parts.last().unwrap();
+                self.pc = self.labels[*label];
+            }
+            "jump" => {
+                // jump -> r1
+                // Meaning: r1 => PC
+                let reg = parts.last().unwrap();
+                self.pc = self.registers[*reg] as usize;
+            }
+            "cbr" => {
+                // cbr r1 -> l2, l3
+                // Meaning: l2 => PC if r1 is true, l3 => PC otherwise
+                let r1 = self.registers[parts[1]];
+                let labels: Vec<&str> = parts.last().unwrap().split(',').collect();
+                let label = if r1 != 0 { labels[0] } else { labels[1] };
+                // The program counter already points past this line
+                if let Some(counts) = self.branch_counts.get_mut(self.pc - 1) {
+                    counts[usize::from(r1 == 0)] += 1;
                 }
+                self.pc = self.labels[label];
+            }
+
+            "tbl" => {
+                // tbl r1, l2
+                // Meaning: a hint that r1 may hold the address of l2; does nothing
             }
 
             _ => {
-                // Unsupported instruction
+                return Err(Fault::Unimplemented(parts[0].to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// The registers, memory and program counter. Memory is only given as a
+    /// slice when it is stored as one; a `PagedMemory` gives an empty slice,
+    /// so use `get_memory`, which reads every kind, for it.
+    pub fn get_state(&self) -> (&HashMap<String, i64>, &[u8], usize) {
+        (
+            &self.registers,
+            self.memory.as_slice().unwrap_or(&[]),
+            self.pc,
+        )
+    }
+
+    pub fn get_registers(&self) -> &HashMap<String, i64> {
+        &self.registers
+    }
+
+    pub fn get_pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn get_memory(&self) -> &dyn Memory {
+        self.memory.as_ref()
+    }
+
+    pub fn get_float_registers(&self) -> &HashMap<String, f64> {
+        &self.float_registers
+    }
+
+    pub fn get_float_width(&self) -> FloatWidth {
+        self.float_width
+    }
+
+    pub fn get_memory_map(&self) -> &MemoryMap {
+        &self.memory_map
+    }
+
+    pub fn get_machine_config(&self) -> MachineConfig {
+        self.machine
+    }
+
+    /// `value` wrapped to the machine's word size.
+    fn word(&self, value: i64) -> i64 {
+        self.machine.word_size.wrap(value)
+    }
+
+    /// The word for the exact result of `opcode`, under the overflow policy.
+    fn arithmetic(&self, opcode: &str, exact: i128) -> Result<i64, Fault> {
+        let word_size = self.machine.word_size;
+        if let Ok(value) = i64::try_from(exact) {
+            if (word_size.min()..=word_size.max()).contains(&value) {
+                return Ok(value);
             }
         }
+        match self.overflow_policy {
+            OverflowPolicy::Wrap => Ok(word_size.wrap(exact as i64)),
+            OverflowPolicy::Trap => Err(Fault::Overflow(opcode.to_string())),
+            OverflowPolicy::Saturate if exact < 0 => Ok(word_size.min()),
+            OverflowPolicy::Saturate => Ok(word_size.max()),
+        }
+    }
+
+    /// Shifts `value` by `amount` for the shift `opcode`: left, arithmetic
+    /// right (`rshift`) or logical right (`rshiftL`), under the shift policy.
+    fn shift(&self, opcode: &str, value: i64, amount: i64) -> Result<i64, Fault> {
+        let word_size = self.machine.word_size;
+        let bits = word_size.bits();
+        let amount = match u32::try_from(amount) {
+            Ok(amount) if amount < bits => amount,
+            _ => match self.shift_policy {
+                ShiftPolicy::Mask => (amount & (bits as i64 - 1)) as u32,
+                ShiftPolicy::Trap => return Err(Fault::ShiftOutOfRange { amount, bits }),
+                ShiftPolicy::ZeroFill if opcode.starts_with("rshiftL") => return Ok(0),
+                ShiftPolicy::ZeroFill if opcode.starts_with("rshift") => return Ok(value >> 63),
+                ShiftPolicy::ZeroFill => return Ok(0),
+            },
+        };
+        Ok(match opcode {
+            "lshift" | "lshiftI" => word_size.wrap(value << amount),
+            "rshiftL" | "rshiftLI" => word_size.wrap((word_size.unsigned(value) >> amount) as i64),
+            _ => value >> amount,
+        })
+    }
+
+    /// The address `base + offset`, computed in a word and read unsigned,
+    /// so that a negative offset wraps around the address space.
+    fn address(&self, base: i64, offset: i64) -> u64 {
+        self.machine.word_size.unsigned(base.wrapping_add(offset))
+    }
+
+    /// Reads `size` bytes at `address`, as the memory map permits.
+    fn load_bytes(&self, address: u64, size: usize) -> Result<Vec<u8>, Fault> {
+        let range = self.memory_map.check(address, size, Access::Read)?;
+        let mut bytes = vec![0; size];
+        self.memory.read(range.start, &mut bytes);
+        Ok(bytes)
+    }
+
+    /// Writes `bytes` at `address`, as the memory map permits; inside a
+    /// bundle, once the bundle is done.
+    fn store_bytes(&mut self, address: u64, bytes: Vec<u8>) -> Result<(), Fault> {
+        let range = self.memory_map.check(address, bytes.len(), Access::Write)?;
+        match self.bundle_writes.as_mut() {
+            Some(writes) => writes.stores.push((range.start, bytes)),
+            None => self.memory.write(range.start, &bytes),
+        }
+        Ok(())
+    }
+
+    fn read_word(&self, address: u64) -> Result<i64, Fault> {
+        let bytes = self.load_bytes(address, self.machine.word_size.bytes())?;
+        Ok(self.machine.decode(&bytes))
+    }
+
+    fn write_word(&mut self, address: u64, value: i64) -> Result<(), Fault> {
+        self.store_bytes(address, self.machine.encode(value))
+    }
+
+    /// Whether memory holds exactly the pages in `pages`.
+    fn memory_is(&self, pages: &[(usize, Vec<u8>)]) -> bool {
+        let current = self.memory.pages();
+        current.len() == pages.len()
+            && current
+                .iter()
+                .zip(pages)
+                .all(|((address, page), (at, saved))| address == at && **page == saved[..])
+    }
+
+    /// Writes `value` to `reg`; inside a bundle, once the bundle is done.
+    fn write_register(&mut self, reg: &str, value: i64) {
+        match self.bundle_writes.as_mut() {
+            Some(writes) => writes.registers.push((reg.to_string(), value)),
+            None => {
+                self.registers.insert(reg.to_string(), value);
+            }
+        }
+    }
+
+    /// Writes `value`, rounded to the float width, to `reg`; inside a
+    /// bundle, once the bundle is done.
+    fn set_float(&mut self, reg: &str, value: f64) {
+        let value = match self.float_width {
+            FloatWidth::F32 => value as f32 as f64,
+            FloatWidth::F64 => value,
+        };
+        match self.bundle_writes.as_mut() {
+            Some(writes) => writes.float_registers.push((reg.to_string(), value)),
+            None => {
+                self.float_registers.insert(reg.to_string(), value);
+            }
+        }
+    }
+
+    fn read_float(&self, address: u64) -> Result<f64, Fault> {
+        let bytes = self.load_bytes(address, self.float_width.bytes())?;
+        let bytes = self.machine.endianness.order(bytes);
+        Ok(match self.float_width {
+            FloatWidth::F32 => f32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            FloatWidth::F64 => f64::from_le_bytes(bytes.try_into().unwrap()),
+        })
+    }
+
+    fn write_float(&mut self, address: u64, value: f64) -> Result<(), Fault> {
+        let bytes = match self.float_width {
+            FloatWidth::F32 => (value as f32).to_le_bytes().to_vec(),
+            FloatWidth::F64 => value.to_le_bytes().to_vec(),
+        };
+        self.store_bytes(address, self.machine.endianness.order(bytes))
+    }
+
+    /// Everything the program has printed with `output` so far.
+    pub fn get_output(&self) -> &str {
+        &self.output
+    }
+
+    pub fn get_cycle_stats(&self) -> CycleStats {
+        self.cycle_model.stats()
+    }
+
+    /// Number of program lines executed so far; a bundle counts once.
+    pub fn get_instruction_count(&self) -> u64 {
+        self.executed
+    }
+
+    /// Times each program line has been executed.
+    pub fn get_line_counts(&self) -> &[u64] {
+        &self.line_counts
+    }
+
+    /// Cycles each program line has advanced the clock by: its latency
+    /// without a pipeline, or its issue cycle plus any stalls with one.
+    pub fn get_line_cycles(&self) -> &[u64] {
+        &self.line_cycles
+    }
+
+    /// For each program line, how often a `cbr` on it was taken to its
+    /// first label and to its second; zeros for every other line.
+    pub fn get_branch_counts(&self) -> &[[u64; 2]] {
+        &self.branch_counts
     }
 
-    pub fn get_state(&self) -> (&HashMap<String, i32>, &[u8], usize) {
-        (&self.registers, &self.memory, self.pc)
+    pub fn get_bundle_stats(&self) -> BundleStats {
+        self.bundle_stats
     }
 
     pub fn get_program(&self) -> Vec<String> {
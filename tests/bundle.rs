@@ -0,0 +1,170 @@
+use iloc::latency::{CycleModel, LatencyTable};
+use std::sync::{Arc, Mutex};
+
+#[test]
+fn bundle_parse_normalizes() {
+    let program = iloc::parser::parse_iloc(
+        "
+        [add r1, r2 => r3;loadI 4 => r5]
+        L1:   [ nop ; nop ]
+        ",
+    )
+    .unwrap();
+
+    assert_eq!(program[0], "[ add r1,r2 => r3 ; loadI 4 => r5 ]");
+    assert_eq!(program[1], "L1: [ nop ; nop ]");
+}
+
+#[test]
+fn bundle_parse_malformed() {
+    assert!(iloc::parser::parse_iloc("[ loadI 1 => r1").is_err());
+    assert!(iloc::parser::parse_iloc("[ ]").is_err());
+    assert!(iloc::parser::parse_iloc("[ [ nop ] ]").is_err());
+}
+
+#[test]
+fn bundle_reads_before_writes() {
+    let program = "
+    loadI 1 => r1
+    loadI 2 => r2
+    [ i2i r1 => r2 ; i2i r2 => r1 ]
+    ";
+    let vm = Arc::new(Mutex::new(iloc::vm::VM::new(1024)));
+    vm.lock()
+        .unwrap()
+        .load_program(iloc::parser::parse_iloc(program).unwrap());
+
+    vm.lock().unwrap().run();
+
+    let binding = vm.lock().unwrap();
+    let state = binding.get_state();
+    let registers = &state.0;
+
+    assert_eq!(registers["r1"], 2);
+    assert_eq!(registers["r2"], 1);
+}
+
+#[test]
+fn bundle_memory_reads_before_writes() {
+    let program = "
+    loadI 0 => r0
+    loadI 7 => r1
+    store r1 => r0
+    loadI 9 => r2
+    [ store r2 => r0 ; load r0 => r3 ]
+    load r0 => r4
+    ";
+    let vm = Arc::new(Mutex::new(iloc::vm::VM::new(1024)));
+    vm.lock()
+        .unwrap()
+        .load_program(iloc::parser::parse_iloc(program).unwrap());
+
+    vm.lock().unwrap().run();
+
+    let binding = vm.lock().unwrap();
+    let state = binding.get_state();
+    let registers = &state.0;
+
+    assert_eq!(registers["r3"], 7);
+    assert_eq!(registers["r4"], 9);
+}
+
+#[test]
+fn bundle_last_write_wins() {
+    let program = "
+    loadI 5 => r1
+    loadI 2 => r2
+    i2f r2 => f1
+    [ loadI 7 => r1 ; loadI 5 => r1 ; i2f r1 => f1 ; i2f r2 => f1 ]
+    ";
+    let vm = Arc::new(Mutex::new(iloc::vm::VM::new(1024)));
+    vm.lock()
+        .unwrap()
+        .load_program(iloc::parser::parse_iloc(program).unwrap());
+
+    vm.lock().unwrap().run();
+
+    let binding = vm.lock().unwrap();
+    let state = binding.get_state();
+    let registers = &state.0;
+
+    // The later writes win even though they restore the values from before
+    assert_eq!(registers["r1"], 5);
+    assert_eq!(binding.get_float_registers()["f1"], 2.0);
+}
+
+#[test]
+#[should_panic(expected = "exceeds 2 functional units")]
+fn bundle_exceeds_functional_units() {
+    let program = "[ loadI 1 => r1 ; loadI 2 => r2 ; loadI 3 => r3 ]";
+    let mut vm = iloc::vm::VM::new(1024);
+    vm.load_program(iloc::parser::parse_iloc(program).unwrap());
+    vm.set_functional_units(2);
+
+    vm.run();
+}
+
+#[test]
+fn bundle_utilization() {
+    let program = "
+    [ loadI 1 => r1 ; loadI 2 => r2 ]
+    [ add r1, r2 => r3 ; nop ]
+    [ nop ; nop ]
+    ";
+    let mut vm = iloc::vm::VM::new(1024);
+    vm.load_program(iloc::parser::parse_iloc(program).unwrap());
+    vm.set_functional_units(4);
+    vm.run();
+
+    let stats = vm.get_bundle_stats();
+    assert_eq!(stats.bundles, 3);
+    assert_eq!(stats.operations, 3);
+    assert_eq!(stats.slots, 12);
+    assert_eq!(stats.utilization(), 0.25);
+    assert_eq!(vm.get_state().0["r3"], 3);
+}
+
+#[test]
+fn bundle_issues_in_one_cycle() {
+    let program = "
+    [ loadI 1 => r1 ; loadI 2 => r2 ; loadI 3 => r3 ]
+    [ add r1, r2 => r4 ; mult r2, r3 => r5 ]
+    add r4, r5 => r6
+    ";
+    let mut vm = iloc::vm::VM::new(1024);
+    vm.load_program(iloc::parser::parse_iloc(program).unwrap());
+    vm.set_cycle_model(CycleModel::new(LatencyTable::default(), true));
+    vm.run();
+
+    // The mult finishes a cycle after the add, so the last add stalls once
+    assert_eq!(vm.get_cycle_stats().stalls, 1);
+    assert_eq!(vm.get_cycle_stats().cycles, 4);
+    assert_eq!(vm.get_state().0["r6"], 9);
+}
+
+#[test]
+fn bundle_scheduled_program() {
+    let program = iloc::parser::parse_iloc(
+        "
+        loadI 0 => r0
+        loadI 3 => r1
+        loadI 4 => r2
+        mult r1, r2 => r3
+        add r1, r2 => r4
+        L1: sub r3, r4 => r5
+        store r5 => r0
+        ",
+    )
+    .unwrap();
+    let schedule = iloc::scheduler::schedule(&program, &LatencyTable::default(), 2).unwrap();
+    let bundled = schedule.bundled_program();
+
+    assert_eq!(bundled[0], "[ loadI 3 => r1 ; loadI 4 => r2 ]");
+    assert!(bundled.iter().any(|line| line.starts_with("L1: ")));
+
+    let mut vm = iloc::vm::VM::new(1024);
+    vm.load_program(iloc::parser::parse_iloc(&bundled.join("\n")).unwrap());
+    vm.set_functional_units(2);
+    vm.run();
+    assert_eq!(vm.get_state().1[0], 5);
+}
@@ -0,0 +1,114 @@
+use iloc::coverage::{html_page, Coverage};
+use iloc::parser::parse_with_lines;
+use iloc::vm::VM;
+
+// Doubles r1 if it is positive, otherwise negates it
+const ABS_DOUBLE: &str = "// r1 holds the input
+loadI 0 => r2
+
+cmp_GT r1, r2 => r3
+cbr r3 -> L1, L2   // positive?
+L1: add r1, r1 => r4
+jumpI -> L3
+/* negative
+   or zero */
+L2: sub r2, r1 => r4
+L3: nop
+";
+
+fn run(input: i64) -> Coverage {
+    let (source_lines, program): (Vec<usize>, Vec<String>) =
+        parse_with_lines(ABS_DOUBLE).unwrap().into_iter().unzip();
+    let mut vm = VM::new(1024);
+    vm.load_program(program);
+    vm.set_register("r1", input);
+    vm.run();
+    Coverage::from_vm(&vm, &source_lines)
+}
+
+#[test]
+fn coverage_parse_keeps_source_lines() {
+    let lines: Vec<usize> = parse_with_lines(ABS_DOUBLE)
+        .unwrap()
+        .into_iter()
+        .map(|(line, _)| line)
+        .collect();
+
+    assert_eq!(lines, vec![2, 4, 5, 6, 7, 10, 11]);
+}
+
+#[test]
+fn coverage_lines_and_branches() {
+    let coverage = run(5);
+
+    assert_eq!(coverage.lines[&6], 1);
+    assert_eq!(coverage.missed_lines(), vec![10]);
+    let branch = coverage.branches[&5];
+    assert!(branch.executed);
+    assert_eq!((branch.taken, branch.not_taken), (1, 0));
+}
+
+#[test]
+fn coverage_merge_runs() {
+    let mut coverage = run(5);
+    coverage.merge(&run(-3));
+    coverage.merge(&run(7));
+
+    assert!(coverage.missed_lines().is_empty());
+    assert_eq!(coverage.lines[&11], 3);
+    let branch = coverage.branches[&5];
+    assert_eq!((branch.taken, branch.not_taken), (2, 1));
+}
+
+#[test]
+fn coverage_lcov_record() {
+    let info = run(-1).to_lcov("abs.iloc");
+
+    assert_eq!(
+        info,
+        "TN:
+SF:abs.iloc
+BRDA:5,0,0,0
+BRDA:5,0,1,1
+BRF:2
+BRH:1
+DA:2,1
+DA:4,1
+DA:5,1
+DA:6,0
+DA:7,0
+DA:10,1
+DA:11,1
+LF:7
+LH:5
+end_of_record
+"
+    );
+
+    // A branch that never ran has no counts at all
+    let mut vm = VM::new(1024);
+    vm.load_program(vec![
+        "jumpI -> L1".into(),
+        "cbr r1 -> L1,L1".into(),
+        "L1: nop".into(),
+    ]);
+    vm.run();
+    let info = Coverage::from_vm(&vm, &[1, 2, 3]).to_lcov("skip.iloc");
+    assert!(info.contains("BRDA:2,0,0,-\nBRDA:2,0,1,-\nBRF:2\nBRH:0\n"));
+}
+
+#[test]
+fn coverage_html_report() {
+    let section = run(5).to_html("abs.iloc", ABS_DOUBLE);
+
+    assert!(section.contains("<h2>abs.iloc</h2>\n<p>6 of 7 lines covered</p>"));
+    assert!(section.contains("<tr class=\"missed\"><td class=\"line\">10</td>"));
+    assert!(section.contains("<pre>L1: add r1, r1 =&gt; r4</pre>"));
+    assert!(section.contains("taken 1 / not taken 0"));
+    // Comment lines are shown but not counted
+    assert!(section.contains("<tr><td class=\"line\">8</td><td class=\"count\"></td>"));
+
+    let page = html_page(&[section]);
+    assert!(page.starts_with("<!DOCTYPE html>"));
+    assert!(page.ends_with("</html>\n"));
+}
@@ -0,0 +1,161 @@
+use iloc::diffexec::{diff_exec_programs, DiffConfig, Divergence};
+use iloc::parser::{parse_iloc, parse_program};
+use iloc::vm::VM;
+
+const SUM: &str = r#"
+    .data
+    count: .word 4
+    values: .word 1, 2, 3, 0x10
+    greeting: .string "hi // there"
+    .align 4
+    table: .word values, count
+    .text
+    loadI values => r1
+    loadI count => r2
+    load r2 => r2
+    loadI 0 => r3
+    L1: load r1 => r4
+    add r3, r4 => r3
+    addI r1, 4 => r1
+    subI r2, 1 => r2
+    cbr r2 -> L1, L2
+    L2: nop
+"#;
+
+fn run(source: &str) -> VM {
+    let program = parse_program(source, "test.iloc").unwrap();
+    let mut vm = VM::new(1024);
+    vm.load(&program).unwrap();
+    vm.run();
+    vm
+}
+
+#[test]
+fn data_layout() {
+    let program = parse_program(SUM, "test.iloc").unwrap();
+    let data = &program.data;
+
+    assert_eq!(data.labels["count"], 0);
+    assert_eq!(data.labels["values"], 4);
+    assert_eq!(data.labels["greeting"], 20);
+    assert_eq!(&data.bytes[4..8], &[1, 0, 0, 0]);
+    assert_eq!(&data.bytes[20..32], b"hi // there\0");
+    // Aligned up from 32 is still 32; the table holds addresses
+    assert_eq!(data.labels["table"], 32);
+    assert_eq!(&data.bytes[32..40], &[4, 0, 0, 0, 0, 0, 0, 0]);
+}
+
+#[test]
+fn data_labels_in_load_immediate() {
+    let program = parse_program(SUM, "test.iloc").unwrap();
+
+    assert_eq!(program.text()[0], "loadI 4 => r1");
+    assert_eq!(program.text()[1], "loadI 0 => r2");
+    assert_eq!(program.code[0].line, 9);
+    assert_eq!(parse_iloc(SUM).unwrap(), program.text());
+}
+
+#[test]
+fn data_preloaded_into_memory() {
+    let vm = run(SUM);
+    let (registers, memory, _) = vm.get_state();
+
+    assert_eq!(registers["r3"], 22);
+    assert_eq!(memory[21], b'i');
+}
+
+#[test]
+fn data_space_byte_and_align() {
+    let source = "
+        .data
+        a: .byte 1, -1, 0x7f
+        .align 4
+        b: .space 6
+        c: .byte 9
+        .align 8
+        d:
+        .word -2
+        .text
+        loadI d => r1
+        load r1 => r2
+    ";
+    let program = parse_program(source, "").unwrap();
+    let data = &program.data;
+
+    assert_eq!(&data.bytes[..3], &[1, 0xff, 0x7f]);
+    assert_eq!(data.labels["b"], 4);
+    assert_eq!(data.labels["c"], 10);
+    assert_eq!(data.labels["d"], 16);
+    assert_eq!(run(source).get_state().0["r2"], -2);
+}
+
+#[test]
+fn data_errors() {
+    let error = |source: &str| parse_program(source, "").unwrap_err();
+
+    assert_eq!(error(".word 1\nnop"), "Line 1: .word outside of .data");
+    assert_eq!(
+        error(".data\n.byte 256\n.text\nnop"),
+        "Line 2: Value 256 does not fit in a byte"
+    );
+    assert_eq!(
+        error(".data\nadd r1, r2 => r3"),
+        "Line 2: Instruction 'add r1, r2 => r3' inside .data"
+    );
+    assert_eq!(
+        error(".data\n.float 1.0\n.text\nnop"),
+        "Line 2: Unknown directive '.float'"
+    );
+    assert_eq!(
+        error(".data\n.word nowhere\n.text\nnop"),
+        "Line 2: Unknown data label 'nowhere'"
+    );
+    assert_eq!(
+        error("nop\nloadI nowhere => r1"),
+        "Line 2: Unknown data label 'nowhere'"
+    );
+    assert_eq!(
+        error(".data\nx: .word 1\nx: .word 2\n.text\nnop"),
+        "Line 3: Duplicate data label 'x'"
+    );
+    assert_eq!(
+        error(".data\nL1: .word 1\n.text\nL1: nop"),
+        "Line 4: 'L1' is both a code and a data label"
+    );
+    assert_eq!(error(".data\n.word 1"), "Program is empty");
+    assert_eq!(
+        error(".data\nbuf: .space 0x7fffffffffffffff\n.text\nnop"),
+        "Line 2: Data section too large: it must fit in 4294967296 bytes"
+    );
+    assert_eq!(
+        error(".data\n.byte 1\n.align 0x7fffffffffffffff\n.text\nnop"),
+        "Line 3: Data section too large: it must fit in 4294967296 bytes"
+    );
+
+    let program = parse_program(".data\n.space 2000\n.text\nnop", "").unwrap();
+    assert!(VM::new(1024).load(&program).is_err());
+}
+
+#[test]
+fn data_kept_by_diff_exec() {
+    let left = parse_program(".data\nx: .word 7\n.text\nloadI x => r1\nload r1 => r2", "").unwrap();
+    let right = parse_program("loadI 7 => r2", "").unwrap();
+    let config = DiffConfig {
+        result_registers: vec!["r2".to_string()],
+        ..DiffConfig::default()
+    };
+
+    // The data section is in memory, so r2 agrees but memory does not
+    let report = diff_exec_programs(&left, &right, &config);
+    assert_eq!(
+        report.divergence,
+        Some((
+            None,
+            Divergence::Memory {
+                address: 0,
+                left: 7,
+                right: 0
+            }
+        ))
+    );
+}
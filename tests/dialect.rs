@@ -0,0 +1,116 @@
+use iloc::dialect::{translate, Dialect};
+use iloc::parser::{parse_iloc, parse_program_in};
+use iloc::vm::VM;
+
+fn parse(source: &str, dialect: Dialect) -> Result<Vec<String>, String> {
+    parse_program_in(source, "", dialect).map(|program| program.text())
+}
+
+const EAC2: &str = "loadI 6 => r1
+L1: subI r1, 2 => r1
+cbr r1 -> L1, L2
+L2: jumpI -> L3
+L3: nop";
+
+#[test]
+fn dialect_names() {
+    assert_eq!("eac2".parse(), Ok(Dialect::Eac2));
+    assert_eq!("eac3".parse(), Ok(Dialect::Eac3));
+    assert_eq!("lenient".parse(), Ok(Dialect::Lenient));
+    assert_eq!(Dialect::default(), Dialect::Lenient);
+    assert_eq!(
+        "eac4".parse::<Dialect>(),
+        Err("Unknown dialect 'eac4', expected one of eac2, eac3, lenient".to_string())
+    );
+}
+
+#[test]
+fn dialect_arrows() {
+    let normalized = parse(EAC2, Dialect::Eac2).unwrap();
+    let eac3 = EAC2.replace("->", "=>");
+
+    assert_eq!(parse(&eac3, Dialect::Eac3).unwrap(), normalized);
+    assert_eq!(parse(&eac3, Dialect::Lenient).unwrap(), normalized);
+    assert_eq!(normalized[2], "cbr r1 -> L1,L2");
+    assert_eq!(
+        parse(&eac3, Dialect::Eac2).unwrap_err(),
+        "Line 3: 'cbr' takes '->' in EaC 2nd edition ILOC"
+    );
+    assert_eq!(
+        parse(EAC2, Dialect::Eac3).unwrap_err(),
+        "Line 3: 'cbr' takes '=>' in EaC 3rd edition ILOC"
+    );
+    assert_eq!(
+        parse("loadI 1 -> r1", Dialect::Eac2).unwrap_err(),
+        "Line 1: 'loadI' takes '=>' in EaC 2nd edition ILOC"
+    );
+}
+
+#[test]
+fn dialect_commas_and_opcode_case() {
+    assert_eq!(
+        parse(
+            "LOADI 0x10 => r1\nadd r1 r1 => r2\nstoreAI r2 => r1 4",
+            Dialect::Lenient
+        )
+        .unwrap(),
+        vec!["loadI 16 => r1", "add r1,r1 => r2", "storeAI r2 => r1,4"]
+    );
+    // Expressions keep their spaces
+    assert_eq!(
+        parse("addI r1, 4 * 2 => r2", Dialect::Lenient).unwrap(),
+        vec!["addI r1,8 => r2"]
+    );
+    assert_eq!(
+        parse("add r1 r1 => r2", Dialect::Eac2).unwrap_err(),
+        "Line 1: Operands 'r1 r1' must be separated by commas in EaC 2nd edition ILOC"
+    );
+}
+
+#[test]
+fn dialect_opcode_sets() {
+    assert_eq!(
+        parse("loadI 1 => r1\noutput r1", Dialect::Eac2).unwrap_err(),
+        "Line 2: Unknown opcode 'output' in EaC 2nd edition ILOC"
+    );
+    assert_eq!(
+        parse("[ nop ; LOADI 1 => r1 ]", Dialect::Eac3).unwrap_err(),
+        "Line 1: Unknown opcode 'LOADI' in EaC 3rd edition ILOC"
+    );
+    assert!(parse("loadI 1 => r1\noutput r1", Dialect::Lenient).is_ok());
+    assert_eq!(
+        parse("nop\nfrobnicate r1 => r2", Dialect::Lenient).unwrap_err(),
+        "Line 2: Unknown opcode 'frobnicate' in lenient ILOC"
+    );
+    assert!(Dialect::Lenient.accepts("LoadI"));
+    assert!(!Dialect::Lenient.accepts("frobnicate"));
+}
+
+#[test]
+fn dialect_translate() {
+    let eac3 = translate(EAC2, "", Dialect::Eac2, Dialect::Eac3).unwrap();
+
+    assert!(eac3.contains("cbr   r1    => L1, L2"), "{}", eac3);
+    assert_eq!(
+        parse(&eac3, Dialect::Eac3).unwrap(),
+        parse_iloc(EAC2).unwrap()
+    );
+    let back = translate(&eac3, "", Dialect::Eac3, Dialect::Eac2).unwrap();
+    assert_eq!(
+        parse(&back, Dialect::Eac2).unwrap(),
+        parse_iloc(EAC2).unwrap()
+    );
+}
+
+#[test]
+fn dialect_logical_immediates_execute() {
+    let source = "loadI 6 => r1\nandI r1, 3 => r2\norI r1, 9 => r3\nxorI r1, 5 => r4";
+    let mut vm = VM::new(1024);
+    vm.load_program(parse_iloc(source).unwrap());
+    vm.run();
+    let (registers, _, _) = vm.get_state();
+
+    assert_eq!(registers["r2"], 2);
+    assert_eq!(registers["r3"], 15);
+    assert_eq!(registers["r4"], 3);
+}
@@ -0,0 +1,204 @@
+use iloc::diffexec::{diff_exec, DiffConfig, Divergence};
+use iloc::vm::{Fault, Termination};
+
+fn parse(program: &str) -> Vec<String> {
+    iloc::parser::parse_iloc(program).unwrap()
+}
+
+// Sums memory words 0..4 into r3 and prints the sum
+const SUM: &str = "
+    loadI 0 => r1
+    loadI 0 => r3
+    loadI 16 => r4
+    L1: load r1 => r2
+    add r3, r2 => r3
+    addI r1, 4 => r1
+    cmp_LT r1, r4 => r5
+    cbr r5 -> L1, L2
+    L2: loadI 100 => r6
+    store r3 => r6
+    output 100
+    ";
+
+// The same loop, unrolled by hand
+const UNROLLED: &str = "
+    loadI 0 => r1
+    load r1 => r3
+    loadAI r1, 4 => r2
+    add r3, r2 => r3
+    loadAI r1, 8 => r2
+    add r3, r2 => r3
+    loadAI r1, 12 => r2
+    add r3, r2 => r3
+    loadI 100 => r6
+    store r3 => r6
+    output 100
+    ";
+
+#[test]
+fn diff_exec_output_instruction() {
+    let mut vm = iloc::vm::VM::new(1024);
+    vm.load_program(parse(
+        "
+        loadI 42 => r1
+        loadI 64 => r2
+        store r1 => r2
+        output 64
+        ",
+    ));
+    vm.run();
+
+    assert_eq!(vm.get_output(), "42\n");
+}
+
+#[test]
+fn diff_exec_equivalent_programs() {
+    let config = DiffConfig {
+        seed: Some(7),
+        trials: 20,
+        result_registers: vec!["r3".to_string()],
+        ..DiffConfig::default()
+    };
+    let report = diff_exec(&parse(SUM), &parse(UNROLLED), &config);
+
+    assert_eq!(report.divergence, None);
+    assert_eq!(report.agreeing_trials, 20);
+}
+
+#[test]
+fn diff_exec_reports_output_first() {
+    let wrong = UNROLLED.replace("loadAI r1, 12 => r2", "loadAI r1, 8 => r2");
+    let config = DiffConfig {
+        seed: Some(1),
+        ..DiffConfig::default()
+    };
+    let report = diff_exec(&parse(SUM), &parse(&wrong), &config);
+
+    let (seed, divergence) = report.divergence.unwrap();
+    assert_eq!(seed, Some(1));
+    assert!(matches!(divergence, Divergence::Output { line: 1, .. }));
+}
+
+#[test]
+fn diff_exec_memory_and_registers() {
+    let left = parse("loadI 1 => r1\nloadI 8 => r2\nstore r1 => r2");
+    let right = parse("loadI 1 => r3\nloadI 9 => r2\nstore r3 => r2");
+    let report = diff_exec(&left, &right, &DiffConfig::default());
+    assert_eq!(
+        report.divergence.unwrap().1,
+        Divergence::Memory {
+            address: 8,
+            left: 1,
+            right: 0
+        }
+    );
+
+    let right = parse("loadI 1 => r3\nloadI 8 => r2\nstore r3 => r2");
+    let config = DiffConfig {
+        result_registers: vec!["r2".to_string(), "r1".to_string()],
+        ..DiffConfig::default()
+    };
+    let divergence = diff_exec(&left, &right, &config).divergence.unwrap().1;
+    assert_eq!(
+        divergence,
+        Divergence::Register {
+            name: "r1".to_string(),
+            left: Some(1),
+            right: None
+        }
+    );
+    assert_eq!(divergence.to_string(), "Register r1: 1 vs undefined");
+}
+
+#[test]
+fn diff_exec_seeds_inputs() {
+    // Both read r1 before writing it, so it is randomized per trial
+    let left = parse("multI r1, 2 => r2");
+    let right = parse("add r1, r1 => r2");
+    let wrong = parse("addI r1, 2 => r2");
+    let config = DiffConfig {
+        seed: Some(3),
+        trials: 5,
+        result_registers: vec!["r2".to_string()],
+        ..DiffConfig::default()
+    };
+
+    assert_eq!(diff_exec(&left, &right, &config).divergence, None);
+    assert!(diff_exec(&left, &wrong, &config).divergence.is_some());
+
+    // Explicit inputs take precedence: 2 * 2 == 2 + 2
+    let config = DiffConfig {
+        inputs: [("r1".to_string(), 2)].into_iter().collect(),
+        ..config
+    };
+    assert_eq!(diff_exec(&left, &wrong, &config).divergence, None);
+}
+
+#[test]
+fn diff_exec_termination() {
+    let looping = parse("L1: jumpI -> L1");
+    let faulting = parse("loadI 0 => r1\ndivI r1, 0 => r2");
+    let config = DiffConfig {
+        max_steps: 100,
+        ..DiffConfig::default()
+    };
+    let divergence = diff_exec(&looping, &faulting, &config)
+        .divergence
+        .unwrap()
+        .1;
+
+    assert_eq!(
+        divergence,
+        Divergence::Termination(
+            Termination::StepLimit,
+            Termination::Fault(Fault::DivideByZero)
+        )
+    );
+}
+
+#[test]
+fn diff_exec_crashes_are_terminations() {
+    let report = diff_exec(
+        &parse("i2i r9 => r1"),
+        &parse("loadI 0 => r1"),
+        &DiffConfig::default(),
+    );
+
+    assert_eq!(
+        report.divergence,
+        Some((
+            None,
+            Divergence::Termination(
+                Termination::Crashed("no entry found for key".to_string()),
+                Termination::Finished
+            )
+        ))
+    );
+}
+
+#[test]
+fn diff_exec_seeds_float_registers() {
+    let config = DiffConfig {
+        seed: Some(3),
+        result_registers: vec!["r1".to_string()],
+        ..DiffConfig::default()
+    };
+    let report = diff_exec(
+        &parse("fadd f1, f1 => f2"),
+        &parse("fadd f1, f1 => f2\nloadI 1 => r1"),
+        &config,
+    );
+
+    // Both runs get past the float read, so only the result differs
+    assert_eq!(
+        report.divergence,
+        Some((
+            Some(3),
+            Divergence::Register {
+                name: "r1".to_string(),
+                left: None,
+                right: Some(1)
+            }
+        ))
+    );
+}
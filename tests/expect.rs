@@ -0,0 +1,157 @@
+use iloc::expect::{check, parse_expectations, Expectation, Failure};
+use iloc::vm::{Fault, VM};
+
+#[test]
+fn expect_parse_directives() {
+    let source = r#"
+    loadI 15 => r2 //! expect r2 == 15
+    //! expect r3 == -0x10
+    //! expect mem[0x40..0x44] == 01 00 00 00
+    //! expect mem[8] == ff
+    //! expect output "42\n"
+    //! expect fault DivideByZero
+    "#;
+    let expectations: Vec<Expectation> = parse_expectations(source)
+        .unwrap()
+        .into_iter()
+        .map(|(_, expectation)| expectation)
+        .collect();
+
+    assert_eq!(
+        expectations,
+        vec![
+            Expectation::Register {
+                name: "r2".to_string(),
+                value: 15
+            },
+            Expectation::Register {
+                name: "r3".to_string(),
+                value: -16
+            },
+            Expectation::Memory {
+                address: 0x40,
+                bytes: vec![1, 0, 0, 0]
+            },
+            Expectation::Memory {
+                address: 8,
+                bytes: vec![0xff]
+            },
+            Expectation::Output("42\n".to_string()),
+            Expectation::Fault("DivideByZero".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn expect_parse_errors() {
+    assert!(parse_expectations("//! expcet r1 == 1").is_err());
+    assert!(parse_expectations("//! expect r1 = 1").is_err());
+    assert!(parse_expectations("//! expect mem[0..4] == 01 02").is_err());
+    assert!(parse_expectations("//! expect output 42").is_err());
+    assert_eq!(
+        parse_expectations("nop\n//! expect r1 == x").unwrap_err(),
+        "Line 2: Invalid number 'x'"
+    );
+}
+
+#[test]
+fn expect_passing_program() {
+    let source = r#"
+    // Stores 1 at 0x40 and prints 42
+    loadI 1 => r1
+    loadI 64 => r2
+    store r1 => r2
+    loadI 42 => r3
+    storeAI r3 => r2, 4
+    output 68
+    add r3, r1 => r4
+    //! expect r4 == 43
+    //! expect mem[0x40..0x44] == 01 00 00 00
+    //! expect output "42\n"
+    "#;
+
+    assert_eq!(check(source, "").unwrap(), vec![]);
+}
+
+#[test]
+fn expect_failures_name_their_lines() {
+    let source = "
+    loadI 7 => r1
+    //! expect r1 == 8
+    //! expect r2 == 0
+    //! expect mem[0] == 01
+    ";
+    let failures = check(source, "").unwrap();
+
+    assert_eq!(failures.len(), 3);
+    assert_eq!(failures[0].to_string(), "Line 3: expected r1 == 8, got 7");
+    assert_eq!(failures[1].line, Some(4));
+    assert_eq!(failures[2].message, "expected mem[0x0..0x1] == 01, got 00");
+}
+
+#[test]
+fn expect_faults() {
+    let source = "
+    loadI 0 => r1
+    loadI 5 => r2
+    div r2, r1 => r3
+    //! expect fault DivideByZero
+    ";
+    assert_eq!(check(source, "").unwrap(), vec![]);
+
+    // Without the directive the fault itself is a failure
+    let failures = check("loadI 0 => r1\ndivI r1, 0 => r2", "").unwrap();
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].line, None);
+    assert!(failures[0].message.contains("DivideByZero"));
+
+    let failures = check("nop\n//! expect fault DivideByZero", "").unwrap();
+    assert_eq!(
+        failures[0].message,
+        "expected fault DivideByZero, but the program finished"
+    );
+}
+
+#[test]
+fn expect_unknown_opcodes_fault() {
+    let mut vm = VM::new(64);
+    vm.load_program(vec![
+        "loadI 1 => r1".to_string(),
+        "frobnicate r1 => r2".to_string(),
+    ]);
+    assert_eq!(
+        vm.try_run(),
+        Err(Fault::Unimplemented("frobnicate".to_string()))
+    );
+    assert_eq!(
+        parse_expectations("//! expect r1 == 0x100000000").unwrap()[0].1,
+        Expectation::Register {
+            name: "r1".to_string(),
+            value: 1 << 32
+        }
+    );
+}
+
+#[test]
+fn expect_includes_resolve_next_to_the_program() {
+    let dir = std::env::temp_dir().join(format!("iloc-expect-include-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("lib.inc"), "loadI 7 => r7").unwrap();
+    let file = dir.join("inc.iloc").display().to_string();
+
+    let source = "#include \"lib.inc\"\n//! expect r7 == 7";
+    assert_eq!(check(source, &file).unwrap(), vec![]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn expect_crashes_are_failures() {
+    assert_eq!(
+        check("add r1, r2 => r3\n//! expect r3 == 0", "").unwrap(),
+        vec![Failure {
+            line: None,
+            message: "Crashed: no entry found for key".to_string()
+        }]
+    );
+}
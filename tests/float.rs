@@ -0,0 +1,147 @@
+use iloc::parser::{parse_iloc, parse_program};
+use iloc::vm::{FloatWidth, VM};
+
+fn run(source: &str, width: FloatWidth) -> VM {
+    let program = parse_program(source, "").unwrap();
+    let mut vm = VM::new(1024);
+    vm.set_float_width(width);
+    vm.load(&program).unwrap();
+    vm.run();
+    vm
+}
+
+#[test]
+fn float_arithmetic() {
+    let source = "
+        loadF 1.5 => f1
+        loadI 2.25 => f2
+        fadd f1, f2 => f3
+        fsub f1, f2 => f4
+        fmult f1, f2 => f5
+        fdiv f1, f2 => f6
+        f2f f6 => f7
+    ";
+    let vm = run(source, FloatWidth::F32);
+    let floats = vm.get_float_registers();
+
+    assert_eq!(floats["f3"], 3.75);
+    assert_eq!(floats["f4"], -0.75);
+    assert_eq!(floats["f5"], 3.375);
+    // Rounded to f32 precision
+    assert_eq!(floats["f6"], (1.5f32 / 2.25f32) as f64);
+    assert_eq!(floats["f7"], floats["f6"]);
+    // Float registers are a class of their own
+    assert!(vm.get_state().0.is_empty());
+}
+
+#[test]
+fn float_width_f64() {
+    let source = "loadF 0.1 => f1\nloadF 0.2 => f2\nfadd f1, f2 => f3";
+
+    assert_eq!(
+        run(source, FloatWidth::F64).get_float_registers()["f3"],
+        0.1 + 0.2
+    );
+    assert_eq!(
+        run(source, FloatWidth::F32).get_float_registers()["f3"],
+        (0.1f32 + 0.2f32) as f64
+    );
+}
+
+#[test]
+fn float_memory_is_little_endian() {
+    let source = "
+        loadI 16 => r1
+        loadI 4 => r2
+        loadF -2.5 => f1
+        fstore f1 => r1
+        fstoreAI f1 => r1, 8
+        fstoreAO f1 => r1, r2
+        floadAI r1, 8 => f2
+        floadAO r1, r2 => f3
+        fload r1 => f4
+    ";
+    let vm = run(source, FloatWidth::F32);
+    let (_, memory, _) = vm.get_state();
+
+    assert_eq!(&memory[16..20], &(-2.5f32).to_le_bytes());
+    assert_eq!(&memory[20..24], &(-2.5f32).to_le_bytes());
+    assert_eq!(&memory[24..28], &(-2.5f32).to_le_bytes());
+    for reg in ["f2", "f3", "f4"] {
+        assert_eq!(vm.get_float_registers()[reg], -2.5);
+    }
+
+    let vm = run(
+        "loadI 8 => r1\nloadF -2.5 => f1\nfstore f1 => r1",
+        FloatWidth::F64,
+    );
+    assert_eq!(&vm.get_state().1[8..16], &(-2.5f64).to_le_bytes());
+}
+
+#[test]
+fn float_conversions_and_comparisons() {
+    let source = "
+        loadI 7 => r1
+        i2f r1 => f1
+        loadF 2 => f2
+        fdiv f1, f2 => f3
+        f2i f3 => r2
+        loadF -3.9 => f4
+        f2i f4 => r3
+        fcmp_LT f2, f3 => r4
+        fcmp_GE f2, f3 => r5
+        fcmp_EQ f1, f1 => r6
+        fcmp_NE f1, f2 => r7
+    ";
+    let vm = run(source, FloatWidth::F32);
+    let (registers, _, _) = vm.get_state();
+
+    assert_eq!(vm.get_float_registers()["f3"], 3.5);
+    assert_eq!(registers["r2"], 3);
+    assert_eq!(registers["r3"], -3);
+    assert_eq!(
+        [
+            registers["r4"],
+            registers["r5"],
+            registers["r6"],
+            registers["r7"]
+        ],
+        [1, 0, 1, 1]
+    );
+}
+
+#[test]
+fn float_immediates_parsed() {
+    assert_eq!(
+        parse_iloc("loadI 1.5 => f1\nloadF 1_000.25 => f2\nloadF 2 => f3\nloadF -1e-3 => f4")
+            .unwrap(),
+        vec![
+            "loadF 1.5 => f1",
+            "loadF 1000.25 => f2",
+            "loadF 2.0 => f3",
+            "loadF -0.001 => f4",
+        ]
+    );
+    assert_eq!(
+        parse_iloc("loadF 1e999 => f1").unwrap_err(),
+        "Line 1: Value 1e999 is out of range"
+    );
+    assert_eq!(
+        parse_iloc("loadI 1.5 => r1").unwrap_err(),
+        "Line 1: Invalid number '1.5'"
+    );
+}
+
+#[test]
+fn float_bundles_and_optimizer() {
+    let source = "loadF 1.0 => f1\nloadF 2.0 => f2\n[ f2f f1 => f2 ; f2f f2 => f1 ]";
+    let vm = run(source, FloatWidth::F32);
+
+    assert_eq!(vm.get_float_registers()["f1"], 2.0);
+    assert_eq!(vm.get_float_registers()["f2"], 1.0);
+
+    // Float registers are registers to the analyses: the store keeps its inputs
+    let program = parse_iloc("loadF 1.5 => f1\nloadI 0 => r1\nfstore f1 => r1").unwrap();
+    let optimized = iloc::opt::optimize(&program, &iloc::opt::default_passes()).unwrap();
+    assert_eq!(optimized, program);
+}
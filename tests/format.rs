@@ -0,0 +1,98 @@
+use iloc::format::format_source;
+use iloc::parser::{parse_iloc, parse_program};
+
+const MESSY: &str = "// Sums a table
+   .data
+values:   .word 1,2,   3
+msg: .string  \"a,  b\"
+  .text
+    loadI values=>r1
+  loadI   3   =>r2   # count
+
+
+loop:load r1=>r3 /* next */
+add r4,r3=>r4
+ L2: [ addI r1,4 => r1;subI   r2,1=>r2 ]
+    cbr r2->loop,done
+done:   nop
+";
+
+const CLEAN: &str = "// Sums a table
+        .data
+values: .word 1, 2, 3
+msg:    .string \"a,  b\"
+        .text
+        loadI values => r1
+        loadI 3      => r2  // count
+
+loop:   load  r1     => r3  // next
+        add   r4, r3 => r4
+L2:     [ addI r1, 4 => r1 ; subI r2, 1 => r2 ]
+        cbr   r2     -> loop, done
+done:   nop
+";
+
+#[test]
+fn format_aligns_labels_operands_and_comments() {
+    assert_eq!(format_source(MESSY, "").unwrap(), CLEAN);
+}
+
+#[test]
+fn format_is_idempotent_and_keeps_the_program() {
+    let formatted = format_source(MESSY, "").unwrap();
+
+    assert_eq!(format_source(&formatted, "").unwrap(), formatted);
+    let before = parse_program(MESSY, "").unwrap();
+    let after = parse_program(&formatted, "").unwrap();
+    assert_eq!(after.text(), before.text());
+    assert_eq!(after.data, before.data);
+}
+
+#[test]
+fn format_keeps_macros_constants_and_literals() {
+    let source = ".equ N 0x10\n.macro inc reg\naddI reg,1=>reg\n.endm\nloadI 'A'=>r1\ninc r1\naddI r1,N*2=>r1";
+
+    assert_eq!(
+        format_source(source, "").unwrap(),
+        ".equ N 0x10
+.macro inc reg
+addI  reg, 1  => reg
+.endm
+loadI 'A'     => r1
+inc   r1
+addI  r1, N*2 => r1
+"
+    );
+}
+
+#[test]
+fn format_rejects_programs_that_do_not_parse() {
+    assert_eq!(
+        format_source("nop\n[ ]", "").unwrap_err(),
+        "Line 2: Empty bundle"
+    );
+}
+
+#[test]
+fn program_display_round_trip() {
+    let program = parse_program(MESSY, "").unwrap();
+    let printed = program.to_string();
+    let reparsed = parse_program(&printed, "").unwrap();
+
+    assert_eq!(reparsed.text(), program.text());
+    assert_eq!(reparsed.data, program.data);
+    assert!(printed.contains("\nloop:   load  r1     => r3  // next\n"));
+    assert!(printed.starts_with("        .data\nvalues: .byte 1, 0, 0, 0,"));
+}
+
+#[test]
+fn program_display_keeps_labels_sharing_an_address() {
+    let program = parse_program(".data\na:\nb: .space 2\nend:\n.text\nloadI b => r1", "").unwrap();
+    let reparsed = parse_program(&program.to_string(), "").unwrap();
+
+    assert_eq!(reparsed.data, program.data);
+    assert_eq!(
+        parse_iloc(&program.to_string()).unwrap(),
+        vec!["loadI 0 => r1"]
+    );
+}
@@ -0,0 +1,167 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use iloc::golden::{final_state, run_directory, unified_diff, GoldenConfig, Status};
+
+// A scratch directory per test, so tests can run in parallel
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("iloc-golden-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn golden_checked_in_directory() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let results = run_directory(&dir, &GoldenConfig::default()).unwrap();
+
+    assert_eq!(results.len(), 3);
+    for result in &results {
+        assert!(result.passed(), "{}", result);
+    }
+}
+
+#[test]
+fn golden_final_state_format() {
+    let state = final_state(
+        "loadI 513 => r1\nloadI 32 => r10\nstore r1 => r10\nloadI 2 => r2\noutput 32",
+        "",
+        &GoldenConfig::default(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        state,
+        "termination: finished
+registers:
+    r1 = 513
+    r2 = 2
+    r10 = 32
+memory:
+    0x0020: 01 02 00 00 00 00 00 00 00 00 00 00 00 00 00 00
+output:
+    513
+"
+    );
+}
+
+#[test]
+fn golden_step_limits() {
+    let source = "L1: jumpI -> L1";
+    let config = GoldenConfig {
+        max_steps: 10,
+        ..GoldenConfig::default()
+    };
+    assert!(final_state(source, "", &config)
+        .unwrap()
+        .starts_with("termination: step limit reached"));
+
+    // A directive in the program overrides the configured limit
+    let state = final_state(
+        "//! max-steps 3\nloadI 1 => r1\naddI r1, 1 => r1\naddI r1, 1 => r1\naddI r1, 1 => r1",
+        "",
+        &config,
+    )
+    .unwrap();
+    assert!(state.contains("r1 = 3"));
+}
+
+#[test]
+fn golden_bless_and_mismatch() {
+    let dir = scratch("bless");
+    fs::write(dir.join("a.iloc"), "loadI 1 => r1").unwrap();
+    fs::write(dir.join("b.iloc"), "loadI 2 => r2").unwrap();
+
+    let results = run_directory(&dir, &GoldenConfig::default()).unwrap();
+    assert!(results
+        .iter()
+        .all(|result| result.status == Status::Missing));
+
+    let bless = GoldenConfig {
+        bless: true,
+        jobs: 2,
+        ..GoldenConfig::default()
+    };
+    let results = run_directory(&dir, &bless).unwrap();
+    assert!(results
+        .iter()
+        .all(|result| result.status == Status::Blessed));
+    assert!(dir.join("a.expected").exists());
+
+    fs::write(dir.join("a.iloc"), "loadI 5 => r1").unwrap();
+    let results = run_directory(&dir, &GoldenConfig::default()).unwrap();
+    assert_eq!(results[1].status, Status::Passed);
+    let Status::Failed(diff) = &results[0].status else {
+        panic!("expected a mismatch, got {:?}", results[0].status);
+    };
+    assert!(diff.contains("-    r1 = 1\n+    r1 = 5\n"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn golden_unified_diff() {
+    let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+    let new = "a\nb\nc\nD\ne\nf\ng\nh\ni\nj\nk\n";
+    let diff = unified_diff(old, new, "old", "new");
+
+    assert_eq!(
+        diff,
+        "--- old
++++ new
+@@ -1,7 +1,7 @@
+ a
+ b
+ c
+-d
++D
+ e
+ f
+ g
+@@ -8,3 +8,4 @@
+ h
+ i
+ j
++k
+"
+    );
+    assert_eq!(unified_diff(old, old, "old", "new"), "--- old\n+++ new\n");
+}
+
+#[test]
+fn golden_crash_is_reported_with_the_other_results() {
+    let dir = scratch("crash");
+    fs::write(dir.join("a.iloc"), "add r9, r8 => r1").unwrap();
+    fs::write(dir.join("b.iloc"), "loadI 2 => r2").unwrap();
+
+    let results = run_directory(&dir, &GoldenConfig::default()).unwrap();
+    assert_eq!(
+        results[0].status,
+        Status::Error("Crashed: no entry found for key".to_string())
+    );
+    assert_eq!(results[1].status, Status::Missing);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn golden_includes_resolve_next_to_the_program() {
+    let dir = scratch("include");
+    fs::write(dir.join("lib.inc"), "loadI 7 => r7").unwrap();
+    fs::write(
+        dir.join("inc.iloc"),
+        "#include \"lib.inc\"\naddI r7, 1 => r8",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("inc.expected"),
+        "termination: finished\nregisters:\n    r7 = 7\n    r8 = 8\nmemory:\noutput:\n",
+    )
+    .unwrap();
+
+    let results = run_directory(&dir, &GoldenConfig::default()).unwrap();
+    assert_eq!(results[0].status, Status::Passed);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
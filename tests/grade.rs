@@ -0,0 +1,195 @@
+use iloc::grade::{grade, registers_used, Metric, Rubric};
+use iloc::parser::parse_iloc;
+use iloc::vm::{Limits, Termination, VM};
+
+const RUBRIC: &str = r#"
+# Doubles r1 into r2 and prints it
+memory 256
+max-steps 1000
+
+case five 2
+    set r1 = 5
+    expect r2 == 10
+    expect output "10\n"
+
+case negative 1
+    set r1 = -0x10
+    expect r2 == -32
+
+limit instructions 4 1
+limit registers 3 1
+"#;
+
+const GOOD: &str = "
+    add r1, r1 => r2
+    loadI 64 => r3
+    store r2 => r3
+    output 64
+";
+
+#[test]
+fn grade_parse_rubric() {
+    let rubric = Rubric::parse(RUBRIC).unwrap();
+
+    assert_eq!(rubric.memory_size, 256);
+    assert_eq!(rubric.max_steps, 1000);
+    assert_eq!(rubric.cases.len(), 2);
+    assert_eq!(rubric.cases[1].registers, vec![("r1".to_string(), -16)]);
+    assert_eq!(rubric.cases[0].expectations[0].0, 8);
+    assert_eq!(rubric.limits[1].metric, Metric::Registers);
+    assert_eq!(rubric.total(), 5);
+}
+
+#[test]
+fn grade_rubric_errors() {
+    assert_eq!(
+        Rubric::parse("case a 1\n    bogus 3").unwrap_err(),
+        "Line 2: Unknown keyword 'bogus'"
+    );
+    assert!(Rubric::parse("set r1 = 1").is_err());
+    assert!(Rubric::parse("case a 1\nlimit speed 3 1").is_err());
+    assert!(Rubric::parse("memory 1024").is_err());
+}
+
+#[test]
+fn grade_full_marks() {
+    let rubric = Rubric::parse(RUBRIC).unwrap();
+    let report = grade("alice", GOOD, "", &rubric);
+
+    assert_eq!(report.score, 5);
+    assert_eq!(report.total, 5);
+    assert!(report.cases.iter().all(|case| case.passed));
+    assert_eq!(report.cases[0].instructions, 4);
+    assert_eq!(report.limits[1].actual, 3);
+}
+
+#[test]
+fn grade_correct_but_slow() {
+    let rubric = Rubric::parse(RUBRIC).unwrap();
+    let slow = "
+        multI r1, 2 => r4
+        i2i r4 => r2
+        loadI 64 => r3
+        store r2 => r3
+        output 64
+    ";
+    let report = grade("bob", slow, "", &rubric);
+
+    assert_eq!(report.score, 3);
+    assert!(!report.limits[0].met);
+    assert_eq!(report.limits[0].actual, 5);
+    assert!(!report.limits[1].met);
+}
+
+#[test]
+fn grade_wrong_answers_earn_no_efficiency_points() {
+    let rubric = Rubric::parse(RUBRIC).unwrap();
+    let wrong = GOOD.replace("add r1, r1", "addI r1, 5");
+    let report = grade("carol", &wrong, "", &rubric);
+
+    // Right for 5 but not for -16, and fast, but limits need every case passing
+    assert_eq!(report.score, 2);
+    assert!(!report.cases[1].passed);
+    assert_eq!(
+        report.cases[1].failures,
+        vec!["Line 13: expected r2 == -32, got -11"]
+    );
+    assert!(report.limits.iter().all(|limit| !limit.met));
+}
+
+#[test]
+fn grade_sandbox() {
+    let rubric = Rubric::parse(RUBRIC).unwrap();
+
+    let report = grade("dave", "L1: jumpI -> L1", "", &rubric);
+    assert_eq!(report.score, 0);
+    assert_eq!(report.cases[0].termination, "step limit reached");
+    assert_eq!(report.cases[0].instructions, 1000);
+
+    let watched = Rubric::parse(&format!("detect-loops\ntimeout 5000\n{}", RUBRIC)).unwrap();
+    let report = grade("dave", "L1: jumpI -> L1", "", &watched);
+    assert!(report.cases[0].termination.starts_with("infinite loop"));
+    assert!(report.cases[0].instructions < 10);
+
+    // Reading an undefined register fails the case instead of the grader
+    let report = grade("erin", "add r7, r7 => r2", "", &rubric);
+    assert!(report.cases[0].termination.starts_with("crashed"));
+
+    let report = grade("frank", "// nothing yet", "", &rubric);
+    assert!(report.error.is_some());
+    assert!(report.cases.is_empty());
+}
+
+#[test]
+fn grade_json_report() {
+    let rubric = Rubric::parse("case \"quoted\" 1\n    expect r1 == 1").unwrap();
+    let report = grade("gina", "loadI 1 => r1", "", &rubric);
+
+    assert_eq!(
+        report.to_json(),
+        r#"{
+  "student": "gina",
+  "score": 1,
+  "total": 1,
+  "error": null,
+  "cases": [
+    {"name": "\"quoted\"", "weight": 1, "passed": true, "termination": "finished", "instructions": 1, "cycles": 1, "failures": []}
+  ],
+  "limits": []
+}
+"#
+    );
+    assert_eq!(
+        registers_used(&["[ add r1,r2 => r3 ; i2i r1 => r4 ]".to_string()]),
+        4
+    );
+}
+
+#[test]
+fn grade_catches_panics_of_the_run_only() {
+    let mut vm = VM::new(256);
+    vm.load_program(parse_iloc("add r7, r7 => r2").unwrap());
+    assert_eq!(
+        vm.run_catching(&Limits::default()),
+        Err("no entry found for key".to_string())
+    );
+
+    vm.load_program(parse_iloc("loadI 1 => r1").unwrap());
+    assert_eq!(
+        vm.run_catching(&Limits::default()),
+        Ok(Termination::Finished)
+    );
+    // Panics outside a run still unwind as usual
+    assert!(std::panic::catch_unwind(|| panic!("grader bug")).is_err());
+}
+
+#[test]
+fn grade_includes_resolve_next_to_the_submission() {
+    let dir = std::env::temp_dir().join(format!("iloc-grade-include-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("double.inc"), "add r1, r1 => r2").unwrap();
+    let file = dir.join("hank.iloc").display().to_string();
+
+    let rubric = Rubric::parse("case five 1\n    set r1 = 5\n    expect r2 == 10").unwrap();
+    let report = grade("hank", "#include \"double.inc\"", &file, &rubric);
+    assert_eq!(report.error, None);
+    assert_eq!(report.score, 1);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn grade_huge_data_is_a_student_error() {
+    let rubric = Rubric::parse(RUBRIC).unwrap();
+    for size in ["3000000000", "0x7fffffffffffffff"] {
+        let source = format!(".data\nbuf: .space {}\n.text\nnop", size);
+        let report = grade("mallory", &source, "", &rubric);
+
+        assert_eq!(
+            report.error.as_deref(),
+            Some("Line 2: Data section too large: it must fit in 256 bytes")
+        );
+        assert_eq!(report.score, 0);
+    }
+    assert_eq!(grade("alice", GOOD, "", &rubric).score, 5);
+}
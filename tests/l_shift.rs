@@ -103,7 +103,7 @@ fn l_shift_large_shift_amount() {
     let state = binding.get_state();
     let registers = &state.0;
 
-    let expected_result = 1 << 31;
+    let expected_result = (1i32 << 31) as i64;
     assert_eq!(registers["r3"], expected_result);
 }
 
@@ -98,7 +98,7 @@ fn l_shift_i_large_shift_amount() {
     let state = binding.get_state();
     let registers = &state.0;
 
-    let expected_result = 1 << 31; // May overflow if using 32-bit signed integers
+    let expected_result = (1i32 << 31) as i64; // May overflow if using 32-bit signed integers
     assert_eq!(registers["r3"], expected_result);
 }
 
@@ -0,0 +1,100 @@
+use iloc::latency::{CycleModel, LatencyTable};
+
+fn run(program: &str, cycle_model: CycleModel) -> iloc::latency::CycleStats {
+    let mut vm = iloc::vm::VM::new(1024);
+    vm.load_program(iloc::parser::parse_iloc(program).unwrap());
+    vm.set_cycle_model(cycle_model);
+    vm.run();
+    vm.get_cycle_stats()
+}
+
+#[test]
+fn latency_default_table() {
+    let table = LatencyTable::default();
+
+    assert_eq!(table.get("loadAI"), 3);
+    assert_eq!(table.get("store"), 3);
+    assert_eq!(table.get("mult"), 2);
+    assert_eq!(table.get("add"), 1);
+    assert_eq!(table.get("jumpI"), 1);
+}
+
+#[test]
+fn latency_parse_overrides() {
+    let table = LatencyTable::parse(
+        "
+        # slow memory
+        load = 5
+        mult 4
+        default 2
+        ",
+    )
+    .unwrap();
+
+    assert_eq!(table.get("load"), 5);
+    assert_eq!(table.get("mult"), 4);
+    assert_eq!(table.get("storeAI"), 3);
+    assert_eq!(table.get("add"), 2);
+}
+
+#[test]
+fn latency_parse_invalid() {
+    assert!(LatencyTable::parse("load").is_err());
+    assert!(LatencyTable::parse("load three").is_err());
+    assert!(LatencyTable::parse("load 0").is_err());
+}
+
+#[test]
+fn latency_sequential_cycles() {
+    let program = "
+    loadI 0 => r0
+    loadI 6 => r1
+    mult r1, r1 => r2
+    store r2 => r0
+    ";
+    let stats = run(program, CycleModel::new(LatencyTable::default(), false));
+
+    assert_eq!(stats.cycles, 1 + 1 + 2 + 3);
+    assert_eq!(stats.stalls, 0);
+}
+
+#[test]
+fn latency_pipeline_stalls_on_operands() {
+    let program = "
+    loadI 0 => r0
+    load r0 => r1
+    add r1, r1 => r2
+    ";
+    let stats = run(program, CycleModel::new(LatencyTable::default(), true));
+
+    // load issues at 1 and is ready at 4, so add waits from cycle 2 to 4
+    assert_eq!(stats.stalls, 2);
+    assert_eq!(stats.cycles, 5);
+}
+
+#[test]
+fn latency_pipeline_overlaps_independent_work() {
+    let program = "
+    loadI 0 => r0
+    load r0 => r1
+    loadI 1 => r2
+    loadI 2 => r3
+    add r1, r2 => r4
+    ";
+    let stats = run(program, CycleModel::new(LatencyTable::default(), true));
+
+    assert_eq!(stats.stalls, 0);
+    assert_eq!(stats.cycles, 5);
+}
+
+#[test]
+fn latency_counts_reset_on_load() {
+    let mut vm = iloc::vm::VM::new(1024);
+    vm.set_cycle_model(CycleModel::new(LatencyTable::uniform(), false));
+    vm.load_program(iloc::parser::parse_iloc("loadI 1 => r0\nloadI 2 => r1").unwrap());
+    vm.run();
+    assert_eq!(vm.get_cycle_stats().cycles, 2);
+
+    vm.load_program(iloc::parser::parse_iloc("nop").unwrap());
+    assert_eq!(vm.get_cycle_stats().cycles, 0);
+}
@@ -0,0 +1,114 @@
+use std::time::Duration;
+
+use iloc::vm::{Limits, Termination, VM};
+
+fn vm(program: &str) -> VM {
+    let mut vm = VM::new(1024);
+    vm.load_program(iloc::parser::parse_iloc(program).unwrap());
+    vm
+}
+
+// Counts r1 up forever, so no state ever repeats
+const COUNTER: &str = "loadI 0 => r1\nL1: addI r1, 1 => r1\njumpI -> L1";
+
+#[test]
+fn limits_step_budget() {
+    let mut looping = vm(COUNTER);
+    let limits = Limits {
+        max_steps: Some(100),
+        ..Limits::default()
+    };
+    assert_eq!(looping.run_with_limits(&limits), Termination::StepLimit);
+    assert_eq!(looping.get_instruction_count(), 100);
+
+    // Finishing on exactly the last allowed step is not a limit hit
+    let mut short = vm("loadI 1 => r1\nloadI 2 => r2");
+    let limits = Limits {
+        max_steps: Some(2),
+        ..Limits::default()
+    };
+    assert_eq!(short.run_with_limits(&limits), Termination::Finished);
+}
+
+#[test]
+fn limits_timeout() {
+    let mut looping = vm(COUNTER);
+    let timeout = Duration::from_millis(20);
+    let termination = looping.run_with_limits(&Limits {
+        timeout: Some(timeout),
+        ..Limits::default()
+    });
+
+    assert_eq!(termination, Termination::Timeout(timeout));
+    assert_eq!(termination.to_string(), "timed out after 20ms");
+}
+
+#[test]
+fn limits_detect_repeated_state() {
+    // r1 cycles through 0, 1, 2 and the loop never exits
+    let program = "
+        loadI 0 => r1
+        loadI 3 => r2
+        L1: addI r1, 1 => r1
+        cmp_LT r1, r2 => r3
+        cbr r3 -> L1, L2
+        L2: loadI 0 => r1
+        jumpI -> L1
+    ";
+    let mut looping = vm(program);
+    let termination = looping.run_with_limits(&Limits {
+        max_steps: Some(10_000),
+        detect_repeats: true,
+        ..Limits::default()
+    });
+
+    let Termination::RepeatedState { period, .. } = termination else {
+        panic!("expected a repeated state, got {:?}", termination);
+    };
+    assert_eq!(period, 11);
+    assert!(looping.get_instruction_count() < 100);
+}
+
+#[test]
+fn limits_counting_loop_is_not_a_repeat() {
+    let mut looping = vm(COUNTER);
+    let termination = looping.run_with_limits(&Limits {
+        max_steps: Some(1000),
+        detect_repeats: true,
+        ..Limits::default()
+    });
+    assert_eq!(termination, Termination::StepLimit);
+
+    // A self-loop repeats immediately
+    let mut spin = vm("L1: jumpI -> L1");
+    let termination = spin.run_with_limits(&Limits {
+        detect_repeats: true,
+        ..Limits::default()
+    });
+    assert_eq!(termination, Termination::RepeatedState { pc: 0, period: 1 });
+    assert_eq!(
+        termination.to_string(),
+        "infinite loop: state at instruction 1 repeats every 1 steps"
+    );
+}
+
+#[test]
+fn limits_terminating_loop_finishes() {
+    let program = "
+        loadI 0 => r1
+        loadI 50 => r2
+        L1: addI r1, 1 => r1
+        cmp_LT r1, r2 => r3
+        cbr r3 -> L1, L2
+        L2: nop
+    ";
+    let mut vm = vm(program);
+    let termination = vm.run_with_limits(&Limits {
+        max_steps: Some(1000),
+        timeout: Some(Duration::from_secs(10)),
+        detect_repeats: true,
+    });
+
+    assert_eq!(termination, Termination::Finished);
+    assert_eq!(vm.get_state().0["r1"], 50);
+}
@@ -0,0 +1,125 @@
+use iloc::literal::{evaluate, to_word};
+use iloc::parser::{parse_iloc, parse_program};
+use iloc::vm::VM;
+
+#[test]
+fn literal_bases_and_underscores() {
+    assert_eq!(evaluate("0x10"), Ok(16));
+    assert_eq!(evaluate("0XfF"), Ok(255));
+    assert_eq!(evaluate("0b1010_0101"), Ok(0xa5));
+    assert_eq!(evaluate("0o17"), Ok(15));
+    assert_eq!(evaluate("1_000_000"), Ok(1_000_000));
+    assert_eq!(evaluate("-42"), Ok(-42));
+    assert_eq!(evaluate("007"), Ok(7));
+}
+
+#[test]
+fn literal_characters() {
+    assert_eq!(evaluate("'A'"), Ok(65));
+    assert_eq!(evaluate("'\\n'"), Ok(10));
+    assert_eq!(evaluate("'\\''"), Ok(39));
+    assert_eq!(evaluate("'\\0'"), Ok(0));
+    assert_eq!(evaluate("'a' - 'A'"), Ok(32));
+}
+
+#[test]
+fn literal_expressions() {
+    assert_eq!(evaluate("4*8+2"), Ok(34));
+    assert_eq!(evaluate("4 * (8 + 2)"), Ok(40));
+    assert_eq!(evaluate("1 << 4 | 1"), Ok(17));
+    assert_eq!(evaluate("-7 / 2"), Ok(-3));
+    assert_eq!(evaluate("-7 % 2"), Ok(-1));
+    assert_eq!(evaluate("~0 & 0xff ^ 1"), Ok(254));
+    assert_eq!(evaluate("--1"), Ok(1));
+}
+
+#[test]
+fn literal_errors() {
+    assert_eq!(evaluate("x"), Err("Invalid number 'x'".to_string()));
+    assert_eq!(evaluate("0x"), Err("Invalid number '0x'".to_string()));
+    assert_eq!(evaluate("0b102"), Err("Invalid number '0b102'".to_string()));
+    assert_eq!(evaluate("1__0"), Err("Invalid number '1__0'".to_string()));
+    assert_eq!(evaluate("1 +"), Err("Invalid expression '1 +'".to_string()));
+    assert_eq!(evaluate("(1"), Err("Invalid expression '(1'".to_string()));
+    assert_eq!(
+        evaluate("1 / (2 - 2)"),
+        Err("Division by zero in '1 / (2 - 2)'".to_string())
+    );
+    assert_eq!(
+        evaluate("1 << 64"),
+        Err("Invalid shift by 64 in '1 << 64'".to_string())
+    );
+    assert_eq!(
+        evaluate("0x7fff_ffff_ffff_ffff + 1"),
+        Err("Expression '0x7fff_ffff_ffff_ffff + 1' overflows".to_string())
+    );
+    assert_eq!(
+        evaluate("'AB'"),
+        Err("Unterminated character literal ''AB''".to_string())
+    );
+    assert_eq!(to_word(0xffff_ffff), Ok(-1));
+    assert!(to_word(0x1_0000_0000).is_err());
+}
+
+#[test]
+fn literal_immediates_normalized_at_parse_time() {
+    let source = "
+        loadI 0x10 => r1
+        addI r1, 4*8+2 => r2
+        loadI ' ' => r3   // a space
+        loadI '#' => r4   # not a comment
+        L1: [ subI r1, 0b11 => r5 ; multI r1, -(2) => r6 ]
+        storeAI r1 => r1, 0o10
+        .equ WIDTH 1+2
+        loadI WIDTH*3 => r7
+    ";
+    assert_eq!(
+        parse_iloc(source).unwrap(),
+        vec![
+            "loadI 16 => r1",
+            "addI r1,34 => r2",
+            "loadI 32 => r3",
+            "loadI 35 => r4",
+            "L1: [ subI r1,3 => r5 ; multI r1,-2 => r6 ]",
+            "storeAI r1 => r1,8",
+            "loadI 9 => r7",
+        ]
+    );
+
+    let mut vm = VM::new(1024);
+    vm.load_program(parse_iloc(source).unwrap());
+    vm.run();
+    let (registers, _, _) = vm.get_state();
+    assert_eq!(registers["r2"], 50);
+    assert_eq!(registers["r6"], -32);
+}
+
+#[test]
+fn literal_data_values_and_range_errors() {
+    let program = parse_program(
+        ".data\n.byte 'h', 'i', ',', 0x7f\n.word 0xffff_ffff, 2 * 3\n.space 2*2\n.text\nnop",
+        "",
+    )
+    .unwrap();
+    assert_eq!(&program.data.bytes[..4], b"hi,\x7f");
+    assert_eq!(
+        &program.data.bytes[4..12],
+        &[0xff, 0xff, 0xff, 0xff, 6, 0, 0, 0]
+    );
+    assert_eq!(program.data.bytes.len(), 16);
+
+    let error = |source: &str| parse_iloc(source).unwrap_err();
+    assert_eq!(
+        error("nop\nloadI 0x1_0000_0000 => r1"),
+        "Line 2: Value 4294967296 does not fit in a word"
+    );
+    assert_eq!(
+        error("addI r1, 1/0 => r2"),
+        "Line 1: Division by zero in '1/0'"
+    );
+    assert_eq!(error("loadI 0xg => r1"), "Line 1: Invalid number '0xg'");
+    assert_eq!(
+        error("loadI 'é' => r1"),
+        "Line 1: Character ''é'' is not ASCII"
+    );
+}
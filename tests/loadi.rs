@@ -105,19 +105,12 @@ fn loadi_register_static_positive_overflow_wraparound() {
 }
 
 #[test]
-fn loadi_register_static_negative_overflow_wraparound() {
+fn loadi_register_static_negative_overflow_rejected() {
+    // Below -2^31 a value fits neither a signed nor an unsigned word
     let program = "loadI -2147483649 => r0";
 
-    let vm = Arc::new(Mutex::new(iloc::vm::VM::new(1024)));
-    vm.lock()
-        .unwrap()
-        .load_program(iloc::parser::parse_iloc(program).unwrap());
-
-    vm.lock().unwrap().run();
-
-    let binding = vm.lock().unwrap();
-    let state = binding.get_state();
-    let registers = &state.0;
-
-    assert_eq!(registers["r0"], 2147483647);
+    assert_eq!(
+        iloc::parser::parse_iloc(program).unwrap_err(),
+        "Line 1: Value -2147483649 does not fit in a word"
+    );
 }
@@ -0,0 +1,151 @@
+use iloc::lvn::ValueNumbering;
+use iloc::opt::{optimize, Pass};
+
+const LOCAL: ValueNumbering = ValueNumbering { extended: false };
+const SUPERLOCAL: ValueNumbering = ValueNumbering { extended: true };
+
+fn run_pass(program: &str, pass: ValueNumbering) -> Vec<String> {
+    let passes: Vec<Box<dyn Pass>> = vec![Box::new(pass)];
+    optimize(&iloc::parser::parse_iloc(program).unwrap(), &passes).unwrap()
+}
+
+fn final_registers(program: &[String]) -> Vec<(String, i64)> {
+    let mut vm = iloc::vm::VM::new(1024);
+    vm.load_program(program.to_vec());
+    vm.run();
+    let mut registers: Vec<(String, i64)> = vm
+        .get_state()
+        .0
+        .iter()
+        .map(|(reg, val)| (reg.clone(), *val))
+        .collect();
+    registers.sort();
+    registers
+}
+
+#[test]
+fn lvn_commutative_operands() {
+    let program = "
+    add r1, r2 => r3
+    add r2, r1 => r4
+    mult r1, r2 => r5
+    sub r2, r1 => r6
+    ";
+    let optimized = run_pass(program, LOCAL);
+
+    assert_eq!(optimized[1], "i2i r3 => r4");
+    assert_eq!(optimized[2], "mult r1,r2 => r5");
+    // Subtraction does not commute
+    assert_eq!(optimized[3], "sub r2,r1 => r6");
+}
+
+#[test]
+fn lvn_immediate_forms() {
+    let program = "
+    loadI 4 => r2
+    addI r1, 4 => r3
+    add r2, r1 => r4
+    loadI 4 => r5
+    rsubI r1, 10 => r6
+    loadI 10 => r7
+    sub r7, r1 => r8
+    ";
+    let optimized = run_pass(program, LOCAL);
+
+    assert_eq!(optimized[2], "i2i r3 => r4");
+    // r5 already held 4 in r2, so the second loadI is just a copy
+    assert_eq!(optimized[3], "loadI 4 => r5");
+    assert_eq!(optimized[6], "i2i r6 => r8");
+}
+
+#[test]
+fn lvn_redefinition_invalidates() {
+    let program = "
+    add r1, r2 => r3
+    loadI 7 => r1
+    add r1, r2 => r4
+    add r1, r2 => r3
+    ";
+    let optimized = run_pass(program, LOCAL);
+
+    assert_eq!(optimized[2], "add r1,r2 => r4");
+    assert_eq!(optimized[3], "i2i r4 => r3");
+}
+
+#[test]
+fn lvn_removes_recomputation_into_same_register() {
+    let program = "
+    L1: sub r1, r2 => r3
+    sub r1, r2 => r3
+    L2: sub r1, r2 => r3
+    ";
+    let optimized = run_pass(program, LOCAL);
+
+    // L2 starts a new block, so only the second line is redundant
+    assert_eq!(
+        optimized,
+        vec!["L1: sub r1,r2 => r3", "L2: sub r1,r2 => r3"]
+    );
+}
+
+#[test]
+fn lvn_superlocal_extends_into_single_predecessor_blocks() {
+    let program = "
+    loadI 1 => r1
+    loadI 2 => r2
+    add r1, r2 => r3
+    cbr r3 -> L1, L2
+    L1: add r2, r1 => r4
+    jumpI -> L3
+    L2: add r1, r2 => r5
+    L3: add r1, r2 => r6
+    ";
+
+    let local = run_pass(program, LOCAL);
+    assert_eq!(local[4], "L1: add r2,r1 => r4");
+
+    let superlocal = run_pass(program, SUPERLOCAL);
+    assert_eq!(superlocal[4], "L1: i2i r3 => r4");
+    assert_eq!(superlocal[6], "L2: i2i r3 => r5");
+    // L3 joins two paths, so it starts a fresh table
+    assert_eq!(superlocal[7], "L3: add r1,r2 => r6");
+    assert_eq!(
+        final_registers(&superlocal),
+        final_registers(&iloc::parser::parse_iloc(program).unwrap())
+    );
+}
+
+#[test]
+fn lvn_leaves_memory_operations() {
+    let program = "
+    loadI 0 => r0
+    load r0 => r1
+    store r1 => r0
+    load r0 => r2
+    ";
+    let optimized = run_pass(program, SUPERLOCAL);
+
+    assert_eq!(optimized[3], "load r0 => r2");
+}
+
+#[test]
+fn lvn_tables() {
+    let program = iloc::parser::parse_iloc(
+        "
+    loadI 3 => r1
+    addI r1, 2 => r2
+    add r2, r0 => r3
+    ",
+    )
+    .unwrap();
+    let instructions = iloc::ir::parse_program(&program).unwrap();
+    let tables = LOCAL.tables(&instructions);
+
+    assert_eq!(tables.len(), 1);
+    let rows = &tables[0].rows;
+    assert_eq!(rows[0].expression, "#3");
+    assert_eq!(rows[0].registers, vec!["r1"]);
+    assert_eq!(rows[2].expression, "add v0,v1");
+    assert!(rows.iter().any(|row| row.expression == "r0"));
+    assert!(tables[0].to_string().starts_with("Block 0:"));
+}
@@ -0,0 +1,178 @@
+use iloc::memory::{Access, MemoryMap, Region};
+use iloc::parser::{parse_iloc, parse_program};
+use iloc::vm::{Fault, VM};
+
+fn run(source: &str, memory_map: Option<MemoryMap>) -> (VM, Result<(), Fault>) {
+    let program = parse_program(source, "").unwrap();
+    let mut vm = VM::new(256);
+    vm.load(&program).unwrap();
+    if let Some(memory_map) = memory_map {
+        vm.set_memory_map(memory_map).unwrap();
+    }
+    let result = vm.try_run();
+    (vm, result)
+}
+
+/// 16 bytes of static data, 16 of read-only code, a heap and a 64-byte stack.
+fn standard() -> MemoryMap {
+    MemoryMap::standard(256, 16, 16, 64).unwrap()
+}
+
+#[test]
+fn memory_out_of_bounds_faults_with_address() {
+    let (vm, result) = run("loadI 7 => r1\nloadI 254 => r2\nstore r1 => r2", None);
+    let fault = result.unwrap_err();
+
+    assert_eq!(
+        fault,
+        Fault::MemoryViolation {
+            access: Access::Write,
+            address: 256,
+            region: None,
+        }
+    );
+    assert_eq!(fault.name(), "MemoryViolation");
+    assert_eq!(fault.to_string(), "Write at address 256 outside memory");
+    // The store did not land partially
+    assert_eq!(&vm.get_state().1[254..], &[0, 0]);
+}
+
+#[test]
+fn memory_negative_offsets() {
+    let source = "loadI 16 => r1\nloadI 99 => r2\nstoreAI r2 => r1, -8\nloadAI r1, -8 => r3";
+    let (vm, result) = run(source, None);
+    result.unwrap();
+    assert_eq!(vm.get_state().0["r3"], 99);
+    assert_eq!(vm.get_state().1[8], 99);
+
+    // Below 0, addresses wrap around the 32-bit address space
+    let (_, result) = run("loadI 4 => r1\nloadI -12 => r2\nloadAO r1, r2 => r3", None);
+    assert_eq!(
+        result.unwrap_err(),
+        Fault::MemoryViolation {
+            access: Access::Read,
+            address: 0xffff_fff8,
+            region: None,
+        }
+    );
+}
+
+#[test]
+fn memory_regions_enforce_permissions() {
+    let source = "
+        .data
+        value: .word 5
+        .text
+        loadI value => r1
+        load r1 => r2
+        loadI 200 => r3
+        store r2 => r3
+        loadI 100 => r4
+        store r2 => r4
+        loadI 20 => r5
+        load r5 => r6
+        store r6 => r5
+    ";
+    let (vm, result) = run(source, Some(standard()));
+    let fault = result.unwrap_err();
+
+    assert_eq!(
+        fault,
+        Fault::MemoryViolation {
+            access: Access::Write,
+            address: 20,
+            region: Some("code".to_string()),
+        }
+    );
+    assert_eq!(
+        fault.to_string(),
+        "Write at address 20 not permitted in region 'code'"
+    );
+    // Stack, heap and code reads all went through first
+    let registers = vm.get_state().0;
+    assert_eq!(registers["r2"], 5);
+    assert_eq!(registers["r6"], 0);
+    assert_eq!(vm.get_state().1[200], 5);
+    assert_eq!(vm.get_state().1[100], 5);
+}
+
+#[test]
+fn memory_access_straddling_regions() {
+    // A word at 14 covers the last two static bytes and two code bytes
+    let (_, result) = run(
+        "loadI 1 => r1\nloadI 14 => r2\nstore r1 => r2",
+        Some(standard()),
+    );
+    assert_eq!(
+        result.unwrap_err(),
+        Fault::MemoryViolation {
+            access: Access::Write,
+            address: 16,
+            region: Some("code".to_string()),
+        }
+    );
+    // Reading across the same boundary is allowed
+    let (_, result) = run("loadI 14 => r2\nload r2 => r1", Some(standard()));
+    assert!(result.is_ok());
+}
+
+#[test]
+fn memory_alignment_checks() {
+    let mut memory_map = MemoryMap::flat(256);
+    memory_map.check_alignment = true;
+    let (_, result) = run("loadI 6 => r1\nload r1 => r2", Some(memory_map.clone()));
+    let fault = result.unwrap_err();
+
+    assert_eq!(
+        fault,
+        Fault::Misaligned {
+            address: 6,
+            size: 4
+        }
+    );
+    assert_eq!(fault.name(), "Misaligned");
+    assert_eq!(
+        fault.to_string(),
+        "Access of 4 bytes at address 6 is not aligned"
+    );
+    let (_, result) = run(
+        "loadI 8 => r1\nload r1 => r2\nloadF 1.0 => f1\nfstoreAI f1 => r1, 4",
+        Some(memory_map),
+    );
+    assert!(result.is_ok());
+    // Unchecked by default
+    assert!(run("loadI 6 => r1\nload r1 => r2", None).1.is_ok());
+}
+
+#[test]
+fn memory_map_construction() {
+    let mut memory_map = MemoryMap::empty();
+    memory_map
+        .add(Region::new("rom", 0..64, true, false))
+        .unwrap();
+    assert_eq!(
+        memory_map.add(Region::new("ram", 32..128, true, true)),
+        Err("Region 'ram' overlaps region 'rom'".to_string())
+    );
+    assert_eq!(memory_map.region(10).unwrap().name, "rom");
+    assert!(memory_map.region(64).is_none());
+
+    assert_eq!(
+        MemoryMap::standard(64, 32, 16, 32).unwrap_err(),
+        "Regions of 80 bytes do not fit in 64 bytes of memory"
+    );
+    let memory_map = standard();
+    let names: Vec<&str> = memory_map
+        .regions()
+        .iter()
+        .map(|region| region.name.as_str())
+        .collect();
+    assert_eq!(names, ["static", "code", "heap", "stack"]);
+
+    let mut vm = VM::new(128);
+    vm.load_program(parse_iloc("nop").unwrap());
+    assert_eq!(
+        vm.set_memory_map(MemoryMap::flat(256)),
+        Err("Memory map of 256 bytes does not fit in 128 bytes of memory".to_string())
+    );
+}
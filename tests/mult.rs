@@ -146,7 +146,7 @@ fn mult_large_positive() {
     let registers = &state.0;
 
     let result = 1000000000i64 * 2000000000i64;
-    assert_eq!(registers["r2"], result as i32);
+    assert_eq!(registers["r2"], result as i32 as i64);
 }
 
 #[test]
@@ -99,7 +99,7 @@ fn mult_i_large_positive() {
     let registers = &state.0;
 
     let result = 1000000i64 * 2000000i64;
-    let wrapped_result = result as i32;
+    let wrapped_result = result as i32 as i64;
     assert_eq!(registers["r1"], wrapped_result);
 }
 
@@ -120,7 +120,7 @@ fn mult_i_overflow() {
     let state = binding.get_state();
     let registers = &state.0;
 
-    let result = (2147483647i64 * 2i64) as i32;
+    let result = (2147483647i64 * 2i64) as i32 as i64;
     assert_eq!(registers["r1"], result);
 }
 
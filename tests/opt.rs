@@ -0,0 +1,186 @@
+use iloc::opt::{
+    default_passes, optimize, parse_passes, ConstantFolding, CopyPropagation, DeadCodeElimination,
+    Pass, Peephole, StrengthReduction,
+};
+
+fn run_pass(program: &str, pass: Box<dyn Pass>) -> Vec<String> {
+    optimize(&iloc::parser::parse_iloc(program).unwrap(), &[pass]).unwrap()
+}
+
+fn final_state(program: &[String]) -> (Vec<(String, i64)>, Vec<u8>) {
+    let mut vm = iloc::vm::VM::new(1024);
+    vm.load_program(program.to_vec());
+    vm.run();
+    let (registers, memory, _) = vm.get_state();
+    let mut registers: Vec<(String, i64)> = registers
+        .iter()
+        .map(|(reg, val)| (reg.clone(), *val))
+        .collect();
+    registers.sort();
+    (registers, memory.to_vec())
+}
+
+#[test]
+fn opt_constant_folding() {
+    let program = "
+    loadI 10 => r0
+    loadI 5 => r1
+    sub r0, r1 => r2
+    multI r2, 3 => r3
+    add r3, r4 => r5
+    ";
+    let optimized = run_pass(program, Box::new(ConstantFolding));
+
+    assert_eq!(optimized[2], "loadI 5 => r2");
+    assert_eq!(optimized[3], "loadI 15 => r3");
+    assert_eq!(optimized[4], "addI r4,15 => r5");
+}
+
+#[test]
+fn opt_constant_folding_keeps_division_by_zero() {
+    let program = "
+    loadI 10 => r0
+    loadI 0 => r1
+    div r0, r1 => r2
+    ";
+    let optimized = run_pass(program, Box::new(ConstantFolding));
+
+    assert_eq!(optimized[2], "div r0,r1 => r2");
+}
+
+#[test]
+fn opt_constant_folding_is_local_to_blocks() {
+    let program = "
+    loadI 1 => r1
+    L1: addI r1, 1 => r1
+    cmp_LT r1, r2 => r3
+    cbr r3 -> L1, L2
+    L2: nop
+    ";
+    let optimized = run_pass(program, Box::new(ConstantFolding));
+
+    assert_eq!(optimized[1], "L1: addI r1,1 => r1");
+}
+
+#[test]
+fn opt_copy_propagation() {
+    let program = "
+    loadI 4 => r1
+    i2i r1 => r2
+    add r2, r2 => r3
+    loadI 9 => r1
+    add r2, r1 => r4
+    ";
+    let optimized = run_pass(program, Box::new(CopyPropagation));
+
+    assert_eq!(optimized[2], "add r1,r1 => r3");
+    // r1 was redefined, so r2 is no longer a copy of it
+    assert_eq!(optimized[4], "add r2,r1 => r4");
+}
+
+#[test]
+fn opt_dead_code_elimination() {
+    let program = "
+    loadI 4 => r1
+    loadI 8 => r2
+    loadI 0 => r0
+    store r1 => r0
+    loadI 2 => r2
+    ";
+
+    // The first write to r2 is overwritten before being read
+    let optimized = run_pass(program, Box::new(DeadCodeElimination::default()));
+    assert_eq!(optimized.len(), 4);
+    assert!(!optimized.contains(&"loadI 8 => r2".to_string()));
+
+    // Only memory is the result, so every register write after the store dies
+    let optimized = run_pass(program, Box::new(DeadCodeElimination::keeping(&[])));
+    assert_eq!(
+        optimized,
+        vec!["loadI 4 => r1", "loadI 0 => r0", "store r1 => r0"]
+    );
+}
+
+#[test]
+fn opt_strength_reduction() {
+    let program = "
+    multI r1, 8 => r2
+    multI r1, 6 => r3
+    ";
+    let optimized = run_pass(program, Box::new(StrengthReduction));
+
+    assert_eq!(optimized[0], "lshiftI r1,3 => r2");
+    assert_eq!(optimized[1], "multI r1,6 => r3");
+}
+
+#[test]
+fn opt_peephole() {
+    let program = "
+    addI r1, 0 => r2
+    multI r1, 0 => r3
+    sub r1, r1 => r4
+    i2i r5 => r5
+    nop
+    jumpI -> L1
+    L1: or r1, r1 => r6
+    ";
+    let optimized = run_pass(program, Box::new(Peephole));
+
+    assert_eq!(
+        optimized,
+        vec![
+            "i2i r1 => r2",
+            "loadI 0 => r3",
+            "loadI 0 => r4",
+            "L1: i2i r1 => r6",
+        ]
+    );
+}
+
+#[test]
+fn opt_default_passes_preserve_semantics() {
+    let program = iloc::parser::parse_iloc(
+        "
+        loadI 0 => r0
+        loadI 3 => r1
+        loadI 0 => r10
+        L1: i2i r1 => r2
+        multI r2, 4 => r3
+        addI r3, 0 => r4
+        store r4 => r0
+        addI r0, 4 => r0
+        subI r1, 1 => r1
+        cmp_GT r1, r10 => r5
+        cbr r5 -> L1, L2
+        L2: loadI 2 => r6
+        loadI 5 => r7
+        mult r6, r7 => r8
+        store r8 => r0
+        ",
+    )
+    .unwrap();
+    let optimized = optimize(&program, &default_passes()).unwrap();
+    assert_eq!(final_state(&optimized), final_state(&program));
+
+    // With only memory as the result the copies and constants die as well
+    let passes: Vec<Box<dyn Pass>> = vec![
+        Box::new(ConstantFolding),
+        Box::new(CopyPropagation),
+        Box::new(Peephole),
+        Box::new(StrengthReduction),
+        Box::new(DeadCodeElimination::keeping(&[])),
+    ];
+    let optimized = optimize(&program, &passes).unwrap();
+    assert!(optimized.len() < program.len());
+    assert!(optimized.contains(&"L1: lshiftI r1,2 => r3".to_string()));
+    assert_eq!(final_state(&optimized).1, final_state(&program).1);
+}
+
+#[test]
+fn opt_parse_passes() {
+    let passes = parse_passes("fold, copy,dce").unwrap();
+    let names: Vec<&str> = passes.iter().map(|pass| pass.name()).collect();
+
+    assert_eq!(names, vec!["fold", "copy", "dce"]);
+    assert!(parse_passes("fold,unroll").is_err());
+}
@@ -0,0 +1,187 @@
+use iloc::dialect::Dialect;
+use iloc::machine::{MachineConfig, OverflowPolicy, ShiftPolicy, WordSize};
+use iloc::parser::{parse_iloc, parse_program_for, parse_program_in};
+use iloc::vm::{Fault, VM};
+
+fn run(
+    source: &str,
+    overflow: OverflowPolicy,
+    shift: ShiftPolicy,
+) -> Result<std::collections::HashMap<String, i64>, Fault> {
+    let mut vm = VM::new(1024);
+    vm.load_program(parse_iloc(source).unwrap());
+    vm.set_overflow_policy(overflow);
+    vm.set_shift_policy(shift);
+    vm.try_run()?;
+    Ok(vm.get_state().0.clone())
+}
+
+const OVERFLOWS: &str = "
+    loadI 2147483647 => r1
+    loadI -2147483648 => r2
+    loadI -1 => r3
+    addI r1, 1 => r4
+    sub r2, r1 => r5
+    multI r2, 2 => r6
+    div r2, r3 => r7
+    rsubI r2, 0 => r8
+";
+
+#[test]
+fn overflow_policies() {
+    let wrapped = run(OVERFLOWS, OverflowPolicy::Wrap, ShiftPolicy::Mask).unwrap();
+    assert_eq!(
+        [
+            wrapped["r4"],
+            wrapped["r5"],
+            wrapped["r6"],
+            wrapped["r7"],
+            wrapped["r8"]
+        ],
+        [i32::MIN as i64, 1, 0, i32::MIN as i64, i32::MIN as i64]
+    );
+
+    let saturated = run(OVERFLOWS, OverflowPolicy::Saturate, ShiftPolicy::Mask).unwrap();
+    let (min, max) = (i32::MIN as i64, i32::MAX as i64);
+    assert_eq!(
+        [
+            saturated["r4"],
+            saturated["r5"],
+            saturated["r6"],
+            saturated["r7"],
+            saturated["r8"]
+        ],
+        [max, min, min, max, max]
+    );
+
+    assert_eq!(
+        run(OVERFLOWS, OverflowPolicy::Trap, ShiftPolicy::Mask).unwrap_err(),
+        Fault::Overflow("addI".to_string())
+    );
+}
+
+#[test]
+fn overflow_trap_reports_fault() {
+    let source = "loadI 65536 => r1\nmult r1, r1 => r2\nloadI 1 => r3";
+    let fault = run(source, OverflowPolicy::Trap, ShiftPolicy::Mask).unwrap_err();
+
+    assert_eq!(fault.name(), "Overflow");
+    assert_eq!(fault.to_string(), "Integer overflow in mult");
+    // Results that fit never trap
+    let fits = run(
+        "loadI 46340 => r1\nmult r1, r1 => r2",
+        OverflowPolicy::Trap,
+        ShiftPolicy::Mask,
+    )
+    .unwrap();
+    assert_eq!(fits["r2"], 46340 * 46340);
+}
+
+#[test]
+fn overflow_policy_follows_word_size() {
+    let machine = MachineConfig {
+        word_size: WordSize::W64,
+        ..MachineConfig::default()
+    };
+    let source = "loadI 0x7fffffffffffffff => r1\naddI r1, 1 => r2\nloadI 2147483647 => r3\naddI r3, 1 => r4";
+    let program = parse_program_for(source, "", Dialect::default(), machine).unwrap();
+
+    let mut vm = VM::new(1024);
+    vm.set_machine_config(machine);
+    vm.set_overflow_policy(OverflowPolicy::Saturate);
+    vm.load(&program).unwrap();
+    vm.run();
+    let registers = vm.get_state().0;
+    assert_eq!(registers["r2"], i64::MAX);
+    assert_eq!(registers["r4"], 1 << 31);
+}
+
+#[test]
+fn shift_policies() {
+    let source = "
+        loadI -8 => r1
+        loadI 33 => r2
+        lshift r1, r2 => r3
+        rshift r1, r2 => r4
+        rshiftL r1, r2 => r5
+        loadI -1 => r6
+        lshiftI r1, 1 => r7
+    ";
+
+    let masked = run(source, OverflowPolicy::Wrap, ShiftPolicy::Mask).unwrap();
+    assert_eq!([masked["r3"], masked["r4"]], [-16, -4]);
+    assert_eq!(masked["r5"], 0x7fff_fffc);
+
+    let filled = run(source, OverflowPolicy::Wrap, ShiftPolicy::ZeroFill).unwrap();
+    assert_eq!([filled["r3"], filled["r4"], filled["r5"]], [0, -1, 0]);
+    assert_eq!(filled["r7"], -16);
+
+    let fault = run(source, OverflowPolicy::Wrap, ShiftPolicy::Trap).unwrap_err();
+    assert_eq!(
+        fault,
+        Fault::ShiftOutOfRange {
+            amount: 33,
+            bits: 32
+        }
+    );
+    assert_eq!(fault.name(), "ShiftOutOfRange");
+    assert_eq!(
+        fault.to_string(),
+        "Shift by 33 is out of range for 32-bit words"
+    );
+    assert_eq!(
+        run(
+            "loadI 1 => r1\nloadI -1 => r2\nlshift r1, r2 => r3",
+            OverflowPolicy::Wrap,
+            ShiftPolicy::Trap
+        )
+        .unwrap_err(),
+        Fault::ShiftOutOfRange {
+            amount: -1,
+            bits: 32
+        }
+    );
+}
+
+#[test]
+fn logical_and_arithmetic_right_shifts() {
+    let source = "
+        loadI 0x80000000 => r1
+        rshiftI r1, 4 => r2
+        rshiftLI r1, 4 => r3
+        loadI 64 => r4
+        rshiftLI r4, 3 => r5
+        rshiftLI r1, 0 => r6
+    ";
+    let registers = run(source, OverflowPolicy::Trap, ShiftPolicy::Trap).unwrap();
+
+    assert_eq!(registers["r2"], -0x0800_0000);
+    assert_eq!(registers["r3"], 0x0800_0000);
+    assert_eq!(registers["r5"], 8);
+    assert_eq!(registers["r6"], i32::MIN as i64);
+}
+
+#[test]
+fn logical_shift_opcodes_in_tools() {
+    assert_eq!(
+        parse_program_in("loadI 1 => r1\nrshiftL r1, r1 => r2", "", Dialect::Eac2).unwrap_err(),
+        "Line 2: Unknown opcode 'rshiftL' in EaC 2nd edition ILOC"
+    );
+    assert!(parse_program_in("RSHIFTLI r1, 2 => r2", "", Dialect::Lenient).is_ok());
+
+    let program = parse_iloc("loadI -16 => r1\nloadI 2 => r2\nrshiftL r1, r2 => r3").unwrap();
+    let optimized = iloc::opt::optimize(&program, &iloc::opt::default_passes()).unwrap();
+    assert!(
+        optimized.contains(&"loadI 1073741820 => r3".to_string()),
+        "{:?}",
+        optimized
+    );
+    assert_eq!(
+        "zero-fill".parse::<ShiftPolicy>(),
+        Ok(ShiftPolicy::ZeroFill)
+    );
+    assert_eq!(
+        "clamp".parse::<OverflowPolicy>(),
+        Err("Unknown overflow policy 'clamp', expected wrap, trap or saturate".to_string())
+    );
+}
@@ -0,0 +1,125 @@
+use iloc::parser::{parse_iloc, parse_source, SourceLine};
+
+const PROGRAM: &str = "// Adds two numbers
+loadI 1 => r1   // first
+    loadI   2 =>   r2 # second
+
+/* the sum
+   goes to r3 */ add r1, r2 => r3
+L1: [ nop ; i2i r3 => r4 ] /* copy */ // done
+";
+
+#[test]
+fn parser_source_positions() {
+    let lines = parse_source(PROGRAM, "sum.iloc").unwrap();
+
+    assert_eq!(
+        lines[1],
+        SourceLine {
+            text: "loadI 2 => r2".to_string(),
+            file: "sum.iloc".to_string(),
+            line: 3,
+            columns: 5..22,
+            comment: Some("second".to_string()),
+        }
+    );
+    let lines: Vec<(usize, usize)> = lines.iter().map(|l| (l.line, l.columns.start)).collect();
+    assert_eq!(lines, vec![(2, 1), (3, 5), (6, 18), (7, 1)]);
+}
+
+#[test]
+fn parser_comments() {
+    let lines = parse_source(PROGRAM, "sum.iloc").unwrap();
+
+    assert_eq!(lines[0].comment.as_deref(), Some("first"));
+    // The end of a block comment belongs to the line the instruction is on
+    assert_eq!(lines[2].comment.as_deref(), Some("goes to r3"));
+    assert_eq!(lines[3].comment.as_deref(), Some("copy done"));
+    assert_eq!(lines[3].columns, 1..27);
+}
+
+#[test]
+fn parser_same_text_as_parse_iloc() {
+    let texts: Vec<String> = parse_source(PROGRAM, "sum.iloc")
+        .unwrap()
+        .into_iter()
+        .map(|line| line.text)
+        .collect();
+
+    assert_eq!(texts, parse_iloc(PROGRAM).unwrap());
+    assert_eq!(
+        texts,
+        vec![
+            "loadI 1 => r1",
+            "loadI 2 => r2",
+            "add r1,r2 => r3",
+            "L1: [ nop ; i2i r3 => r4 ]",
+        ]
+    );
+}
+
+#[test]
+fn parser_comment_markers_inside_comments() {
+    // A block comment opener inside a line comment does not open a block
+    let program = "nop // see /* below\nloadI 3 => r1\n/* a // b */ loadI 4 => r2";
+    let lines = parse_source(program, "").unwrap();
+
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[1].text, "loadI 3 => r1");
+    assert_eq!(lines[2].comment.as_deref(), Some("a // b"));
+    assert_eq!(lines[2].columns, 14..27);
+}
+
+#[test]
+fn parser_errors_name_source_lines() {
+    assert_eq!(
+        parse_source("nop\n\n[ ]", "x.iloc").unwrap_err(),
+        "Line 3: Empty bundle"
+    );
+    assert_eq!(
+        parse_source("// only comments\n/* here */", "x.iloc").unwrap_err(),
+        "Program is empty"
+    );
+}
+
+#[test]
+fn parser_rejects_unknown_branch_labels() {
+    assert_eq!(
+        parse_iloc("loadI 1 => r1\ncbr r1 -> L1, L2\nL1: nop").unwrap_err(),
+        "Line 2: Unknown label 'L2'"
+    );
+    assert_eq!(
+        parse_iloc("L1: [ nop ; jumpI -> L3 ]").unwrap_err(),
+        "Line 1: Unknown label 'L3'"
+    );
+    assert!(parse_iloc("jumpI -> L1\nL1: nop").is_ok());
+}
+
+#[test]
+fn parser_rejects_non_immediates() {
+    assert_eq!(
+        parse_iloc("loadI 1 => r1\naddI r1, foo => r2").unwrap_err(),
+        "Line 2: Expected an immediate operand for addI, got 'foo'"
+    );
+    assert_eq!(
+        parse_iloc("loadI r1 => r2").unwrap_err(),
+        "Line 1: Expected an immediate operand for loadI, got 'r1'"
+    );
+    assert_eq!(
+        parse_iloc("loadI foo => r1").unwrap_err(),
+        "Line 1: Unknown data label 'foo'"
+    );
+    assert_eq!(
+        parse_iloc("loadI 1 => r1\nstoreAI r1 => r0, r1").unwrap_err(),
+        "Line 2: Expected an immediate operand for storeAI, got 'r1'"
+    );
+    assert!(parse_iloc(".equ N 4\nloadI N => r1\nmultI r1, N => r2").is_ok());
+}
+
+#[test]
+fn parser_writes_jumps_without_an_empty_field() {
+    assert_eq!(
+        parse_iloc("jumpI -> L1\nL1: nop").unwrap(),
+        ["jumpI -> L1", "L1: nop"]
+    );
+}
@@ -0,0 +1,177 @@
+use std::path::PathBuf;
+
+use iloc::parser::parse_program;
+use iloc::vm::VM;
+
+/// A fresh directory holding `files`, removed again when dropped.
+struct Dir(PathBuf);
+
+impl Dir {
+    fn new(name: &str, files: &[(&str, &str)]) -> Self {
+        let dir = std::env::temp_dir().join(format!("iloc-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for (file, source) in files {
+            std::fs::write(dir.join(file), source).unwrap();
+        }
+        Dir(dir)
+    }
+
+    fn path(&self, file: &str) -> String {
+        self.0.join(file).display().to_string()
+    }
+
+    fn parse(&self, file: &str) -> Result<iloc::parser::Program, String> {
+        let path = self.path(file);
+        parse_program(&std::fs::read_to_string(&path).unwrap(), &path)
+    }
+}
+
+impl Drop for Dir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn run(program: &iloc::parser::Program) -> VM {
+    let mut vm = VM::new(1024);
+    vm.load(program).unwrap();
+    vm.run();
+    vm
+}
+
+#[test]
+fn preprocess_include_keeps_file_and_line() {
+    let dir = Dir::new(
+        "include",
+        &[
+            (
+                "main.iloc",
+                "loadI 2 => r1\n#include \"lib.iloc\"\n.include \"lib.iloc\"\nnop",
+            ),
+            ("lib.iloc", "// doubles r1\nadd r1, r1 => r1"),
+        ],
+    );
+    let program = dir.parse("main.iloc").unwrap();
+    let positions: Vec<(String, usize)> = program
+        .code
+        .iter()
+        .map(|line| (line.file.clone(), line.line))
+        .collect();
+
+    assert_eq!(
+        positions,
+        vec![
+            (dir.path("main.iloc"), 1),
+            (dir.path("lib.iloc"), 2),
+            (dir.path("lib.iloc"), 2),
+            (dir.path("main.iloc"), 4),
+        ]
+    );
+    assert_eq!(run(&program).get_state().0["r1"], 8);
+}
+
+#[test]
+fn preprocess_include_errors() {
+    let dir = Dir::new(
+        "include-errors",
+        &[
+            ("a.iloc", "nop\n#include \"b.iloc\""),
+            ("b.iloc", ".include \"a.iloc\""),
+            ("missing.iloc", "nop\n.include \"nowhere.iloc\""),
+            ("broken.iloc", "nop\n.include \"bad.iloc\""),
+            ("bad.iloc", "nop\n\n[ ]"),
+        ],
+    );
+
+    let error = dir.parse("a.iloc").unwrap_err();
+    assert!(error.starts_with(&format!("{}: Line 1: Include cycle: ", dir.path("b.iloc"))));
+    let cycle: Vec<&str> = error.rsplit(": ").next().unwrap().split(" -> ").collect();
+    assert_eq!(cycle.len(), 3);
+    assert!(cycle[0].ends_with("a.iloc") && cycle[2].ends_with("a.iloc"));
+
+    let error = dir.parse("missing.iloc").unwrap_err();
+    assert!(error.starts_with("Line 2: Cannot include"), "{}", error);
+
+    assert_eq!(
+        dir.parse("broken.iloc").unwrap_err(),
+        format!("{}: Line 3: Empty bundle", dir.path("bad.iloc"))
+    );
+}
+
+#[test]
+fn preprocess_macros() {
+    let source = "
+        .macro countdown reg, steps
+        loadI steps => reg
+        loop\\@: subI reg, 1 => reg
+        cbr reg -> loop\\@, done\\@
+        done\\@: nop
+        .endm
+
+        countdown r1, 3
+        start: countdown r2, 5
+    ";
+    let program = parse_program(source, "").unwrap();
+
+    assert_eq!(program.text()[0], "loadI 3 => r1");
+    assert_eq!(program.text()[2], "cbr r1 -> loop1,done1");
+    // A label before a call stays on a line of its own
+    assert_eq!(program.text()[4], "start:");
+    assert_eq!(program.text()[7], "cbr r2 -> loop2,done2");
+    // Expanded lines point at the call
+    assert_eq!(program.code[2].line, 9);
+    assert_eq!(program.code[4].line, 10);
+
+    let vm = run(&program);
+    let (registers, _, _) = vm.get_state();
+    assert_eq!(registers["r1"], 0);
+    assert_eq!(registers["r2"], 0);
+}
+
+#[test]
+fn preprocess_equ_constants() {
+    let source = "
+        .equ SIZE 8
+        .equ LAST SIZE
+        .data
+        buffer: .space SIZE
+        name: .string \"SIZE\"
+        .text
+        loadI LAST => r1
+        addI r1, SIZE => r1
+    ";
+    let program = parse_program(source, "").unwrap();
+
+    assert_eq!(program.data.labels["name"], 8);
+    assert_eq!(&program.data.bytes[8..12], b"SIZE");
+    assert_eq!(program.text()[0], "loadI 8 => r1");
+    assert_eq!(run(&program).get_state().0["r1"], 16);
+}
+
+#[test]
+fn preprocess_errors() {
+    let error = |source: &str| parse_program(source, "").unwrap_err();
+
+    assert_eq!(
+        error(".macro two a, b\nadd a, b => a\n.endm\ntwo r1"),
+        "Line 4: Macro 'two' takes 2 arguments but 1 were given"
+    );
+    assert_eq!(
+        error("nop\n.macro open\nnop"),
+        "Line 2: .macro open has no .endm"
+    );
+    assert_eq!(error("nop\n.endm"), "Line 2: .endm without .macro");
+    assert_eq!(
+        error(".macro a\n.macro b\n.endm"),
+        "Line 2: Macros cannot be defined inside a macro"
+    );
+    assert_eq!(
+        error(".equ ONLY\nnop"),
+        "Line 1: Expected '.equ NAME value'"
+    );
+    assert_eq!(
+        error(".macro loop\nloop\n.endm\nloop"),
+        "Line 4: Macro 'loop' expands too deeply"
+    );
+}
@@ -0,0 +1,93 @@
+use iloc::latency::{CycleModel, LatencyTable};
+use iloc::profile::Profile;
+use iloc::vm::VM;
+
+// Ten trips around a loop whose multiply costs twice as much as the rest
+const LOOP: &str = "
+    loadI 0 => r1
+    loadI 10 => r2
+    L1: addI r1, 1 => r1
+    multI r1, 2 => r3
+    cmp_LT r1, r2 => r4
+    cbr r4 -> L1, L2
+    L2: nop
+";
+
+fn profile(program: &str, latencies: LatencyTable, pipelined: bool) -> Profile {
+    let mut vm = VM::new(1024);
+    vm.load_program(iloc::parser::parse_iloc(program).unwrap());
+    vm.set_cycle_model(CycleModel::new(latencies, pipelined));
+    vm.run();
+    Profile::from_vm(&vm)
+}
+
+#[test]
+fn profile_line_counts() {
+    let mut vm = VM::new(1024);
+    vm.load_program(iloc::parser::parse_iloc(LOOP).unwrap());
+    vm.run();
+
+    assert_eq!(vm.get_line_counts(), &[1, 1, 10, 10, 10, 10, 1]);
+    assert_eq!(vm.get_line_cycles()[3], 20);
+}
+
+#[test]
+fn profile_opcodes_and_hotspots() {
+    let profile = profile(LOOP, LatencyTable::default(), false);
+
+    assert_eq!(profile.opcodes[0], ("addI".to_string(), 10));
+    assert_eq!(profile.opcodes.last().unwrap(), &("nop".to_string(), 1));
+
+    let hot = profile.hotspots();
+    assert_eq!(hot.len(), 7);
+    assert_eq!(hot[0].text, "multI r1,2 => r3");
+    assert_eq!(hot[0].cycles, 20);
+    assert_eq!(profile.heat(3), 1.0);
+    assert_eq!(profile.heat(2), 0.5);
+}
+
+#[test]
+fn profile_blocks() {
+    let profile = profile(LOOP, LatencyTable::default(), false);
+
+    let hottest = &profile.blocks[0];
+    assert_eq!((hottest.start, hottest.end), (2, 6));
+    assert_eq!(hottest.label.as_deref(), Some("L1"));
+    assert_eq!(hottest.entries, 10);
+    assert_eq!(hottest.cycles, 50);
+    assert_eq!(profile.total_cycles, 53);
+}
+
+#[test]
+fn profile_under_pipeline_counts_stalls() {
+    let mut latencies = LatencyTable::uniform();
+    latencies.set("multI", 4);
+    let program = "loadI 3 => r1\nmultI r1, 2 => r2\naddI r2, 1 => r3";
+    let profile = profile(program, latencies, true);
+
+    // The add waits three cycles for the multiply, then issues
+    let cycles: Vec<u64> = profile.lines.iter().map(|line| line.cycles).collect();
+    assert_eq!(cycles, vec![1, 1, 4]);
+}
+
+#[test]
+fn profile_bundles_and_report() {
+    let program = "
+        loadI 0 => r1
+        L1: [ addI r1, 1 => r1 ; loadI 3 => r2 ]
+        cmp_LT r1, r2 => r3
+        [ nop ; cbr r3 -> L1, L2 ]
+        L2: nop
+    ";
+    let profile = profile(program, LatencyTable::uniform(), false);
+
+    assert_eq!(profile.opcodes[0], ("loadI".to_string(), 4));
+    assert_eq!(profile.opcodes[1], ("nop".to_string(), 4));
+    assert_eq!(profile.blocks[0].entries, 3);
+    assert_eq!((profile.blocks[0].start, profile.blocks[0].end), (1, 4));
+
+    let report = profile.to_string();
+    assert!(report.starts_with("Hotspots:\n"));
+    assert!(report.contains("\nOpcodes:\n"));
+    assert!(report.contains("       9  81.8%        3  2-4       L1\n"));
+}
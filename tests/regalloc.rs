@@ -0,0 +1,239 @@
+use iloc::dialect::Dialect;
+use iloc::machine::{MachineConfig, WordSize};
+use iloc::parser::parse_program_for;
+use iloc::regalloc::{allocate, AllocatorConfig, Strategy};
+
+const STRATEGIES: [Strategy; 3] = [
+    Strategy::TopDown,
+    Strategy::BottomUp,
+    Strategy::GraphColoring,
+];
+
+const STRAIGHT_LINE: &str = "
+    loadI 0 => r100
+    loadI 3 => r1
+    loadI 5 => r2
+    loadI 7 => r3
+    loadI 11 => r4
+    add r1, r2 => r5
+    mult r3, r4 => r6
+    sub r6, r5 => r7
+    add r7, r1 => r8
+    mult r8, r2 => r9
+    store r9 => r100
+    addI r100, 4 => r101
+    add r3, r4 => r10
+    store r10 => r101
+    addI r101, 4 => r102
+    mult r5, r6 => r11
+    store r11 => r102
+    ";
+
+const LOOP: &str = "
+    loadI 0 => r1
+    loadI 1 => r2
+    loadI 10 => r3
+    loadI 0 => r4
+    loadI 1 => r5
+    L1: add r1, r2 => r1
+    mult r5, r2 => r5
+    addI r2, 1 => r2
+    cmp_LE r2, r3 => r6
+    cbr r6 -> L1, L2
+    L2: store r1 => r4
+    addI r4, 4 => r7
+    store r5 => r7
+    ";
+
+fn run(program: Vec<String>) -> Vec<u8> {
+    let mut vm = iloc::vm::VM::new(1024);
+    vm.load_program(program);
+    vm.run();
+    // Only the data area; spill slots live at the top half
+    vm.get_state().1[..512].to_vec()
+}
+
+fn physical_registers(program: &[String]) -> Vec<String> {
+    let mut registers: Vec<String> = iloc::ir::parse_program(program)
+        .unwrap()
+        .iter()
+        .flat_map(|inst| {
+            inst.uses()
+                .into_iter()
+                .chain(inst.defs())
+                .map(str::to_string)
+                .collect::<Vec<String>>()
+        })
+        .collect();
+    registers.sort();
+    registers.dedup();
+    registers
+}
+
+#[test]
+fn regalloc_preserves_straight_line_results() {
+    let program = iloc::parser::parse_iloc(STRAIGHT_LINE).unwrap();
+    let expected = run(program.clone());
+
+    for strategy in STRATEGIES {
+        for k in 3..8 {
+            let allocation = allocate(&program, &AllocatorConfig::new(k, strategy)).unwrap();
+            assert_eq!(
+                run(allocation.program),
+                expected,
+                "{:?} with k = {}",
+                strategy,
+                k
+            );
+        }
+    }
+}
+
+#[test]
+fn regalloc_preserves_loop_results() {
+    let program = iloc::parser::parse_iloc(LOOP).unwrap();
+    let expected = run(program.clone());
+    assert_eq!(expected[0], 55);
+
+    for strategy in STRATEGIES {
+        for k in 3..8 {
+            let allocation = allocate(&program, &AllocatorConfig::new(k, strategy)).unwrap();
+            assert_eq!(
+                run(allocation.program),
+                expected,
+                "{:?} with k = {}",
+                strategy,
+                k
+            );
+        }
+    }
+}
+
+#[test]
+fn regalloc_uses_only_k_registers() {
+    let program = iloc::parser::parse_iloc(STRAIGHT_LINE).unwrap();
+
+    for strategy in STRATEGIES {
+        let allocation = allocate(&program, &AllocatorConfig::new(3, strategy)).unwrap();
+        for reg in physical_registers(&allocation.program) {
+            assert!(
+                ["r0", "r1", "r2", "rarp"].contains(&reg.as_str()),
+                "{:?} emitted {}",
+                strategy,
+                reg
+            );
+        }
+    }
+}
+
+#[test]
+fn regalloc_no_spills_with_enough_registers() {
+    let program = iloc::parser::parse_iloc(STRAIGHT_LINE).unwrap();
+
+    let allocation =
+        allocate(&program, &AllocatorConfig::new(16, Strategy::GraphColoring)).unwrap();
+    assert_eq!(allocation.spilled, 0);
+    assert_eq!(allocation.spill_loads, 0);
+    assert_eq!(allocation.spill_stores, 0);
+    assert_eq!(allocation.program.len(), program.len());
+}
+
+#[test]
+fn regalloc_reports_spills() {
+    let program = iloc::parser::parse_iloc(STRAIGHT_LINE).unwrap();
+
+    for strategy in STRATEGIES {
+        let allocation = allocate(&program, &AllocatorConfig::new(3, strategy)).unwrap();
+        assert!(allocation.spilled > 0, "{:?}", strategy);
+        assert!(allocation.spill_stores > 0, "{:?}", strategy);
+        assert!(allocation.spill_loads > 0, "{:?}", strategy);
+        assert_eq!(allocation.program[0], "loadI 512 => rarp");
+    }
+}
+
+#[test]
+fn regalloc_keeps_existing_rarp() {
+    let program = iloc::parser::parse_iloc(
+        "
+        loadI 600 => rarp
+        loadI 1 => r1
+        loadI 2 => r2
+        loadI 3 => r3
+        loadI 4 => r4
+        add r1, r2 => r5
+        add r3, r4 => r6
+        add r5, r6 => r7
+        add r7, r1 => r8
+        loadI 0 => r9
+        store r8 => r9
+        ",
+    )
+    .unwrap();
+
+    let allocation = allocate(&program, &AllocatorConfig::new(3, Strategy::BottomUp)).unwrap();
+    assert_eq!(allocation.program[0], "loadI 600 => rarp");
+    assert_eq!(run(allocation.program)[0], 11);
+}
+
+#[test]
+fn regalloc_too_few_registers() {
+    let program = iloc::parser::parse_iloc(STRAIGHT_LINE).unwrap();
+
+    let result = allocate(&program, &AllocatorConfig::new(2, Strategy::BottomUp));
+    assert!(result.is_err());
+}
+
+#[test]
+fn regalloc_spill_slots_hold_64_bit_words() {
+    let w64 = MachineConfig {
+        word_size: WordSize::W64,
+        ..MachineConfig::default()
+    };
+    let program = parse_program_for(
+        "
+        loadI 0x10000000001 => r1
+        loadI 0x20000000002 => r2
+        loadI 0x30000000003 => r3
+        loadI 0x40000000004 => r4
+        add r1, r2 => r5
+        add r3, r4 => r6
+        add r5, r6 => r7
+        add r7, r1 => r8
+        add r8, r2 => r9
+        add r9, r3 => r10
+        add r10, r4 => r11
+        loadI 0 => r12
+        store r11 => r12
+        ",
+        "",
+        Dialect::default(),
+        w64,
+    )
+    .unwrap()
+    .text();
+    let run = |program: Vec<String>| {
+        let mut vm = iloc::vm::VM::new(1024);
+        vm.set_machine_config(w64);
+        vm.load_program(program);
+        vm.run();
+        let mut word = [0; 8];
+        vm.get_memory().read(0, &mut word);
+        i64::from_le_bytes(word)
+    };
+    assert_eq!(run(program.clone()), 20 * 0x10000000001);
+
+    for strategy in STRATEGIES {
+        let config = AllocatorConfig {
+            word_size: WordSize::W64,
+            ..AllocatorConfig::new(3, strategy)
+        };
+        let allocation = allocate(&program, &config).unwrap();
+        assert!(allocation.spilled >= 2, "{:?}", strategy);
+        assert_eq!(
+            run(allocation.program),
+            20 * 0x10000000001,
+            "{:?}",
+            strategy
+        );
+    }
+}
@@ -0,0 +1,180 @@
+use iloc::latency::{CycleModel, LatencyTable};
+use iloc::scheduler::{schedule, Dependence, DependenceGraph};
+
+// a = a * 2 * b * c * d, with the operands in memory at rarp + 0 .. rarp + 12
+const EXPRESSION: &str = "
+    loadI 100 => rarp
+    loadAI rarp, 0 => r1
+    add r1, r1 => r1
+    loadAI rarp, 4 => r2
+    mult r1, r2 => r1
+    loadAI rarp, 8 => r2
+    mult r1, r2 => r1
+    loadAI rarp, 12 => r2
+    mult r1, r2 => r1
+    storeAI r1 => rarp, 0
+    ";
+
+// The same computation with a fresh register per value, which frees the
+// scheduler to hoist the loads
+const RENAMED: &str = "
+    loadI 100 => rarp
+    loadAI rarp, 0 => r1
+    add r1, r1 => r2
+    loadAI rarp, 4 => r3
+    mult r2, r3 => r4
+    loadAI rarp, 8 => r5
+    mult r4, r5 => r6
+    loadAI rarp, 12 => r7
+    mult r6, r7 => r8
+    storeAI r8 => rarp, 0
+    ";
+
+const SETUP: &str = "
+    loadI 100 => r90
+    loadI 3 => r91
+    store r91 => r90
+    addI r90, 4 => r90
+    loadI 5 => r91
+    store r91 => r90
+    addI r90, 4 => r90
+    loadI 7 => r91
+    store r91 => r90
+    addI r90, 4 => r90
+    loadI 11 => r91
+    store r91 => r90
+    ";
+
+fn run(program: &[String]) -> Vec<u8> {
+    let mut vm = iloc::vm::VM::new(1024);
+    let mut full = iloc::parser::parse_iloc(SETUP).unwrap();
+    full.extend(program.iter().cloned());
+    vm.load_program(full);
+    vm.run();
+    vm.get_state().1.to_vec()
+}
+
+fn pipeline_cycles(program: &[String]) -> u64 {
+    let mut vm = iloc::vm::VM::new(1024);
+    vm.load_program(program.to_vec());
+    vm.set_cycle_model(CycleModel::new(LatencyTable::default(), true));
+    vm.run();
+    vm.get_cycle_stats().cycles
+}
+
+#[test]
+fn scheduler_dependence_kinds() {
+    let block = iloc::ir::parse_program(&iloc::parser::parse_iloc(EXPRESSION).unwrap()).unwrap();
+    let graph = DependenceGraph::build(&block, &LatencyTable::default());
+
+    let has = |from: usize, to: usize, kind: Dependence| {
+        graph
+            .edges
+            .iter()
+            .any(|edge| edge.from == from && edge.to == to && edge.kind == kind)
+    };
+
+    // loadAI rarp, 0 => r1 feeds add r1, r1 => r1 with the load latency
+    assert!(has(1, 2, Dependence::True));
+    assert!(graph
+        .edges
+        .iter()
+        .any(|edge| edge.from == 1 && edge.to == 2 && edge.latency == 3));
+    // mult r1, r2 => r1 reads r2 before loadAI rarp, 8 => r2 overwrites it
+    assert!(has(4, 5, Dependence::Anti));
+    assert!(has(3, 5, Dependence::Output));
+    // The final store may alias every load
+    assert!(has(7, 9, Dependence::Memory));
+}
+
+#[test]
+fn scheduler_critical_path_priorities() {
+    let block = iloc::ir::parse_program(&iloc::parser::parse_iloc(RENAMED).unwrap()).unwrap();
+    let graph = DependenceGraph::build(&block, &LatencyTable::default());
+
+    // loadAI(3) add(1) mult(2) mult(2) mult(2) storeAI(3)
+    assert_eq!(graph.priorities[1], 13);
+    assert_eq!(graph.priorities[9], 3);
+    assert!(graph.priorities[1] > graph.priorities[3]);
+}
+
+#[test]
+fn scheduler_preserves_semantics() {
+    let program = iloc::parser::parse_iloc(EXPRESSION).unwrap();
+    let scheduled = schedule(&program, &LatencyTable::default(), 1).unwrap();
+
+    assert_eq!(scheduled.program.len(), program.len());
+    assert_eq!(run(&scheduled.program), run(&program));
+    assert_eq!(run(&program)[100], (3 * 2 * 5 * 7 * 11) as u8);
+}
+
+#[test]
+fn scheduler_reduces_pipeline_cycles() {
+    let program = iloc::parser::parse_iloc(EXPRESSION).unwrap();
+    let renamed = iloc::parser::parse_iloc(RENAMED).unwrap();
+    let scheduled = schedule(&renamed, &LatencyTable::default(), 1).unwrap();
+
+    assert_eq!(run(&scheduled.program), run(&renamed));
+    assert_eq!(run(&renamed), run(&program));
+    assert!(pipeline_cycles(&scheduled.program) < pipeline_cycles(&renamed));
+    assert_eq!(scheduled.length, pipeline_cycles(&scheduled.program));
+}
+
+#[test]
+fn scheduler_keeps_labels_and_branches() {
+    let program = iloc::parser::parse_iloc(
+        "
+        loadI 0 => r1
+        loadI 5 => r2
+        L1: addI r1, 1 => r1
+        loadI 0 => r3
+        cmp_LT r1, r2 => r4
+        cbr r4 -> L1, L2
+        L2: store r1 => r3
+        ",
+    )
+    .unwrap();
+    let scheduled = schedule(&program, &LatencyTable::default(), 2).unwrap();
+
+    assert!(scheduled.program[2].starts_with("L1: "));
+    assert_eq!(scheduled.program[5], "cbr r4 -> L1,L2");
+    assert!(scheduled.program[6].starts_with("L2: "));
+
+    let mut vm = iloc::vm::VM::new(1024);
+    vm.load_program(scheduled.program);
+    vm.run();
+    assert_eq!(vm.get_state().1[0], 5);
+}
+
+#[test]
+fn scheduler_multiple_units() {
+    let program = iloc::parser::parse_iloc(
+        "
+        loadI 1 => r1
+        loadI 2 => r2
+        loadI 3 => r3
+        loadI 4 => r4
+        ",
+    )
+    .unwrap();
+
+    assert_eq!(
+        schedule(&program, &LatencyTable::default(), 1)
+            .unwrap()
+            .length,
+        4
+    );
+    assert_eq!(
+        schedule(&program, &LatencyTable::default(), 2)
+            .unwrap()
+            .length,
+        2
+    );
+    assert_eq!(
+        schedule(&program, &LatencyTable::default(), 2)
+            .unwrap()
+            .issue_cycles,
+        vec![0, 0, 1, 1]
+    );
+    assert!(schedule(&program, &LatencyTable::default(), 0).is_err());
+}
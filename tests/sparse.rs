@@ -0,0 +1,137 @@
+// Sparse memory spans 4 GiB, more than a 32-bit host can address
+#![cfg(target_pointer_width = "64")]
+
+use iloc::memory::{Access, DenseMemory, Memory, PagedMemory, ADDRESS_SPACE, PAGE_SIZE};
+use iloc::parser::{parse_iloc, parse_program};
+use iloc::vm::{Fault, Limits, Termination, VM};
+
+fn sparse_vm(source: &str) -> VM {
+    let mut vm = VM::with_memory(Box::new(PagedMemory::new(ADDRESS_SPACE as usize)));
+    vm.load(&parse_program(source, "").unwrap()).unwrap();
+    vm
+}
+
+#[test]
+fn paged_memory_maps_pages_on_write() {
+    let mut memory = PagedMemory::new(ADDRESS_SPACE as usize);
+    let mut buffer = [0xaa; 4];
+
+    memory.read(0x1234_5678, &mut buffer);
+    assert_eq!(buffer, [0; 4]);
+    assert!(memory.pages().is_empty());
+
+    // A write across a page boundary maps both pages
+    memory.write(2 * PAGE_SIZE - 2, &[1, 2, 3, 4]);
+    let starts: Vec<usize> = memory.pages().iter().map(|(start, _)| *start).collect();
+    assert_eq!(starts, [PAGE_SIZE, 2 * PAGE_SIZE]);
+    memory.read(2 * PAGE_SIZE - 2, &mut buffer);
+    assert_eq!(buffer, [1, 2, 3, 4]);
+    assert_eq!(memory.size(), 1 << 32);
+    assert!(memory.as_slice().is_none());
+}
+
+#[test]
+fn dense_memory_reports_every_page() {
+    let mut memory = DenseMemory::new(PAGE_SIZE + 16);
+    memory.write(PAGE_SIZE, &[7]);
+
+    let pages = memory.pages();
+    assert_eq!(pages.len(), 2);
+    assert_eq!((pages[1].0, pages[1].1.len()), (PAGE_SIZE, 16));
+    assert_eq!(memory.as_slice().unwrap()[PAGE_SIZE], 7);
+}
+
+#[test]
+fn sparse_memory_stack_at_high_addresses() {
+    let source = "
+        .data
+        value: .word 42
+        .text
+        loadI 0x80000000 => r0
+        loadI value => r1
+        load r1 => r2
+        storeAI r2 => r0, -4
+        loadAI r0, -4 => r3
+        loadI 0xfffffffc => r4
+        store r3 => r4
+        load r4 => r5
+    ";
+    let mut vm = sparse_vm(source);
+    vm.try_run().unwrap();
+
+    assert_eq!(vm.get_registers()["r3"], 42);
+    assert_eq!(vm.get_registers()["r5"], 42);
+    let starts: Vec<usize> = vm
+        .get_memory()
+        .pages()
+        .iter()
+        .map(|(start, _)| *start)
+        .collect();
+    assert_eq!(
+        starts,
+        [
+            0,
+            0x8000_0000 - PAGE_SIZE,
+            ADDRESS_SPACE as usize - PAGE_SIZE
+        ]
+    );
+}
+
+#[test]
+fn sparse_memory_end_of_address_space() {
+    let mut vm = sparse_vm("loadI 0xfffffffe => r1\nstore r1 => r1");
+
+    assert_eq!(
+        vm.try_run().unwrap_err(),
+        Fault::MemoryViolation {
+            access: Access::Write,
+            address: 1 << 32,
+            region: None,
+        }
+    );
+    // Nothing was stored, so no page was mapped beyond the data section
+    assert!(vm.get_memory().pages().is_empty());
+}
+
+#[test]
+fn sparse_memory_matches_dense() {
+    let source = "
+        loadI 256 => r1
+        loadI 3 => r2
+        loadI 10 => r3
+        loop: storeAO r3 => r1, r2
+        [ addI r2, 4 => r2 ; subI r3, 1 => r3 ; loadAO r1, r2 => r5 ]
+        cbr r3 -> loop, done
+        done: [ store r2 => r1 ; load r1 => r4 ]
+    ";
+    let program = parse_iloc(source).unwrap();
+
+    let mut dense = VM::new(1024);
+    dense.load_program(program.clone());
+    dense.try_run().unwrap();
+    let mut sparse = VM::with_memory(Box::new(PagedMemory::new(ADDRESS_SPACE as usize)));
+    sparse.load_program(program);
+    sparse.try_run().unwrap();
+
+    assert_eq!(sparse.get_registers(), dense.get_state().0);
+    assert!(sparse.get_state().1.is_empty());
+    let mut bytes = vec![0; 1024];
+    sparse.get_memory().read(0, &mut bytes);
+    assert_eq!(bytes, dense.get_state().1);
+}
+
+#[test]
+fn sparse_memory_repeated_states() {
+    let mut vm = sparse_vm("loadI 0x40000000 => r1\nloop: store r1 => r1\njumpI -> loop");
+    let termination = vm.run_with_limits(&Limits {
+        max_steps: Some(1000),
+        detect_repeats: true,
+        ..Limits::default()
+    });
+
+    assert!(
+        matches!(termination, Termination::RepeatedState { .. }),
+        "{:?}",
+        termination
+    );
+}
@@ -0,0 +1,153 @@
+use iloc::cfg::{Cfg, Dominators};
+use iloc::ssa::{from_ssa, to_ssa};
+
+const LOOP: &str = "
+    loadI 0 => r1
+    loadI 1 => r2
+    loadI 10 => r3
+    L1: add r1, r2 => r1
+    addI r2, 1 => r2
+    cmp_LE r2, r3 => r4
+    cbr r4 -> L1, L2
+    L2: loadI 0 => r5
+    store r1 => r5
+    ";
+
+// Copy folding has left the two phis reading each other's targets
+const SWAP: &str = "
+    B0: loadI 1 => r1
+    loadI 2 => r2
+    loadI 0 => r3
+    loadI 3 => r9
+    L1: phi r1,B0,r5,L1 => r4
+    phi r2,B0,r4,L1 => r5
+    phi r3,B0,r7,L1 => r6
+    addI r6, 1 => r7
+    cmp_LT r7, r9 => r8
+    cbr r8 -> L1, L2
+    L2: loadI 0 => r10
+    store r4 => r10
+    storeAI r5 => r10, 4
+    ";
+
+// The phi's target is still read after the loop, where the next value is live
+const LOST_COPY: &str = "
+    B0: loadI 1 => r1
+    loadI 3 => r9
+    L1: phi r1,B0,r3,L1 => r2
+    addI r2, 1 => r3
+    cmp_LT r3, r9 => r4
+    cbr r4 -> L1, L2
+    L2: loadI 0 => r10
+    store r2 => r10
+    ";
+
+fn parse(program: &str) -> Vec<String> {
+    iloc::parser::parse_iloc(program).unwrap()
+}
+
+fn memory(program: &[String]) -> Vec<u8> {
+    let mut vm = iloc::vm::VM::new(1024);
+    vm.load_program(program.to_vec());
+    vm.run();
+    vm.get_state().1.to_vec()
+}
+
+#[test]
+fn ssa_dominance_frontiers() {
+    let program = parse(
+        "
+        loadI 1 => r1
+        cbr r1 -> L1, L2
+        L1: loadI 2 => r2
+        jumpI -> L3
+        L2: loadI 3 => r2
+        L3: addI r2, 1 => r3
+        ",
+    );
+    let instructions = iloc::ir::parse_program(&program).unwrap();
+    let cfg = Cfg::build(&instructions);
+    let dominators = Dominators::compute(&cfg);
+
+    assert_eq!(dominators.idom, vec![Some(0), Some(0), Some(0), Some(0)]);
+    assert!(dominators.frontiers[1].contains(&3));
+    assert!(dominators.frontiers[2].contains(&3));
+    assert!(dominators.frontiers[0].is_empty());
+    assert!(dominators.dominates(0, 3));
+    assert!(!dominators.dominates(1, 3));
+}
+
+#[test]
+fn ssa_construction() {
+    let ssa = to_ssa(&parse(LOOP)).unwrap();
+
+    assert_eq!(ssa[0], "B0: loadI 0 => r1_1");
+    assert_eq!(ssa[3], "L1: phi r1_1,B0,r1_3,L1 => r1_2");
+    assert_eq!(ssa[4], "phi r2_1,B0,r2_3,L1 => r2_2");
+    assert_eq!(ssa[5], "add r1_2,r2_2 => r1_3");
+    // Pruned: r3 is never redefined and r4 is dead at the join
+    assert_eq!(ssa.iter().filter(|line| line.contains("phi")).count(), 2);
+    assert_eq!(ssa.last().unwrap(), "store r1_3 => r5_1");
+}
+
+#[test]
+fn ssa_vm_executes_phis() {
+    let program = parse(LOOP);
+    let ssa = to_ssa(&program).unwrap();
+
+    assert_eq!(memory(&ssa), memory(&program));
+    assert_eq!(memory(&program)[0], 55);
+}
+
+#[test]
+fn ssa_entry_with_predecessors() {
+    let program = parse(
+        "
+        L0: loadI 0 => r0
+        addI r1, 1 => r1
+        store r1 => r0
+        cmp_LT r1, r0 => r2
+        cbr r2 -> L0, L1
+        L1: nop
+        ",
+    );
+    let ssa = to_ssa(&program).unwrap();
+
+    // A nop becomes the entry so the loop header can have a phi
+    assert_eq!(ssa[0], "B0: nop");
+    assert!(ssa[1].starts_with("L0: phi r1,B0,"));
+}
+
+#[test]
+fn ssa_round_trip() {
+    let program = parse(LOOP);
+    let ssa = to_ssa(&program).unwrap();
+    let plain = from_ssa(&ssa).unwrap();
+
+    assert!(plain.iter().all(|line| !line.contains("phi")));
+    assert_eq!(memory(&plain), memory(&program));
+}
+
+#[test]
+fn ssa_swap_problem() {
+    let ssa = parse(SWAP);
+    let plain = from_ssa(&ssa).unwrap();
+
+    let memory = memory(&plain);
+    assert_eq!(memory[..8], [1, 0, 0, 0, 2, 0, 0, 0]);
+    assert_eq!(memory[..8], self::memory(&ssa)[..8]);
+    // The cycle between r4 and r5 goes through a temporary
+    assert!(plain.iter().any(|line| line.contains("=> r_copy_1")));
+}
+
+#[test]
+fn ssa_lost_copy_problem() {
+    let ssa = parse(LOST_COPY);
+    let plain = from_ssa(&ssa).unwrap();
+
+    // The back edge leaves a block with two successors, so it is split
+    assert!(plain.contains(&"cbr r4 -> B1,L2".to_string()));
+    assert!(plain.contains(&"B1: i2i r3 => r2".to_string()));
+    assert_eq!(memory(&plain)[0], 2);
+    assert_eq!(memory(&ssa)[0], 2);
+}
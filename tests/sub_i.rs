@@ -119,7 +119,7 @@ fn subi_large_positive() {
     let registers = &state.0;
 
     let result = 1000000000i64 - 2000000000i64;
-    let wrapped_result = result as i32;
+    let wrapped_result = result as i32 as i64;
 
     assert_eq!(registers["r1"], wrapped_result);
 }
@@ -0,0 +1,157 @@
+use iloc::dialect::Dialect;
+use iloc::machine::{Endianness, MachineConfig, WordSize};
+use iloc::parser::{parse_program, parse_program_for};
+use iloc::vm::VM;
+
+const W64: MachineConfig = MachineConfig {
+    word_size: WordSize::W64,
+    endianness: Endianness::Little,
+};
+
+const BIG: MachineConfig = MachineConfig {
+    word_size: WordSize::W32,
+    endianness: Endianness::Big,
+};
+
+fn run(source: &str, machine: MachineConfig) -> VM {
+    let program = parse_program_for(source, "", Dialect::default(), machine).unwrap();
+    let mut vm = VM::new(1024);
+    vm.set_machine_config(machine);
+    vm.load(&program).unwrap();
+    vm.run();
+    vm
+}
+
+#[test]
+fn word_size_sets_register_width_and_wrapping() {
+    let source = "
+        loadI 2000000000 => r1
+        add r1, r1 => r2
+        loadI 1 => r3
+        lshiftI r3, 40 => r4
+        multI r4, 8388608 => r5
+    ";
+
+    let registers = run(source, MachineConfig::default()).get_state().0.clone();
+    assert_eq!(registers["r2"], (4_000_000_000i64 as i32) as i64);
+    // Shift amounts are taken modulo the word's bits
+    assert_eq!(registers["r4"], 1 << 8);
+
+    let registers = run(source, W64).get_state().0.clone();
+    assert_eq!(registers["r2"], 4_000_000_000);
+    assert_eq!(registers["r4"], 1 << 40);
+    // 2^40 * 2^23 wraps around 64 bits to the most negative word
+    assert_eq!(registers["r5"], i64::MIN);
+}
+
+#[test]
+fn word_size_sets_immediate_ranges() {
+    let parse = |source: &str, machine| {
+        parse_program_for(source, "", Dialect::default(), machine).map(|program| program.text())
+    };
+
+    assert_eq!(
+        parse("loadI 0x1_0000_0000 => r1", MachineConfig::default()).unwrap_err(),
+        "Line 1: Value 4294967296 does not fit in a word"
+    );
+    assert_eq!(
+        parse("loadI 0xffffffff => r1", MachineConfig::default()).unwrap(),
+        vec!["loadI -1 => r1"]
+    );
+    assert_eq!(
+        parse("loadI 0x1_0000_0000 => r1\naddI r1, 0xffffffff => r1", W64).unwrap(),
+        vec!["loadI 4294967296 => r1", "addI r1,4294967295 => r1"]
+    );
+}
+
+#[test]
+fn word_size_sets_load_and_store_size() {
+    let source = "
+        loadI -2 => r1
+        loadI 16 => r2
+        store r1 => r2
+        loadI 0x7fffffffffff => r3
+        storeAI r3 => r2, 8
+        loadAI r2, 8 => r4
+        load r2 => r5
+    ";
+    let vm = run(source, W64);
+    let (registers, memory, _) = vm.get_state();
+
+    assert_eq!(&memory[16..24], &(-2i64).to_le_bytes());
+    assert_eq!(&memory[24..32], &0x7fff_ffff_ffffi64.to_le_bytes());
+    assert_eq!(registers["r4"], 0x7fff_ffff_ffff);
+    assert_eq!(registers["r5"], -2);
+}
+
+#[test]
+fn endianness_orders_words_and_floats() {
+    let source = "
+        loadI 0x01020304 => r1
+        loadI 8 => r2
+        store r1 => r2
+        loadI 8 => r3
+        cload r3 => r4
+        loadF 1.0 => f1
+        fstoreAI f1 => r2, 8
+    ";
+    let vm = run(source, BIG);
+    let (registers, memory, _) = vm.get_state();
+
+    assert_eq!(&memory[8..12], &[1, 2, 3, 4]);
+    assert_eq!(registers["r4"], 0x01020304);
+    assert_eq!(&memory[16..20], &1.0f32.to_be_bytes());
+
+    let vm = run(source, MachineConfig::default());
+    assert_eq!(&vm.get_state().1[8..12], &[4, 3, 2, 1]);
+}
+
+#[test]
+fn machine_lays_out_word_data() {
+    let source =
+        ".data\nfirst: .word -1, last\nlast: .word 0x0102\n.text\nloadI last => r1\nload r1 => r2";
+
+    let program = parse_program_for(
+        source,
+        "",
+        Dialect::default(),
+        MachineConfig {
+            word_size: WordSize::W64,
+            endianness: Endianness::Big,
+        },
+    )
+    .unwrap();
+    assert_eq!(program.data.labels["last"], 16);
+    assert_eq!(&program.data.bytes[..8], &[0xff; 8]);
+    assert_eq!(&program.data.bytes[8..16], &16i64.to_be_bytes());
+    assert_eq!(&program.data.bytes[16..], &0x0102i64.to_be_bytes());
+
+    let program = parse_program(source, "").unwrap();
+    assert_eq!(
+        program.data.bytes,
+        [255, 255, 255, 255, 8, 0, 0, 0, 2, 1, 0, 0]
+    );
+    assert_eq!(run(source, W64).get_state().0["r2"], 0x0102);
+}
+
+#[test]
+fn machine_names_and_float_conversion() {
+    assert_eq!("64".parse(), Ok(WordSize::W64));
+    assert_eq!("big".parse(), Ok(Endianness::Big));
+    assert_eq!(
+        "16".parse::<WordSize>(),
+        Err("Unknown word size '16', expected 32 or 64".to_string())
+    );
+    assert_eq!(
+        "middle".parse::<Endianness>(),
+        Err("Unknown endianness 'middle', expected little or big".to_string())
+    );
+
+    // f2i saturates at the limits of the configured word
+    let source = "loadF 0x100_0000_0000 => f1\nf2i f1 => r1";
+    assert_eq!(
+        run(source, MachineConfig::default()).get_state().0["r1"],
+        i32::MAX as i64
+    );
+    assert_eq!(run(source, W64).get_state().0["r1"], 1 << 40);
+}